@@ -0,0 +1 @@
+ALTER TABLE rom_files DROP COLUMN archive_chain;
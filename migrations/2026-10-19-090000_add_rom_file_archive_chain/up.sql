@@ -0,0 +1 @@
+ALTER TABLE rom_files ADD archive_chain TEXT;
@@ -0,0 +1,7 @@
+DROP INDEX rom_files_archive_file_id_relation_index;
+ALTER TABLE rom_files DROP COLUMN archive_file_id;
+
+DROP INDEX archive_files_path_unique;
+ALTER TABLE archive_files DROP COLUMN nested_depth;
+ALTER TABLE archive_files DROP COLUMN mtime;
+ALTER TABLE archive_files DROP COLUMN size;
@@ -0,0 +1,13 @@
+ALTER TABLE archive_files ADD size BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE archive_files ADD mtime BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE archive_files ADD nested_depth INTEGER NOT NULL DEFAULT 0;
+
+CREATE UNIQUE INDEX archive_files_path_unique ON archive_files (
+    path
+);
+
+ALTER TABLE rom_files ADD archive_file_id INTEGER REFERENCES archive_files (id);
+
+CREATE INDEX rom_files_archive_file_id_relation_index ON rom_files (
+    archive_file_id
+);
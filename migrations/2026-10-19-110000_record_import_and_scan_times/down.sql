@@ -0,0 +1,3 @@
+DROP TABLE source_roots;
+
+ALTER TABLE data_files DROP COLUMN imported_at;
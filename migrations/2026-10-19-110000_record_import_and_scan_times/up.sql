@@ -0,0 +1,9 @@
+ALTER TABLE data_files ADD imported_at TIMESTAMP;
+
+CREATE TABLE source_roots (
+    id         INTEGER   PRIMARY KEY AUTOINCREMENT
+                         NOT NULL,
+    path       TEXT      NOT NULL
+                         UNIQUE,
+    scanned_at TIMESTAMP NOT NULL
+);
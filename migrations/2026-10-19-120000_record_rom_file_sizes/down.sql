@@ -0,0 +1 @@
+ALTER TABLE rom_files DROP COLUMN size;
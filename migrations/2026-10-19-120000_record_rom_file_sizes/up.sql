@@ -0,0 +1,3 @@
+-- Rows scanned before sizes were recorded keep a NULL size; their archives
+-- are rehashed lazily, on the next scan of their source.
+ALTER TABLE rom_files ADD size BIGINT;
@@ -0,0 +1,4 @@
+ALTER TABLE roms DROP COLUMN status;
+ALTER TABLE roms DROP COLUMN merge;
+ALTER TABLE games DROP COLUMN sourcefile;
+ALTER TABLE games DROP COLUMN description;
@@ -0,0 +1,4 @@
+ALTER TABLE games ADD description TEXT;
+ALTER TABLE games ADD sourcefile TEXT;
+ALTER TABLE roms ADD merge TEXT;
+ALTER TABLE roms ADD status TEXT;
@@ -0,0 +1,53 @@
+PRAGMA foreign_keys = OFF;
+
+-- Only the latest version of each DAT survives a downgrade.
+DELETE FROM roms
+WHERE game_id IN (
+    SELECT games.id FROM games
+    JOIN data_files ON data_files.id = games.data_file_id
+    WHERE data_files.id < (
+        SELECT MAX(newer.id) FROM data_files AS newer WHERE newer.name = data_files.name
+    )
+);
+
+DELETE FROM games
+WHERE data_file_id IN (
+    SELECT id FROM data_files
+    WHERE id < (
+        SELECT MAX(newer.id) FROM data_files AS newer WHERE newer.name = data_files.name
+    )
+);
+
+DELETE FROM data_files
+WHERE id < (
+    SELECT MAX(newer.id) FROM data_files AS newer WHERE newer.name = data_files.name
+);
+
+UPDATE rom_files
+SET rom_id = NULL
+WHERE rom_id NOT IN (SELECT id FROM roms);
+
+CREATE TABLE data_files_unique_name (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT
+                        NOT NULL,
+    build       TEXT,
+    debug       TEXT,
+    file_name   TEXT,
+    name        TEXT NOT NULL UNIQUE,
+    description TEXT,
+    category    TEXT,
+    version     TEXT,
+    author      TEXT,
+    email       TEXT,
+    homepage    TEXT,
+    url         TEXT,
+    sha1        BLOB,
+    imported_at TIMESTAMP
+);
+
+INSERT INTO data_files_unique_name SELECT * FROM data_files;
+
+DROP TABLE data_files;
+ALTER TABLE data_files_unique_name RENAME TO data_files;
+
+PRAGMA foreign_keys = ON;
@@ -0,0 +1,63 @@
+PRAGMA foreign_keys = OFF;
+
+-- Several versions of a DAT may share a name; the most recently imported one
+-- (the highest id) is the one built by default.
+CREATE TABLE data_files_versioned (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT
+                        NOT NULL,
+    build       TEXT,
+    debug       TEXT,
+    file_name   TEXT,
+    name        TEXT NOT NULL,
+    description TEXT,
+    category    TEXT,
+    version     TEXT,
+    author      TEXT,
+    email       TEXT,
+    homepage    TEXT,
+    url         TEXT,
+    sha1        BLOB,
+    imported_at TIMESTAMP
+);
+
+INSERT INTO data_files_versioned (
+    id,
+    build,
+    debug,
+    file_name,
+    name,
+    description,
+    category,
+    version,
+    author,
+    email,
+    homepage,
+    url,
+    sha1,
+    imported_at
+)
+SELECT
+    id,
+    build,
+    debug,
+    file_name,
+    name,
+    description,
+    category,
+    version,
+    author,
+    email,
+    homepage,
+    url,
+    sha1,
+    imported_at
+FROM data_files;
+
+DROP TABLE data_files;
+ALTER TABLE data_files_versioned RENAME TO data_files;
+
+CREATE INDEX data_files_name_index ON data_files (
+    name
+);
+
+PRAGMA foreign_keys = ON;
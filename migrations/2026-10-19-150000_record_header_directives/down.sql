@@ -0,0 +1,4 @@
+ALTER TABLE data_files DROP COLUMN header_skipper;
+ALTER TABLE data_files DROP COLUMN force_packing;
+ALTER TABLE data_files DROP COLUMN force_nodump;
+ALTER TABLE data_files DROP COLUMN force_merging;
@@ -0,0 +1,4 @@
+ALTER TABLE data_files ADD force_merging TEXT;
+ALTER TABLE data_files ADD force_nodump TEXT;
+ALTER TABLE data_files ADD force_packing TEXT;
+ALTER TABLE data_files ADD header_skipper TEXT;
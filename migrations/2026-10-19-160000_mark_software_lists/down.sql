@@ -0,0 +1 @@
+ALTER TABLE data_files DROP COLUMN software_list;
@@ -0,0 +1 @@
+ALTER TABLE data_files ADD software_list BOOLEAN NOT NULL DEFAULT 0;
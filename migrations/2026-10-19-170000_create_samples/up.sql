@@ -0,0 +1,11 @@
+CREATE TABLE samples (
+    id      INTEGER PRIMARY KEY AUTOINCREMENT
+                    NOT NULL,
+    name    TEXT    NOT NULL,
+    game_id INTEGER NOT NULL
+                    REFERENCES games (id)
+);
+
+CREATE INDEX samples_game_id_index ON samples (
+    game_id
+);
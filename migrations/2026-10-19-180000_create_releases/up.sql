@@ -0,0 +1,15 @@
+CREATE TABLE releases (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT
+                       NOT NULL,
+    name       TEXT    NOT NULL,
+    region     TEXT    NOT NULL,
+    language   TEXT,
+    date       TEXT,
+    is_default TEXT,
+    game_id    INTEGER NOT NULL
+                       REFERENCES games (id)
+);
+
+CREATE INDEX releases_game_id_index ON releases (
+    game_id
+);
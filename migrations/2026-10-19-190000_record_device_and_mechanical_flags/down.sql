@@ -0,0 +1,2 @@
+ALTER TABLE games DROP COLUMN is_mechanical;
+ALTER TABLE games DROP COLUMN is_device;
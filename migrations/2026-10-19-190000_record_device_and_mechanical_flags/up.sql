@@ -0,0 +1,2 @@
+ALTER TABLE games ADD is_device TEXT;
+ALTER TABLE games ADD is_mechanical TEXT;
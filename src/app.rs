@@ -1,12 +1,36 @@
-use camino::Utf8PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+
+use camino::{Utf8Path, Utf8PathBuf};
 use log::{info, warn};
 
 use crate::{
-    build::{planner::plan_build, writer::write_plan_with_compression},
+    build::{
+        game_filter::filter_games,
+        one_game_one_rom::choose_games,
+        planner::{plan_build, plan_samples},
+        writer::{
+            EntryMismatch, WrittenOutput, check_output, hash_existing_output, verification_result,
+            verify_outputs, write_plan_as_7z, write_plan_as_directories,
+            write_plan_with_compression,
+        },
+    },
     database::Database,
-    domain::{BuildMode, BuildReport, BuildRequest, ZipCompression},
-    operations,
-    storage::repositories::{BuildRepository, DataFileSelector, SourceRepository},
+    domain::{
+        AfterBuild, BuildMode, BuildPlan, BuildReport, BuildRequest, CacheStats, DatDiff,
+        DatDirectives, DatIssue, DatRom, DatSummary, FamilyChoice, GameFilter, GameKind,
+        NodumpPolicy, OutputFormat, ReleasePreferences, RomLookup, RomQuery, SourceFile,
+        SourcePreference, SourceSummary, ZipCompression, ZipSpec,
+    },
+    hashes::Sha1Digest,
+    logiqx, operations,
+    storage::{
+        db::RemovedRows,
+        models::{ArchiveFile, FileStamp, NewArchiveFile},
+        repositories::{
+            BuildRepository, DatRepository, DataFileSelector, InventoryRepository,
+            MaintenanceRepository, OutputRepository, SourceRepository,
+        },
+    },
 };
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -23,6 +47,7 @@ pub struct DatImportReport {
 pub struct SourceScanRequest {
     pub source_path: Utf8PathBuf,
     pub jobs: usize,
+    pub nested_depth: usize,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -30,13 +55,31 @@ pub struct SourceScanReport {
     pub source_path: Utf8PathBuf,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct BuildWorkflowRequest {
     pub dat_path: Utf8PathBuf,
+    /// Build this version (or hex SHA1) of a cached DAT instead of the
+    /// latest import.
+    pub dat_version: Option<String>,
     pub source_path: Utf8PathBuf,
     pub destination_path: Utf8PathBuf,
-    pub mode: BuildMode,
+    /// Write each sample set the DAT's games play to `<samples>/<set>.zip`.
+    pub samples_path: Option<Utf8PathBuf>,
+    /// `None` uses the DAT's `forcemerging`, then parent bundles.
+    pub mode: Option<BuildMode>,
+    /// Which game the 1G1R layout keeps from each family.
+    pub release_preferences: ReleasePreferences,
+    /// Which of the DAT's games are planned and reported.
+    pub game_filter: GameFilter,
+    /// `None` uses the DAT's `forcenodump`, then required.
+    pub nodump: Option<NodumpPolicy>,
+    pub prefer: SourcePreference,
+    /// `None` uses the DAT's `forcepacking`, then zip.
+    pub output: Option<OutputFormat>,
     pub compression: ZipCompression,
+    pub after_build: AfterBuild,
+    /// Reread every output after writing and check each entry's SHA1.
+    pub verify: bool,
     pub dry_run: bool,
     pub strict: bool,
 }
@@ -44,26 +87,240 @@ pub struct BuildWorkflowRequest {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BuildWorkflowReport {
     pub written_paths: Vec<Utf8PathBuf>,
+    /// Outputs left in place because they were already byte-identical.
+    pub unchanged_paths: Vec<Utf8PathBuf>,
+    /// Source files moved or deleted by `after_build`; with `dry_run`, the
+    /// ones that would have been.
+    pub consumed_paths: Vec<Utf8PathBuf>,
     pub build_report: BuildReport,
     pub exit_code: i32,
     pub mode: BuildMode,
+    pub prefer: SourcePreference,
+    pub output: OutputFormat,
     pub compression: ZipCompression,
+    pub after_build: AfterBuild,
+    pub verify: bool,
     pub dry_run: bool,
     pub strict: bool,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct RunWorkflowRequest {
     pub dat_path: Utf8PathBuf,
     pub source_path: Utf8PathBuf,
     pub destination_path: Utf8PathBuf,
-    pub mode: BuildMode,
+    pub samples_path: Option<Utf8PathBuf>,
+    pub mode: Option<BuildMode>,
+    pub release_preferences: ReleasePreferences,
+    pub game_filter: GameFilter,
+    pub nodump: Option<NodumpPolicy>,
+    pub prefer: SourcePreference,
+    pub output: Option<OutputFormat>,
+    pub compression: ZipCompression,
+    pub after_build: AfterBuild,
+    pub verify: bool,
+    pub jobs: usize,
+    pub nested_depth: usize,
+    pub dry_run: bool,
+    pub strict: bool,
+}
+
+/// Build several DATs against one source scan. DATs come from `dat_dir`
+/// when given, otherwise every DAT already in the cache is built. Settings
+/// left as `None` come from each DAT's own directives.
+///
+/// Sources are always kept, since a file one DAT consumes may still be read
+/// by the next.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildAllRequest {
+    pub dat_dir: Option<Utf8PathBuf>,
+    pub source_path: Utf8PathBuf,
+    pub destination_path: Utf8PathBuf,
+    /// Sample sets of every DAT share this directory, as MAME expects.
+    pub samples_path: Option<Utf8PathBuf>,
+    pub mode: Option<BuildMode>,
+    pub release_preferences: ReleasePreferences,
+    pub game_filter: GameFilter,
+    pub nodump: Option<NodumpPolicy>,
+    pub prefer: SourcePreference,
+    pub output: Option<OutputFormat>,
+    pub compression: ZipCompression,
+    pub verify: bool,
+    pub jobs: usize,
+    pub nested_depth: usize,
+    pub dry_run: bool,
+    pub strict: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildAllReport {
+    pub builds: Vec<DatBuildReport>,
+    /// The highest exit code of any DAT build.
+    pub exit_code: i32,
+}
+
+/// One DAT's build. A DAT that could not be imported or built has no
+/// report, only the error that stopped it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatBuildReport {
+    pub dat_name: String,
+    pub report: Option<BuildWorkflowReport>,
+    pub error: Option<String>,
+    pub exit_code: i32,
+}
+
+impl DatBuildReport {
+    fn failed(dat_name: String, error: &crate::Error) -> Self {
+        Self {
+            dat_name,
+            report: None,
+            error: Some(error.to_string()),
+            exit_code: DAT_ERROR_EXIT_CODE,
+        }
+    }
+}
+
+/// The exit code of a DAT whose build stopped with an error, the same one
+/// the command exits with for any other error.
+const DAT_ERROR_EXIT_CODE: i32 = 1;
+
+/// Rebuild an existing collection in place.
+///
+/// The collection is scanned as a source alongside `source_paths`, zips
+/// whose entries differ from the plan are rewritten, and replaced or
+/// unexpected files are moved under `backup_path`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FixRequest {
+    pub dat_path: Utf8PathBuf,
+    pub collection_path: Utf8PathBuf,
+    pub source_paths: Vec<Utf8PathBuf>,
+    pub backup_path: Utf8PathBuf,
+    pub mode: Option<BuildMode>,
+    /// Only ZIP collections can be fixed; anything else, given here or by
+    /// the DAT's packing directive, is rejected.
+    pub output: Option<OutputFormat>,
+    pub release_preferences: ReleasePreferences,
+    pub game_filter: GameFilter,
+    pub nodump: Option<NodumpPolicy>,
+    pub prefer: SourcePreference,
     pub compression: ZipCompression,
+    pub verify: bool,
     pub jobs: usize,
+    pub nested_depth: usize,
     pub dry_run: bool,
     pub strict: bool,
 }
 
+/// With `dry_run`, the paths that would have been rewritten and moved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FixReport {
+    pub rewritten_paths: Vec<Utf8PathBuf>,
+    pub unchanged_paths: Vec<Utf8PathBuf>,
+    /// Collection files moved to the backup directory, at their old paths.
+    pub backed_up_paths: Vec<Utf8PathBuf>,
+    pub backup_path: Option<Utf8PathBuf>,
+    pub build_report: BuildReport,
+    pub exit_code: i32,
+}
+
+/// Check outputs already in a destination against an imported DAT.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerifyRequest {
+    pub dat_path: Utf8PathBuf,
+    pub destination_path: Utf8PathBuf,
+    pub mode: Option<BuildMode>,
+    pub release_preferences: ReleasePreferences,
+    pub game_filter: GameFilter,
+    /// `None` uses the DAT's `forcenodump`, then required.
+    pub nodump: Option<NodumpPolicy>,
+    pub output: Option<OutputFormat>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub verified_paths: Vec<Utf8PathBuf>,
+    /// Bundles with no output in the destination.
+    pub missing_paths: Vec<Utf8PathBuf>,
+    /// DAT ROMs absent from an output that does exist.
+    pub missing_entries: Vec<EntryMismatch>,
+    /// `2` when any output or entry is missing.
+    pub exit_code: i32,
+}
+
+/// Write a Logiqx DAT describing a scanned source. Header fields left as
+/// `None` fall back to the source directory's name and today's date.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatCreateRequest {
+    pub source_path: Utf8PathBuf,
+    pub dat_path: Utf8PathBuf,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub version: Option<String>,
+    pub author: Option<String>,
+    pub homepage: Option<String>,
+    pub url: Option<String>,
+    pub jobs: usize,
+    pub nested_depth: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatCreateReport {
+    pub games: usize,
+    pub roms: usize,
+}
+
+/// Compare two DATs, each given as a file or an imported DAT's name with an
+/// optional version.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatDiffRequest {
+    pub old_dat_path: Utf8PathBuf,
+    pub old_version: Option<String>,
+    pub new_dat_path: Utf8PathBuf,
+    pub new_version: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatExportRequest {
+    pub dat_path: Utf8PathBuf,
+    pub dat_version: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatRemovalRequest {
+    pub dat_path: Utf8PathBuf,
+    /// Remove only this version (or hex SHA1) instead of every version.
+    pub dat_version: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatRemovalReport {
+    pub games: usize,
+    pub roms: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceRemovalRequest {
+    pub source_path: Utf8PathBuf,
+}
+
+/// Scan rows dropped by `remove_source` or `collect_garbage`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheRemovalReport {
+    pub rom_files: usize,
+    pub archives: usize,
+    pub source_roots: usize,
+}
+
+impl From<RemovedRows> for CacheRemovalReport {
+    fn from(removed: RemovedRows) -> Self {
+        Self {
+            rom_files: removed.rom_files,
+            archives: removed.archives,
+            source_roots: removed.source_roots,
+        }
+    }
+}
+
 pub fn import_dat(
     database: &Database,
     request: &DatImportRequest,
@@ -76,42 +333,125 @@ pub fn scan_source(
     database: &Database,
     request: &SourceScanRequest,
 ) -> crate::Result<SourceScanReport> {
-    operations::source(&request.source_path, request.jobs, database.pool())
-        .map(|source_path| SourceScanReport { source_path })
+    operations::source(
+        &request.source_path,
+        request.jobs,
+        request.nested_depth,
+        database.pool(),
+    )
+    .map(|source_path| SourceScanReport { source_path })
 }
 
 pub fn build(
     database: &Database,
     request: &BuildWorkflowRequest,
 ) -> crate::Result<BuildWorkflowReport> {
-    let dat_selector = resolve_dat_selector(&request.dat_path);
+    build_dat(database, &resolve_dat_selector(&request.dat_path), request)
+}
+
+fn build_dat(
+    database: &Database,
+    dat_selector: &BuildDatSelector,
+    request: &BuildWorkflowRequest,
+) -> crate::Result<BuildWorkflowReport> {
     let source_root = request.source_path.canonicalize_utf8()?;
-    let dat_roms =
-        BuildRepository::new(database.pool()).load_dat_roms(dat_selector.repository_selector())?;
+    let directives = load_directives(database, dat_selector, request.dat_version.as_deref())?;
+    let destination_path = software_list_destination(
+        database,
+        dat_selector,
+        request.dat_version.as_deref(),
+        &request.destination_path,
+    )?;
+    let mode = request
+        .mode
+        .or_else(|| directives.mode())
+        .unwrap_or_default();
+    let build_roms = load_build_roms(
+        database,
+        dat_selector,
+        request.dat_version.as_deref(),
+        mode,
+        &request.release_preferences,
+        &request.game_filter,
+    )?;
+    let output = request
+        .output
+        .or_else(|| directives.output())
+        .unwrap_or_default();
     let source_files = SourceRepository::new(database.pool()).load_source_files()?;
     let plan = plan_build(
-        &dat_roms,
+        &build_roms.dat_roms,
         &source_files,
         &BuildRequest {
             dat_name: dat_selector.value().to_owned(),
-            source_root: source_root.to_string(),
-            mode: request.mode,
+            source_roots: vec![source_root.to_string()],
+            mode,
+            nodump: request
+                .nodump
+                .or_else(|| directives.nodump_policy())
+                .unwrap_or_default(),
+            prefer: request.prefer.clone(),
             dry_run: request.dry_run,
             strict: request.strict,
         },
     );
-    report_build_outcome(&plan.report);
+    let sample_plan = plan_dat_samples(
+        database,
+        dat_selector,
+        request,
+        build_roms.kept_games.as_ref(),
+        &source_files,
+        &source_root,
+    )?;
     let exit_code = plan.report.exit_code;
-    let build_report = plan.report.clone();
-    let written_paths =
-        write_plan_with_compression(&plan, &request.destination_path, request.compression)?;
+    let mut build_report = with_sample_report(&plan.report, sample_plan.as_ref());
+    build_report.family_choices = build_roms.family_choices;
+    report_build_outcome(&build_report, &request.prefer);
+    let output_repository = OutputRepository::new(database.pool());
+    let mut written_outputs = write_outputs(
+        &output_repository,
+        &plan,
+        output,
+        &destination_path,
+        request.compression,
+    )?;
+    // A build that failed under `strict` writes no samples either.
+    if exit_code == 0 {
+        written_outputs.extend(write_sample_sets(
+            &output_repository,
+            request,
+            sample_plan.as_ref(),
+        )?);
+    }
+    output_repository.record_outputs(&changed_outputs(&written_outputs)?)?;
+    let unchanged_paths = unchanged_outputs(&written_outputs);
+    if plan.writes_files() && (request.verify || request.after_build != AfterBuild::Keep) {
+        verify_outputs(&plan, &destination_path, output)?;
+        info!("verified {} outputs", plan.zips.len());
+    }
+    let consumed_paths = consume_sources(
+        &plan,
+        &source_files,
+        &source_root,
+        &destination_path,
+        &request.after_build,
+    )?;
 
     Ok(BuildWorkflowReport {
-        written_paths,
+        written_paths: written_outputs
+            .into_iter()
+            .map(|written_output| written_output.path)
+            .collect(),
+        unchanged_paths,
+        consumed_paths,
         build_report,
         exit_code,
-        mode: request.mode,
+        mode,
+        prefer: request.prefer.clone(),
+        output,
         compression: request.compression,
+        after_build: request.after_build.clone(),
+        verify: request.verify,
         dry_run: request.dry_run,
         strict: request.strict,
     })
@@ -131,6 +471,516 @@ pub fn run(
     build(database, &build_workflow_request_from_run(request))
 }
 
+/// Each DAT is written to `<destination>/<DAT name>/`. A DAT that fails
+/// under `strict` does not stop the others from being built.
+pub fn build_all(database: &Database, request: &BuildAllRequest) -> crate::Result<BuildAllReport> {
+    let (dats, mut builds) = match &request.dat_dir {
+        Some(dat_dir) => import_dat_dir(database, dat_dir)?,
+        None => (
+            list_dats(database)?
+                .into_iter()
+                .filter(|dat| dat.latest)
+                .collect(),
+            Vec::new(),
+        ),
+    };
+    if dats.is_empty() && builds.is_empty() {
+        warn!("no DATs to build");
+    }
+    scan_source(
+        database,
+        &SourceScanRequest {
+            source_path: request.source_path.clone(),
+            jobs: request.jobs,
+            nested_depth: request.nested_depth,
+        },
+    )?;
+
+    builds.extend(
+        dats.into_iter()
+            .map(|dat| match build_one_of_all(database, request, &dat) {
+                Ok(report) => DatBuildReport {
+                    dat_name: dat.name,
+                    exit_code: report.exit_code,
+                    report: Some(report),
+                    error: None,
+                },
+                Err(error) => DatBuildReport::failed(dat.name, &error),
+            }),
+    );
+
+    for build in &builds {
+        match (&build.report, &build.error) {
+            (Some(report), _) => info!(
+                "{}: {} matched, {} missing, exit code {}",
+                build.dat_name,
+                report.build_report.matched_roms,
+                report.build_report.missing_roms.len(),
+                build.exit_code
+            ),
+            (None, error) => warn!(
+                "{}: failed: {}, exit code {}",
+                build.dat_name,
+                error.as_deref().unwrap_or_default(),
+                build.exit_code
+            ),
+        }
+    }
+    let failed = builds.iter().filter(|build| build.exit_code != 0).count();
+    info!("built {} DATs, {failed} failed", builds.len());
+    Ok(BuildAllReport {
+        exit_code: builds
+            .iter()
+            .map(|build| build.exit_code)
+            .max()
+            .unwrap_or(0),
+        builds,
+    })
+}
+
+/// Build one DAT of a `build-all` run into its own directory.
+fn build_one_of_all(
+    database: &Database,
+    request: &BuildAllRequest,
+    dat: &DatSummary,
+) -> crate::Result<BuildWorkflowReport> {
+    // Software lists already build into a directory named after them.
+    let destination_path = if dat.software_list {
+        request.destination_path.clone()
+    } else {
+        request
+            .destination_path
+            .join(dat_output_dir_name(&dat.name)?)
+    };
+    info!("building DAT {} into {destination_path}", dat.name);
+    build_dat(
+        database,
+        &BuildDatSelector::Name(dat.name.clone()),
+        &BuildWorkflowRequest {
+            dat_path: Utf8PathBuf::from(&dat.name),
+            dat_version: None,
+            source_path: request.source_path.clone(),
+            destination_path,
+            samples_path: request.samples_path.clone(),
+            mode: request.mode,
+            release_preferences: request.release_preferences.clone(),
+            game_filter: request.game_filter.clone(),
+            nodump: request.nodump,
+            prefer: request.prefer.clone(),
+            output: request.output,
+            compression: request.compression,
+            after_build: AfterBuild::Keep,
+            verify: request.verify,
+            dry_run: request.dry_run,
+            strict: request.strict,
+        },
+    )
+}
+
+pub fn fix(database: &Database, request: &FixRequest) -> crate::Result<FixReport> {
+    import_dat(
+        database,
+        &DatImportRequest {
+            dat_path: request.dat_path.clone(),
+        },
+    )?;
+    let dat_selector = resolve_dat_selector(&request.dat_path);
+    let directives = load_directives(database, &dat_selector, None)?;
+    let output = request
+        .output
+        .or_else(|| directives.output())
+        .unwrap_or_default();
+    if output != OutputFormat::Zip {
+        return Err(crate::Error::Unsupported(
+            "fix only rewrites ZIP collections".to_owned(),
+        ));
+    }
+    let source_roots = scan_fix_sources(database, request)?;
+
+    let collection = software_list_destination(
+        database,
+        &dat_selector,
+        None,
+        &request.collection_path.canonicalize_utf8()?,
+    )?;
+    let source_files = SourceRepository::new(database.pool()).load_source_files()?;
+    let (plan, excluded_zips) = plan_fix(
+        database,
+        request,
+        &dat_selector,
+        &directives,
+        &source_files,
+        source_roots,
+    )?;
+
+    let (unchanged, wrong): (Vec<_>, Vec<_>) = plan
+        .zips
+        .iter()
+        .cloned()
+        .partition(|zip_spec| collection_zip_matches(&source_files, &collection, zip_spec));
+    let collection_paths = |zip_specs: &[ZipSpec]| {
+        zip_specs
+            .iter()
+            .map(|zip_spec| collection.join(&zip_spec.file_name))
+            .collect::<Vec<_>>()
+    };
+    let mut report = FixReport {
+        rewritten_paths: collection_paths(&wrong),
+        unchanged_paths: collection_paths(&unchanged),
+        backed_up_paths: unexpected_collection_paths(
+            &collection,
+            &plan,
+            &excluded_zips,
+            &request.backup_path,
+        )?,
+        backup_path: None,
+        build_report: plan.report.clone(),
+        exit_code: plan.report.exit_code,
+    };
+    report.backed_up_paths.extend(
+        report
+            .rewritten_paths
+            .iter()
+            .filter(|path| path.exists())
+            .cloned(),
+    );
+    let nothing_to_change = report.rewritten_paths.is_empty() && report.backed_up_paths.is_empty();
+    if !plan.writes_files() || nothing_to_change {
+        if !request.dry_run {
+            report.rewritten_paths.clear();
+            report.backed_up_paths.clear();
+        }
+        return Ok(report);
+    }
+
+    let backup_path = request
+        .backup_path
+        .join(chrono::Local::now().format("%Y%m%d-%H%M%S").to_string());
+    swap_fixed_zips(
+        &BuildPlan {
+            zips: wrong,
+            ..plan
+        },
+        &collection,
+        &backup_path,
+        &report,
+        request.compression,
+        request.verify,
+    )?;
+    info!(
+        "rewrote {} zips, kept {}, moved {} files to {backup_path}",
+        report.rewritten_paths.len(),
+        report.unchanged_paths.len(),
+        report.backed_up_paths.len()
+    );
+    report.backup_path = Some(backup_path);
+    scan_source(
+        database,
+        &fix_scan_request(request, request.collection_path.clone()),
+    )?;
+    Ok(report)
+}
+
+/// Rehash every ROM the DAT expects in each output of the destination.
+///
+/// A DAT file is imported first; anything else names a DAT in the cache.
+/// Missing outputs and entries are reported, while an entry holding the
+/// wrong data fails verification.
+pub fn verify(database: &Database, request: &VerifyRequest) -> crate::Result<VerifyReport> {
+    if request.dat_path.is_file() {
+        import_dat(
+            database,
+            &DatImportRequest {
+                dat_path: request.dat_path.clone(),
+            },
+        )?;
+    }
+    let dat_selector = resolve_dat_selector(&request.dat_path);
+    let directives = load_directives(database, &dat_selector, None)?;
+    let mode = request
+        .mode
+        .or_else(|| directives.mode())
+        .unwrap_or_default();
+    let dat_roms = load_build_roms(
+        database,
+        &dat_selector,
+        None,
+        mode,
+        &request.release_preferences,
+        &request.game_filter,
+    )?
+    .dat_roms;
+    let output = request
+        .output
+        .or_else(|| directives.output())
+        .unwrap_or_default();
+    let destination_path =
+        software_list_destination(database, &dat_selector, None, &request.destination_path)?;
+    let nodumps_required = request
+        .nodump
+        .or_else(|| directives.nodump_policy())
+        .unwrap_or_default()
+        == NodumpPolicy::Required;
+    let bundles = dat_roms
+        .iter()
+        .filter(|rom| nodumps_required || !rom.nodump)
+        .fold(
+            BTreeMap::<&str, Vec<(&str, Sha1Digest)>>::new(),
+            |mut bundles, rom| {
+                bundles
+                    .entry(rom.bundle_name(mode))
+                    .or_default()
+                    .push((rom.rom_name.as_str(), rom.sha1));
+                bundles
+            },
+        );
+
+    let mut report = VerifyReport::default();
+    let mut mismatches = Vec::new();
+    for (bundle_name, entries) in bundles {
+        let output_path = destination_path.join(output.bundle_file_name(bundle_name));
+        if !output_path.exists() {
+            report.missing_paths.push(output_path);
+            continue;
+        }
+        let (missing, wrong): (Vec<_>, Vec<_>) = check_output(&output_path, output, &entries)?
+            .into_iter()
+            .partition(|mismatch| mismatch.actual.is_none());
+        report.missing_entries.extend(missing);
+        mismatches.extend(wrong);
+        report.verified_paths.push(output_path);
+    }
+
+    // Missing outputs and entries are listed even when others are wrong.
+    for missing_path in &report.missing_paths {
+        warn!("{missing_path} is missing");
+    }
+    for missing in &report.missing_entries {
+        warn!("{missing}");
+    }
+    if !report.missing_paths.is_empty() || !report.missing_entries.is_empty() {
+        report.exit_code = 2;
+    }
+    info!(
+        "verified {} outputs; {} outputs and {} entries are missing",
+        report.verified_paths.len(),
+        report.missing_paths.len(),
+        report.missing_entries.len()
+    );
+    verification_result(&mismatches)?;
+    Ok(report)
+}
+
+/// Scan a source and write a DAT with one game per archive and one per
+/// directory of loose files.
+///
+/// Entries of nested archives are left out, since the outer archive entry
+/// already describes them.
+pub fn create_dat(
+    database: &Database,
+    request: &DatCreateRequest,
+) -> crate::Result<DatCreateReport> {
+    let source_root = scan_source(
+        database,
+        &SourceScanRequest {
+            source_path: request.source_path.clone(),
+            jobs: request.jobs,
+            nested_depth: request.nested_depth,
+        },
+    )?
+    .source_path;
+    let dat_path = request
+        .dat_path
+        .canonicalize_utf8()
+        .unwrap_or_else(|_| request.dat_path.clone());
+    let scanned_roms = SourceRepository::new(database.pool()).load_scanned_roms(&source_root)?;
+
+    let mut games = BTreeMap::<String, BTreeMap<String, logiqx::Rom>>::new();
+    let mut game_containers = BTreeMap::<String, &Utf8Path>::new();
+    for scanned in scanned_roms
+        .iter()
+        .filter(|scanned| scanned.source.archive_chain.is_empty())
+        .filter(|scanned| scanned.source.canonical_path != dat_path.as_str())
+    {
+        let (container, game_name, rom_name) =
+            created_game_and_rom_names(&source_root, &scanned.source)?;
+        let claimed_by = *game_containers
+            .entry(game_name.clone())
+            .or_insert(container);
+        if claimed_by != container {
+            warn!(
+                "skipping {}: game {game_name} already comes from {claimed_by}",
+                scanned.source.display_name()
+            );
+            continue;
+        }
+        let roms = games.entry(game_name).or_default();
+        if roms.contains_key(&rom_name) {
+            warn!(
+                "skipping {}: its game already has a ROM named {rom_name}",
+                scanned.source.display_name()
+            );
+            continue;
+        }
+        let size = i32::try_from(scanned.size).map_err(|_| {
+            crate::Error::RomSize(format!(
+                "{} is too large for a Logiqx ROM size",
+                scanned.source.display_name()
+            ))
+        })?;
+        let rom = logiqx::Rom::new(
+            rom_name.clone(),
+            size,
+            &scanned.crc32,
+            &scanned.md5,
+            &scanned.source.sha1,
+        );
+        roms.insert(rom_name, rom);
+    }
+
+    let name = request.name.clone().unwrap_or_else(|| {
+        source_root
+            .file_name()
+            .unwrap_or(source_root.as_str())
+            .to_owned()
+    });
+    let header = logiqx::Header::new(
+        name.clone(),
+        Some(request.description.clone().unwrap_or(name)),
+        Some(
+            request
+                .version
+                .clone()
+                .unwrap_or_else(|| chrono::Local::now().format("%Y%m%d").to_string()),
+        ),
+        request.author.clone(),
+        request.homepage.clone(),
+        request.url.clone(),
+    );
+    let report = DatCreateReport {
+        games: games.len(),
+        roms: games.values().map(BTreeMap::len).sum(),
+    };
+    let games = games
+        .into_iter()
+        .map(|(name, roms)| logiqx::Game::new(name, String::new(), roms.into_values().collect()))
+        .collect();
+    logiqx::DataFile::new(header, games).write_to(std::fs::File::create(&request.dat_path)?)?;
+
+    info!(
+        "wrote {} games and {} ROMs to {}",
+        report.games, report.roms, request.dat_path
+    );
+    Ok(report)
+}
+
+pub fn list_dats(database: &Database) -> crate::Result<Vec<DatSummary>> {
+    InventoryRepository::new(database.pool()).list_dats()
+}
+
+pub fn list_sources(database: &Database) -> crate::Result<Vec<SourceSummary>> {
+    InventoryRepository::new(database.pool()).list_sources()
+}
+
+pub fn cache_stats(database: &Database) -> crate::Result<CacheStats> {
+    InventoryRepository::new(database.pool()).stats()
+}
+
+pub fn find_roms(database: &Database, query: &RomQuery) -> crate::Result<RomLookup> {
+    InventoryRepository::new(database.pool()).find_roms(query)
+}
+
+/// Rebuild an imported DAT from the cache, header included, ready to be
+/// written back out as Logiqx XML.
+pub fn export_dat(
+    database: &Database,
+    request: &DatExportRequest,
+) -> crate::Result<logiqx::DataFile> {
+    let dat_selector = resolve_dat_selector(&request.dat_path);
+    DatRepository::new(database.pool()).export(
+        dat_selector.repository_selector(),
+        request.dat_version.as_deref(),
+    )
+}
+
+pub fn diff_dats(database: &Database, request: &DatDiffRequest) -> crate::Result<DatDiff> {
+    let old = load_data_file(
+        database,
+        &request.old_dat_path,
+        request.old_version.as_deref(),
+    )?;
+    let new = load_data_file(
+        database,
+        &request.new_dat_path,
+        request.new_version.as_deref(),
+    )?;
+    Ok(logiqx::diff(&old, &new))
+}
+
+/// Lint a DAT file, reporting every problem found rather than stopping at the
+/// first.
+pub fn check_dat(dat_path: &Utf8Path) -> crate::Result<Vec<DatIssue>> {
+    logiqx::check(&std::fs::read_to_string(dat_path)?)
+}
+
+/// Read a `--games-from` list: one game name per line, with blank lines
+/// and `#` comments skipped.
+pub fn read_game_list(path: &Utf8Path) -> crate::Result<BTreeSet<String>> {
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+pub fn remove_dat(
+    database: &Database,
+    request: &DatRemovalRequest,
+) -> crate::Result<DatRemovalReport> {
+    let dat_selector = resolve_dat_selector(&request.dat_path);
+    let (games, roms) = MaintenanceRepository::new(database.pool()).remove_dat(
+        dat_selector.repository_selector(),
+        request.dat_version.as_deref(),
+    )?;
+    info!(
+        "removed DAT {} ({games} games, {roms} ROMs)",
+        dat_selector.value()
+    );
+    Ok(DatRemovalReport { games, roms })
+}
+
+/// Forget a scanned source root. The root may already be gone from disk, in
+/// which case it is matched by its absolute path as given.
+pub fn remove_source(
+    database: &Database,
+    request: &SourceRemovalRequest,
+) -> crate::Result<CacheRemovalReport> {
+    let source_root = request.source_path.canonicalize_utf8().or_else(|_| {
+        Utf8PathBuf::try_from(std::path::absolute(&request.source_path)?)
+            .map_err(|error| crate::Error::InvalidPath(error.to_string()))
+    })?;
+    let report = CacheRemovalReport::from(
+        MaintenanceRepository::new(database.pool()).remove_source(&source_root)?,
+    );
+    info!(
+        "removed source {source_root} ({} ROM files, {} archives)",
+        report.rom_files, report.archives
+    );
+    Ok(report)
+}
+
+/// Drop rows for source files that no longer exist and compact the cache.
+pub fn collect_garbage(database: &Database) -> crate::Result<CacheRemovalReport> {
+    let report =
+        CacheRemovalReport::from(MaintenanceRepository::new(database.pool()).collect_garbage()?);
+    info!(
+        "removed {} ROM files, {} archives and {} source roots no longer on disk",
+        report.rom_files, report.archives, report.source_roots
+    );
+    Ok(report)
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 enum BuildDatSelector {
     FileName(String),
@@ -152,6 +1002,371 @@ impl BuildDatSelector {
     }
 }
 
+/// Import every DAT in `dat_dir`. DATs that fail to import are reported as
+/// failed builds, named after their file, rather than stopping the others.
+fn import_dat_dir(
+    database: &Database,
+    dat_dir: &Utf8Path,
+) -> crate::Result<(Vec<DatSummary>, Vec<DatBuildReport>)> {
+    let mut dat_paths = dat_dir
+        .read_dir_utf8()?
+        .map(|entry| entry.map(camino::Utf8DirEntry::into_path))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    dat_paths.retain(|path| {
+        path.is_file()
+            && path.extension().is_some_and(|extension| {
+                extension.eq_ignore_ascii_case("dat") || extension.eq_ignore_ascii_case("xml")
+            })
+    });
+    dat_paths.sort();
+
+    let mut imported_file_names = BTreeSet::new();
+    let mut failed = Vec::new();
+    for dat_path in dat_paths {
+        let imported = import_dat(
+            database,
+            &DatImportRequest {
+                dat_path: dat_path.clone(),
+            },
+        )
+        .and_then(|_| Ok(dat_path.canonicalize_utf8()?));
+        match imported {
+            Ok(canonical_path) => {
+                imported_file_names.insert(canonical_path.into_string());
+            }
+            Err(error) => failed.push(DatBuildReport::failed(
+                dat_path.file_name().unwrap_or(dat_path.as_str()).to_owned(),
+                &error,
+            )),
+        }
+    }
+    let dats = list_dats(database)?
+        .into_iter()
+        .filter(|dat| {
+            dat.latest
+                && dat
+                    .file_name
+                    .as_ref()
+                    .is_some_and(|file_name| imported_file_names.contains(file_name))
+        })
+        .collect();
+    Ok((dats, failed))
+}
+
+/// DAT header names become directory names, so path separators in them are
+/// replaced.
+fn dat_output_dir_name(dat_name: &str) -> crate::Result<String> {
+    let dir_name = dat_name.replace(['/', '\\', '\0'], "_");
+    if dir_name.is_empty() || dir_name == "." || dir_name == ".." {
+        return Err(crate::Error::InvalidPath(format!(
+            "DAT name cannot be used as an output directory: {dat_name}"
+        )));
+    }
+    Ok(dir_name)
+}
+
+/// Plan the collection's zips, along with the zip names of the games the
+/// filters and 1G1R choice leave out, which fix leaves as they are.
+fn plan_fix(
+    database: &Database,
+    request: &FixRequest,
+    dat_selector: &BuildDatSelector,
+    directives: &DatDirectives,
+    source_files: &[SourceFile],
+    source_roots: Vec<String>,
+) -> crate::Result<(BuildPlan, BTreeSet<String>)> {
+    let mode = request
+        .mode
+        .or_else(|| directives.mode())
+        .unwrap_or_default();
+    let build_roms = load_build_roms(
+        database,
+        dat_selector,
+        None,
+        mode,
+        &request.release_preferences,
+        &request.game_filter,
+    )?;
+    let mut plan = plan_build(
+        &build_roms.dat_roms,
+        source_files,
+        &BuildRequest {
+            dat_name: dat_selector.value().to_owned(),
+            source_roots,
+            mode,
+            nodump: request
+                .nodump
+                .or_else(|| directives.nodump_policy())
+                .unwrap_or_default(),
+            prefer: request.prefer.clone(),
+            dry_run: request.dry_run,
+            strict: request.strict,
+        },
+    );
+    plan.report.family_choices = build_roms.family_choices;
+    report_build_outcome(&plan.report, &request.prefer);
+    let excluded_zips = build_roms
+        .excluded_roms
+        .iter()
+        .map(|rom| format!("{}.zip", rom.bundle_name(mode)))
+        .collect();
+    Ok((plan, excluded_zips))
+}
+
+/// Scan the collection and every extra source, returning their roots.
+fn scan_fix_sources(database: &Database, request: &FixRequest) -> crate::Result<Vec<String>> {
+    std::iter::once(&request.collection_path)
+        .chain(&request.source_paths)
+        .map(|source_path| {
+            scan_source(database, &fix_scan_request(request, source_path.clone()))
+                .map(|report| report.source_path.to_string())
+        })
+        .collect()
+}
+
+const fn fix_scan_request(request: &FixRequest, source_path: Utf8PathBuf) -> SourceScanRequest {
+    SourceScanRequest {
+        source_path,
+        jobs: request.jobs,
+        nested_depth: request.nested_depth,
+    }
+}
+
+/// Stage every rewritten zip before touching the collection, so each one is
+/// built from the collection as it was scanned, then move the old files to
+/// the backup and the staged zips into place.
+fn swap_fixed_zips(
+    plan: &BuildPlan,
+    collection: &Utf8Path,
+    backup_path: &Utf8Path,
+    report: &FixReport,
+    compression: ZipCompression,
+    verify: bool,
+) -> crate::Result<()> {
+    let staging_path = collection.join(FIX_STAGING_DIR);
+    write_plan_with_compression(plan, &staging_path, compression, hash_existing_output)?;
+    if verify && let Err(error) = verify_outputs(plan, &staging_path, OutputFormat::Zip) {
+        std::fs::remove_dir_all(&staging_path)?;
+        return Err(error);
+    }
+    let relative = |path: &Utf8Path| {
+        path.strip_prefix(collection)
+            .map(Utf8Path::to_path_buf)
+            .map_err(|_| crate::Error::InvalidPath(format!("{path} is outside {collection}")))
+    };
+    for path in &report.backed_up_paths {
+        move_path(path, &backup_path.join(relative(path)?))?;
+    }
+    for path in &report.rewritten_paths {
+        move_path(&staging_path.join(relative(path)?), path)?;
+    }
+    Ok(std::fs::remove_dir_all(&staging_path)?)
+}
+
+/// Where `fix` writes replacement zips before swapping them in.
+const FIX_STAGING_DIR: &str = ".mame_coalesce-fix";
+
+/// A collection zip is correct when its scanned entries are exactly the
+/// planned names and ROMs, with nothing extra.
+fn collection_zip_matches(
+    source_files: &[SourceFile],
+    collection: &Utf8Path,
+    zip_spec: &ZipSpec,
+) -> bool {
+    let zip_path = collection.join(&zip_spec.file_name);
+    let existing = source_files
+        .iter()
+        .filter(|source| source.canonical_path == zip_path.as_str())
+        .map(|source| (source.display_name(), source.sha1))
+        .collect::<BTreeSet<_>>();
+    let planned = zip_spec
+        .entries
+        .iter()
+        .map(|entry| {
+            (
+                format!("{zip_path}:{}", entry.output_name),
+                entry.source.sha1,
+            )
+        })
+        .collect::<BTreeSet<_>>();
+    zip_path.is_file() && existing == planned
+}
+
+/// Top-level collection entries that neither a planned zip nor a game the
+/// fix leaves out accounts for.
+fn unexpected_collection_paths(
+    collection: &Utf8Path,
+    plan: &BuildPlan,
+    excluded_zips: &BTreeSet<String>,
+    backup_path: &Utf8Path,
+) -> crate::Result<Vec<Utf8PathBuf>> {
+    if !collection.is_dir() {
+        return Ok(Vec::new());
+    }
+    let planned = plan
+        .zips
+        .iter()
+        .map(|zip_spec| zip_spec.file_name.as_str())
+        .chain(excluded_zips.iter().map(String::as_str))
+        .chain(std::iter::once(FIX_STAGING_DIR))
+        .collect::<BTreeSet<_>>();
+    let backup_path = backup_path.canonicalize_utf8().ok();
+    let mut paths = collection
+        .read_dir_utf8()?
+        .map(|entry| Ok(entry?.path().to_path_buf()))
+        .collect::<crate::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|file_name| !planned.contains(file_name))
+                && backup_path.as_deref() != Some(path.as_path())
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Source files under `source_root` holding only ROMs that the plan writes,
+/// and at least one that it reads. Files inside the build destination are
+/// never consumed, since they may be the outputs themselves.
+fn consumed_source_paths(
+    plan: &BuildPlan,
+    source_files: &[SourceFile],
+    source_root: &Utf8Path,
+    destination: &Utf8Path,
+) -> Vec<Utf8PathBuf> {
+    let entries = plan.zips.iter().flat_map(|zip_spec| &zip_spec.entries);
+    let written_sha1s = entries
+        .clone()
+        .map(|entry| entry.source.sha1)
+        .collect::<BTreeSet<_>>();
+    let read_paths = entries
+        .map(|entry| entry.source.canonical_path.as_str())
+        .collect::<BTreeSet<_>>();
+    let destination = destination
+        .canonicalize_utf8()
+        .unwrap_or_else(|_| destination.to_path_buf());
+
+    let mut consumed = BTreeMap::<&str, bool>::new();
+    source_files
+        .iter()
+        .filter(|source| source.source_root == source_root.as_str())
+        .for_each(|source| {
+            *consumed.entry(&source.canonical_path).or_insert(true) &=
+                written_sha1s.contains(&source.sha1);
+        });
+    consumed
+        .into_iter()
+        .filter(|(path, all_written)| *all_written && read_paths.contains(path))
+        .map(|(path, _)| Utf8PathBuf::from(path))
+        .filter(|path| !path.starts_with(&destination))
+        .collect()
+}
+
+/// The sources `after_build` applies to, disposed of unless the plan writes
+/// nothing.
+fn consume_sources(
+    plan: &BuildPlan,
+    source_files: &[SourceFile],
+    source_root: &Utf8Path,
+    destination_path: &Utf8Path,
+    after_build: &AfterBuild,
+) -> crate::Result<Vec<Utf8PathBuf>> {
+    if *after_build == AfterBuild::Keep {
+        return Ok(Vec::new());
+    }
+    let consumed_paths = consumed_source_paths(plan, source_files, source_root, destination_path);
+    if plan.writes_files() {
+        dispose_consumed_sources(&consumed_paths, source_root, after_build)?;
+    }
+    Ok(consumed_paths)
+}
+
+fn dispose_consumed_sources(
+    paths: &[Utf8PathBuf],
+    source_root: &Utf8Path,
+    after_build: &AfterBuild,
+) -> crate::Result<()> {
+    paths.iter().try_for_each(|path| match after_build {
+        AfterBuild::Keep => Ok(()),
+        AfterBuild::Move(move_to) => {
+            let relative_path = path.strip_prefix(source_root).map_err(|_| {
+                crate::Error::InvalidPath(format!("{path} is not under {source_root}"))
+            })?;
+            move_path(path, &move_to.join(relative_path))
+        }
+        AfterBuild::Delete => Ok(std::fs::remove_file(path)?),
+    })?;
+    match after_build {
+        AfterBuild::Keep => {}
+        AfterBuild::Move(move_to) => info!("moved {} consumed sources to {move_to}", paths.len()),
+        AfterBuild::Delete => info!("deleted {} consumed sources", paths.len()),
+    }
+    Ok(())
+}
+
+/// Rename, falling back to copy and delete when `to` is on another
+/// filesystem.
+fn move_path(from: &Utf8Path, to: &Utf8Path) -> crate::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::rename(from, to) {
+        Err(error) if error.kind() == std::io::ErrorKind::CrossesDevices && from.is_file() => {
+            std::fs::copy(from, to)?;
+            std::fs::remove_file(from)?;
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Archive entries belong to a game named after the archive; loose files to
+/// one named after their directory, which is the source root's own name for
+/// files directly inside it. The archive or directory is returned first, so
+/// same-named ones in different subdirectories can be told apart.
+fn created_game_and_rom_names<'a>(
+    source_root: &'a Utf8Path,
+    source: &'a SourceFile,
+) -> crate::Result<(&'a Utf8Path, String, String)> {
+    let path = Utf8Path::new(&source.canonical_path);
+    let names = source.entry_name.as_ref().map_or_else(
+        || {
+            path.file_name().and_then(|file_name| {
+                let dir = path.parent().unwrap_or(source_root);
+                dir.file_name()
+                    .map(|game_name| (dir, game_name.to_owned(), file_name.to_owned()))
+            })
+        },
+        |entry_name| {
+            path.file_stem()
+                .map(|stem| (path, stem.to_owned(), entry_name.clone()))
+        },
+    );
+    names.ok_or_else(|| {
+        crate::Error::InvalidPath(format!(
+            "cannot name a DAT game for {}",
+            source.display_name()
+        ))
+    })
+}
+
+/// Parse a DAT file, or rebuild an imported DAT from the cache by name.
+fn load_data_file(
+    database: &Database,
+    dat_path: &Utf8PathBuf,
+    version: Option<&str>,
+) -> crate::Result<logiqx::DataFile> {
+    if dat_path.is_file() && version.is_none() {
+        logiqx::DataFile::from_path(dat_path)
+    } else {
+        DatRepository::new(database.pool()).export(
+            resolve_dat_selector(dat_path).repository_selector(),
+            version,
+        )
+    }
+}
+
 fn resolve_dat_selector(dat_path: &Utf8PathBuf) -> BuildDatSelector {
     dat_path.canonicalize_utf8().map_or_else(
         |_| BuildDatSelector::Name(dat_path.to_string()),
@@ -159,26 +1374,315 @@ fn resolve_dat_selector(dat_path: &Utf8PathBuf) -> BuildDatSelector {
     )
 }
 
+/// The DAT's clrmamepro directives, warning about values that aren't
+/// understood and so are not applied.
+fn load_directives(
+    database: &Database,
+    dat_selector: &BuildDatSelector,
+    version: Option<&str>,
+) -> crate::Result<DatDirectives> {
+    let directives = BuildRepository::new(database.pool())
+        .load_directives(dat_selector.repository_selector(), version)?;
+    for (directive, value, understood) in [
+        (
+            "forcemerging",
+            &directives.merging,
+            directives.mode().is_some(),
+        ),
+        (
+            "forcenodump",
+            &directives.nodump,
+            directives.nodump_policy().is_some(),
+        ),
+        (
+            "forcepacking",
+            &directives.packing,
+            directives.output().is_some(),
+        ),
+    ] {
+        if let Some(value) = value.as_ref().filter(|_| !understood) {
+            warn!(
+                "ignoring {directive}=\"{value}\" in DAT {}",
+                dat_selector.value()
+            );
+        }
+    }
+    Ok(directives)
+}
+
+/// MAME looks for software list sets under `<rompath>/<list>/`, so a software
+/// list builds into that subdirectory of the destination.
+fn software_list_destination(
+    database: &Database,
+    dat_selector: &BuildDatSelector,
+    version: Option<&str>,
+    destination_path: &Utf8Path,
+) -> crate::Result<Utf8PathBuf> {
+    BuildRepository::new(database.pool())
+        .load_software_list_name(dat_selector.repository_selector(), version)?
+        .map_or_else(
+            || Ok(destination_path.to_path_buf()),
+            |list_name| Ok(destination_path.join(dat_output_dir_name(&list_name)?)),
+        )
+}
+
 fn source_scan_request_from_run(request: &RunWorkflowRequest) -> SourceScanRequest {
     SourceScanRequest {
         source_path: request.source_path.clone(),
         jobs: request.jobs,
+        nested_depth: request.nested_depth,
     }
 }
 
 fn build_workflow_request_from_run(request: &RunWorkflowRequest) -> BuildWorkflowRequest {
     BuildWorkflowRequest {
         dat_path: request.dat_path.clone(),
+        dat_version: None,
         source_path: request.source_path.clone(),
         destination_path: request.destination_path.clone(),
+        samples_path: request.samples_path.clone(),
         mode: request.mode,
+        release_preferences: request.release_preferences.clone(),
+        game_filter: request.game_filter.clone(),
+        nodump: request.nodump,
+        prefer: request.prefer.clone(),
+        output: request.output,
         compression: request.compression,
+        after_build: request.after_build.clone(),
+        verify: request.verify,
         dry_run: request.dry_run,
         strict: request.strict,
     }
 }
 
-fn report_build_outcome(report: &BuildReport) {
+/// Cached rows for outputs this plan may overwrite, keyed by canonical path.
+fn previous_outputs(
+    output_repository: &OutputRepository<'_>,
+    plan: &BuildPlan,
+    output: OutputFormat,
+    destination: &Utf8Path,
+) -> crate::Result<BTreeMap<String, ArchiveFile>> {
+    let Ok(destination) = destination.canonicalize_utf8() else {
+        return Ok(BTreeMap::new());
+    };
+    let paths = plan
+        .zips
+        .iter()
+        .map(|zip_spec| destination.join(output.file_name(zip_spec)).to_string())
+        .collect::<Vec<_>>();
+    Ok(output_repository
+        .load_outputs(&paths)?
+        .into_iter()
+        .map(|archive_file| (archive_file.path.clone(), archive_file))
+        .collect())
+}
+
+/// Trust the recorded SHA1 of an output that has not been touched since it
+/// was recorded; otherwise read it again.
+fn existing_output_sha1(
+    previous_outputs: &BTreeMap<String, ArchiveFile>,
+    path: &Utf8Path,
+) -> crate::Result<Sha1Digest> {
+    let path = path.canonicalize_utf8()?;
+    if let Some(previous) = previous_outputs.get(path.as_str())
+        && previous.matches_stamp(FileStamp::from_path(&path)?)
+        && let Ok(sha1) = Sha1Digest::try_from(previous.sha1.as_slice())
+    {
+        return Ok(sha1);
+    }
+    hash_existing_output(&path)
+}
+
+/// The ROMs a build writes and the games they come from.
+struct BuildRoms {
+    dat_roms: Vec<DatRom>,
+    family_choices: Vec<FamilyChoice>,
+    /// The games the filter and 1G1R choice kept, or `None` when every
+    /// game is built.
+    kept_games: Option<BTreeSet<String>>,
+    /// The ROMs of the games they left out.
+    excluded_roms: Vec<DatRom>,
+}
+
+/// The selected DAT's ROMs, restricted to the games the filter keeps. With
+/// the 1G1R layout, only the ROMs of the game chosen from each remaining
+/// parent/clone family are kept, along with the choices; with the split
+/// layout, clones leave out the ROMs they share with their parent.
+fn load_build_roms(
+    database: &Database,
+    dat_selector: &BuildDatSelector,
+    version: Option<&str>,
+    mode: BuildMode,
+    preferences: &ReleasePreferences,
+    filter: &GameFilter,
+) -> crate::Result<BuildRoms> {
+    let repository = BuildRepository::new(database.pool());
+    let mut dat_roms = repository.load_dat_roms(dat_selector.repository_selector(), version)?;
+    if mode == BuildMode::Split {
+        dat_roms.retain(|rom| !(rom.merged && rom.parent_name.is_some()));
+    }
+    if mode != BuildMode::OneGameOneRom && filter.is_empty() {
+        return Ok(BuildRoms {
+            dat_roms,
+            family_choices: Vec::new(),
+            kept_games: None,
+            excluded_roms: Vec::new(),
+        });
+    }
+    let games = repository.load_dat_games(dat_selector.repository_selector(), version)?;
+    if (filter.excluded.contains(&GameKind::Device)
+        || filter.excluded.contains(&GameKind::Mechanical))
+        && repository.lacks_device_flags(dat_selector.repository_selector(), version)?
+    {
+        warn!(
+            "{} was imported without device and mechanical flags; import it again to exclude them",
+            dat_selector.value()
+        );
+    }
+    let game_count = games.len();
+    let games = filter_games(games, filter);
+    if !filter.is_empty() {
+        info!("game filters kept {} of {game_count} games", games.len());
+    }
+    let (kept, family_choices) = if mode == BuildMode::OneGameOneRom {
+        let family_choices = choose_games(&games, preferences);
+        let kept = family_choices
+            .iter()
+            .filter_map(|choice| choice.selected.clone())
+            .collect::<BTreeSet<_>>();
+        (kept, family_choices)
+    } else {
+        let kept = games.into_iter().map(|game| game.game_name).collect();
+        (kept, Vec::new())
+    };
+    let (dat_roms, excluded_roms) = dat_roms
+        .into_iter()
+        .partition(|rom| kept.contains(&rom.game_name));
+    Ok(BuildRoms {
+        dat_roms,
+        family_choices,
+        kept_games: Some(kept),
+        excluded_roms,
+    })
+}
+
+/// The sample sets the built games play, matched against the build's
+/// source, when the build writes samples.
+fn plan_dat_samples(
+    database: &Database,
+    dat_selector: &BuildDatSelector,
+    request: &BuildWorkflowRequest,
+    kept_games: Option<&BTreeSet<String>>,
+    source_files: &[SourceFile],
+    source_root: &Utf8Path,
+) -> crate::Result<Option<BuildPlan>> {
+    if request.samples_path.is_none() {
+        return Ok(None);
+    }
+    let repository = BuildRepository::new(database.pool());
+    let version = request.dat_version.as_deref();
+    let mut dat_samples =
+        repository.load_dat_samples(dat_selector.repository_selector(), version)?;
+    if dat_samples.is_empty()
+        && repository.lacks_samples(dat_selector.repository_selector(), version)?
+    {
+        warn!(
+            "{} was imported without its samples; import it again to write them",
+            dat_selector.value()
+        );
+    }
+    if let Some(kept_games) = kept_games {
+        dat_samples.retain(|sample| kept_games.contains(&sample.game_name));
+    }
+    Ok(Some(plan_samples(
+        &dat_samples,
+        source_files,
+        &[source_root.to_string()],
+        request.dry_run,
+    )))
+}
+
+fn with_sample_report(report: &BuildReport, sample_plan: Option<&BuildPlan>) -> BuildReport {
+    let mut report = report.clone();
+    if let Some(sample_plan) = sample_plan {
+        report
+            .missing_samples
+            .clone_from(&sample_plan.report.missing_samples);
+        report.matched_samples = sample_plan.report.matched_samples;
+    }
+    report
+}
+
+/// Sample sets are always zips, which is what MAME reads them from.
+fn write_sample_sets(
+    output_repository: &OutputRepository<'_>,
+    request: &BuildWorkflowRequest,
+    sample_plan: Option<&BuildPlan>,
+) -> crate::Result<Vec<WrittenOutput>> {
+    match (&request.samples_path, sample_plan) {
+        (Some(samples_path), Some(sample_plan)) => write_outputs(
+            output_repository,
+            sample_plan,
+            OutputFormat::Zip,
+            samples_path,
+            request.compression,
+        ),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Outputs already identical to the plan are left in place.
+fn write_outputs(
+    output_repository: &OutputRepository<'_>,
+    plan: &BuildPlan,
+    output: OutputFormat,
+    destination_path: &Utf8Path,
+    compression: ZipCompression,
+) -> crate::Result<Vec<WrittenOutput>> {
+    let previous_outputs = previous_outputs(output_repository, plan, output, destination_path)?;
+    match output {
+        OutputFormat::Zip => {
+            write_plan_with_compression(plan, destination_path, compression, |path| {
+                existing_output_sha1(&previous_outputs, path)
+            })
+        }
+        OutputFormat::Directory(link) => write_plan_as_directories(plan, destination_path, link),
+        OutputFormat::SevenZip { solid } => {
+            write_plan_as_7z(plan, destination_path, solid, |path| {
+                existing_output_sha1(&previous_outputs, path)
+            })
+        }
+    }
+}
+
+/// Rows for rewritten zips; directory outputs have no single SHA1 to record.
+fn changed_outputs(written_outputs: &[WrittenOutput]) -> crate::Result<Vec<NewArchiveFile>> {
+    written_outputs
+        .iter()
+        .filter(|written_output| !written_output.unchanged)
+        .filter_map(|written_output| written_output.sha1.map(|sha1| (&written_output.path, sha1)))
+        .map(|(path, sha1)| {
+            let path = path.canonicalize_utf8()?;
+            let stamp = FileStamp::from_path(&path)?;
+            Ok(NewArchiveFile::from_stamp(&path, sha1, stamp, 0))
+        })
+        .collect()
+}
+
+/// Paths of the outputs a build left as they were.
+fn unchanged_outputs(written_outputs: &[WrittenOutput]) -> Vec<Utf8PathBuf> {
+    let unchanged_paths = written_outputs
+        .iter()
+        .filter(|written_output| written_output.unchanged)
+        .map(|written_output| written_output.path.clone())
+        .collect::<Vec<_>>();
+    if !unchanged_paths.is_empty() {
+        info!("{} outputs were already up to date", unchanged_paths.len());
+    }
+    unchanged_paths
+}
+
+fn report_build_outcome(report: &BuildReport, prefer: &SourcePreference) {
     info!("matched {} ROMs", report.matched_roms);
 
     if !report.missing_roms.is_empty() {
@@ -193,9 +1697,35 @@ fn report_build_outcome(report: &BuildReport) {
         }
     }
 
+    for choice in &report.family_choices {
+        match &choice.selected {
+            Some(selected) => info!("1G1R {}: kept {selected}: {}", choice.family, choice.reason),
+            None => warn!("1G1R {}: kept nothing: {}", choice.family, choice.reason),
+        }
+        for rejected in &choice.rejected {
+            info!(
+                "1G1R {}: skipped {}: {}",
+                choice.family, rejected.game_name, rejected.reason
+            );
+        }
+    }
+
+    if report.matched_samples > 0 || !report.missing_samples.is_empty() {
+        info!("matched {} samples", report.matched_samples);
+    }
+    if !report.missing_samples.is_empty() {
+        warn!("{} samples are missing", report.missing_samples.len());
+        for missing in &report.missing_samples {
+            warn!(
+                "missing sample: set={} sample={}",
+                missing.sample_set, missing.sample_name
+            );
+        }
+    }
+
     if !report.duplicate_matches.is_empty() {
         warn!(
-            "{} ROMs had duplicate source matches",
+            "{} ROMs had duplicate source matches; selected by --prefer {prefer}",
             report.duplicate_matches.len()
         );
         for duplicate in &report.duplicate_matches {
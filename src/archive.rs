@@ -0,0 +1,57 @@
+//! Container formats that sources hold ROMs in, shared by scanning and
+//! extraction.
+
+use std::{io::Write, path::Path};
+
+use camino::Utf8Path;
+
+/// A container recognized by its leading bytes rather than its extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    SevenZip,
+    Rar,
+    Gzip,
+    Tar,
+}
+
+impl ArchiveKind {
+    pub fn of_path(path: impl AsRef<Path>) -> crate::Result<Option<Self>> {
+        Ok(infer::get_from_path(path)?
+            .and_then(|file_type| Self::of_mime_type(file_type.mime_type())))
+    }
+
+    #[must_use]
+    pub fn of_bytes(data: &[u8]) -> Option<Self> {
+        infer::get(data).and_then(|file_type| Self::of_mime_type(file_type.mime_type()))
+    }
+
+    fn of_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type {
+            "application/zip" => Some(Self::Zip),
+            "application/x-7z-compressed" => Some(Self::SevenZip),
+            "application/vnd.rar" => Some(Self::Rar),
+            "application/gzip" => Some(Self::Gzip),
+            "application/x-tar" => Some(Self::Tar),
+            _ => None,
+        }
+    }
+}
+
+/// How much of a gzip stream is read to tell a `.tar.gz` from a single
+/// compressed file.
+pub const TAR_HEADER_LEN: u64 = 512;
+
+/// 7z and RAR readers only open archives by path, so nested ones are spooled
+/// to a temporary file first.
+pub fn with_spooled_file<T>(
+    data: &[u8],
+    read: impl FnOnce(&Utf8Path) -> crate::Result<T>,
+) -> crate::Result<T> {
+    let mut spooled = tempfile::NamedTempFile::new()?;
+    spooled.write_all(data)?;
+    spooled.flush()?;
+    let path = Utf8Path::from_path(spooled.path())
+        .ok_or_else(|| crate::Error::InvalidPath("temporary path is not UTF-8".to_owned()))?;
+    read(path)
+}
@@ -1,15 +1,21 @@
 use std::process::ExitCode;
 
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::Parser;
 
 mod logger;
 mod options;
-use options::{CacheCommand, Cli, Command};
+mod output;
+use options::{CacheCommand, Cli, Command, Config, DatCommand, FixArgs, Profile, required};
 
 use mame_coalesce::{
-    app::{self, BuildWorkflowRequest, DatImportRequest, RunWorkflowRequest, SourceScanRequest},
+    app::{
+        self, BuildAllRequest, BuildWorkflowRequest, DatCreateRequest, DatDiffRequest,
+        DatExportRequest, DatImportRequest, DatRemovalRequest, FixRequest, RunWorkflowRequest,
+        SourceRemovalRequest, SourceScanRequest, VerifyRequest,
+    },
     database::Database,
+    domain::IssueSeverity,
 };
 
 fn main() -> ExitCode {
@@ -25,70 +31,295 @@ fn main() -> ExitCode {
 
 fn run() -> mame_coalesce::Result<ExitCode> {
     let cli = Cli::parse();
-    let database = Database::open(&resolve_cache_path(cli.cache()))?;
+    // `dat check` only reads the DAT, so it needs neither config nor cache.
+    if let Command::Dat {
+        command: DatCommand::Check { dat, format },
+    } = cli.command()
+    {
+        return check_dat(dat, *format);
+    }
+    let (profile, cache_path) = profile_and_cache_path(&cli)?;
+    let database = Database::open(&resolve_cache_path(cache_path))?;
 
     match cli.command() {
         Command::Build(args) => {
+            let args = args.with_profile(&profile);
             let report = app::run(
                 &database,
                 &RunWorkflowRequest {
-                    dat_path: args.dat.clone(),
+                    dat_path: required(args.dat.as_ref(), "dat")?,
+                    source_path: required(args.source.as_ref(), "source")?,
+                    destination_path: required(args.out.as_ref(), "out")?,
+                    samples_path: args.options.samples.clone(),
+                    mode: args.options.mode(),
+                    release_preferences: args.options.release.preferences(),
+                    game_filter: args.options.filter.game_filter()?,
+                    nodump: args.options.nodump(),
+                    prefer: args.options.source_preference()?,
+                    output: args.options.output(),
+                    compression: args.options.compression()?,
+                    after_build: args.after_build.after_build()?,
+                    verify: args.options.verify,
+                    jobs: args.jobs.unwrap_or_default(),
+                    nested_depth: args.nested_depth.unwrap_or_default(),
+                    dry_run: args.options.dry_run,
+                    strict: args.options.strict(),
+                },
+            )?;
+            Ok(exit_code(report.exit_code))
+        }
+        Command::BuildAll(args) => {
+            let args = args.with_profile(&profile);
+            let report = app::build_all(
+                &database,
+                &BuildAllRequest {
+                    dat_dir: args.dats.clone(),
                     source_path: args.source.clone(),
                     destination_path: args.out.clone(),
-                    mode: args.options.layout.into(),
-                    compression: args.options.compression.into(),
-                    jobs: args.jobs,
+                    samples_path: args.options.samples.clone(),
+                    mode: args.options.mode(),
+                    release_preferences: args.options.release.preferences(),
+                    game_filter: args.options.filter.game_filter()?,
+                    nodump: args.options.nodump(),
+                    prefer: args.options.source_preference()?,
+                    output: args.options.output(),
+                    compression: args.options.compression()?,
+                    verify: args.options.verify,
+                    jobs: args.jobs.unwrap_or_default(),
+                    nested_depth: args.nested_depth.unwrap_or_default(),
                     dry_run: args.options.dry_run,
-                    strict: args.options.missing.strict(),
+                    strict: args.options.strict(),
+                },
+            )?;
+            Ok(exit_code(report.exit_code))
+        }
+        Command::Fix(args) => run_fix(&database, &args.with_profile(&profile)),
+        Command::Verify(args) => {
+            let args = args.with_profile(&profile);
+            let report = app::verify(
+                &database,
+                &VerifyRequest {
+                    dat_path: args.dat.clone(),
+                    destination_path: args.out.clone(),
+                    mode: args.layout.map(Into::into),
+                    release_preferences: args.release.preferences(),
+                    game_filter: args.filter.game_filter()?,
+                    nodump: args.nodump.map(Into::into),
+                    output: args.output(),
                 },
             )?;
             Ok(exit_code(report.exit_code))
         }
-        Command::Cache {
-            command: CacheCommand::Import { dat },
+        Command::Dat { command } => run_dat(&database, command, &profile),
+        Command::Cache { command } => run_cache(&database, command, &profile),
+    }
+}
+
+/// The `--profile` settings and the cache database to open. The config file
+/// is only read when a profile is named or `--cache` is absent, so a broken
+/// config does not stop commands that never need it.
+fn profile_and_cache_path(cli: &Cli) -> mame_coalesce::Result<(Profile, Option<Utf8PathBuf>)> {
+    if cli.profile().is_none()
+        && let Some(cache) = cli.cache()
+    {
+        return Ok((Profile::default(), Some(cache.clone())));
+    }
+    let config = Config::load(cli.config())?;
+    let profile = config.profile(cli.profile())?;
+    let cache_path = config.cache_path(cli, &profile);
+    Ok((profile, cache_path))
+}
+
+fn run_fix(database: &Database, args: &FixArgs) -> mame_coalesce::Result<ExitCode> {
+    let report = app::fix(
+        database,
+        &FixRequest {
+            dat_path: args.dat.clone(),
+            collection_path: args.collection.clone(),
+            source_paths: args.sources.clone(),
+            backup_path: args.backup_path(),
+            mode: args.options.mode(),
+            output: args.options.output(),
+            release_preferences: args.options.release.preferences(),
+            game_filter: args.options.filter.game_filter()?,
+            nodump: args.options.nodump(),
+            prefer: args.options.source_preference()?,
+            compression: args.options.compression()?,
+            verify: args.options.verify,
+            jobs: args.jobs.unwrap_or_default(),
+            nested_depth: args.nested_depth.unwrap_or_default(),
+            dry_run: args.options.dry_run,
+            strict: args.options.strict(),
+        },
+    )?;
+    Ok(exit_code(report.exit_code))
+}
+
+fn run_dat(
+    database: &Database,
+    command: &DatCommand,
+    profile: &Profile,
+) -> mame_coalesce::Result<ExitCode> {
+    match command {
+        DatCommand::Create(args) => {
+            app::create_dat(
+                database,
+                &DatCreateRequest {
+                    source_path: args.source.clone(),
+                    dat_path: args.out.clone(),
+                    name: args.name.clone(),
+                    description: args.description.clone(),
+                    version: args.version.clone(),
+                    author: args.author.clone(),
+                    homepage: args.homepage.clone(),
+                    url: args.url.clone(),
+                    jobs: args.jobs.or(profile.jobs).unwrap_or_default(),
+                    nested_depth: args
+                        .nested_depth
+                        .or(profile.nested_depth)
+                        .unwrap_or_default(),
+                },
+            )?;
+        }
+        DatCommand::Check { dat, format } => return check_dat(dat, *format),
+        DatCommand::Diff {
+            old,
+            new,
+            old_version,
+            new_version,
+            format,
         } => {
+            let diff = app::diff_dats(
+                database,
+                &DatDiffRequest {
+                    old_dat_path: old.clone(),
+                    old_version: old_version.clone(),
+                    new_dat_path: new.clone(),
+                    new_version: new_version.clone(),
+                },
+            )?;
+            output::dat_diff(&diff, *format)?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn check_dat(dat: &Utf8Path, format: options::OutputFormat) -> mame_coalesce::Result<ExitCode> {
+    let issues = app::check_dat(dat)?;
+    output::dat_issues(&issues, format)?;
+    if issues
+        .iter()
+        .any(|issue| issue.severity == IssueSeverity::Error)
+    {
+        return Ok(ExitCode::from(1));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_cache(
+    database: &Database,
+    command: &CacheCommand,
+    profile: &Profile,
+) -> mame_coalesce::Result<ExitCode> {
+    match command {
+        CacheCommand::Import { dat } => {
             app::import_dat(
-                &database,
+                database,
                 &DatImportRequest {
                     dat_path: dat.clone(),
                 },
             )?;
-            Ok(ExitCode::SUCCESS)
         }
-        Command::Cache {
-            command: CacheCommand::Scan { source, jobs },
+        CacheCommand::Scan {
+            source,
+            jobs,
+            nested_depth,
         } => {
             app::scan_source(
-                &database,
+                database,
                 &SourceScanRequest {
                     source_path: source.clone(),
-                    jobs: *jobs,
+                    jobs: jobs.or(profile.jobs).unwrap_or_default(),
+                    nested_depth: nested_depth.or(profile.nested_depth).unwrap_or_default(),
                 },
             )?;
-            Ok(ExitCode::SUCCESS)
         }
-        Command::Cache {
-            command: CacheCommand::Build(args),
-        } => {
+        CacheCommand::Build(args) => {
+            let args = args.with_profile(profile);
             let report = app::build(
-                &database,
+                database,
                 &BuildWorkflowRequest {
                     dat_path: args.dat.clone(),
+                    dat_version: args.dat_version.clone(),
                     source_path: args.source.clone(),
                     destination_path: args.out.clone(),
-                    mode: args.options.layout.into(),
-                    compression: args.options.compression.into(),
+                    samples_path: args.options.samples.clone(),
+                    mode: args.options.mode(),
+                    release_preferences: args.options.release.preferences(),
+                    game_filter: args.options.filter.game_filter()?,
+                    nodump: args.options.nodump(),
+                    prefer: args.options.source_preference()?,
+                    output: args.options.output(),
+                    compression: args.options.compression()?,
+                    after_build: args.after_build.after_build()?,
+                    verify: args.options.verify,
                     dry_run: args.options.dry_run,
-                    strict: args.options.missing.strict(),
+                    strict: args.options.strict(),
                 },
             )?;
-            Ok(exit_code(report.exit_code))
+            return Ok(exit_code(report.exit_code));
+        }
+        CacheCommand::ExportDat {
+            dat,
+            dat_version,
+            out,
+        } => {
+            let data_file = app::export_dat(
+                database,
+                &DatExportRequest {
+                    dat_path: dat.clone(),
+                    dat_version: dat_version.clone(),
+                },
+            )?;
+            match out {
+                Some(out) => data_file.write_to(std::fs::File::create(out)?)?,
+                None => data_file.write_to(std::io::stdout().lock())?,
+            }
+        }
+        CacheCommand::ListDats { format } => output::dats(&app::list_dats(database)?, *format)?,
+        CacheCommand::ListSources { format } => {
+            output::sources(&app::list_sources(database)?, *format)?;
+        }
+        CacheCommand::Stats { format } => output::stats(&app::cache_stats(database)?, *format)?,
+        CacheCommand::Find { query, format } => {
+            output::rom_lookup(&app::find_roms(database, &query.rom_query()?)?, *format)?;
+        }
+        CacheCommand::RemoveDat { dat, dat_version } => {
+            app::remove_dat(
+                database,
+                &DatRemovalRequest {
+                    dat_path: dat.clone(),
+                    dat_version: dat_version.clone(),
+                },
+            )?;
+        }
+        CacheCommand::RemoveSource { source } => {
+            app::remove_source(
+                database,
+                &SourceRemovalRequest {
+                    source_path: source.clone(),
+                },
+            )?;
+        }
+        CacheCommand::Gc => {
+            app::collect_garbage(database)?;
         }
     }
+    Ok(ExitCode::SUCCESS)
 }
 
-fn resolve_cache_path(cache: Option<&Utf8PathBuf>) -> Utf8PathBuf {
-    cache.cloned().unwrap_or_else(default_cache_path)
+fn resolve_cache_path(cache: Option<Utf8PathBuf>) -> Utf8PathBuf {
+    cache.unwrap_or_else(default_cache_path)
 }
 
 fn default_cache_path() -> Utf8PathBuf {
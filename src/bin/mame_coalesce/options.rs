@@ -1,6 +1,13 @@
-use camino::Utf8PathBuf;
+use std::collections::BTreeMap;
+
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::{Args, Parser, Subcommand, ValueEnum};
-use mame_coalesce::domain::{BuildMode, ZipCompression};
+use mame_coalesce::domain::{
+    AfterBuild, BuildMode, GameFilter, GameKind, LinkMode, NamePattern, NodumpPolicy,
+    OutputFormat as OutputFormatKind, ReleasePreferences, RomQuery, SourcePreference, YearRange,
+    ZipCompression,
+};
+use serde::Deserialize;
 
 #[derive(Parser)]
 #[command(name = "mame_coalesce")]
@@ -15,6 +22,24 @@ pub struct Cli {
     )]
     cache: Option<Utf8PathBuf>,
 
+    #[arg(
+        long,
+        env = "MAME_COALESCE_CONFIG",
+        global = true,
+        value_name = "config-toml",
+        help = "Config file with named build profiles [default: $XDG_CONFIG_HOME/mame_coalesce/config.toml]"
+    )]
+    config: Option<Utf8PathBuf>,
+
+    #[arg(
+        long,
+        env = "MAME_COALESCE_PROFILE",
+        global = true,
+        value_name = "name",
+        help = "Config profile supplying values not given on the command line"
+    )]
+    profile: Option<String>,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -25,6 +50,16 @@ impl Cli {
         self.cache.as_ref()
     }
 
+    #[must_use]
+    pub fn config(&self) -> Option<&Utf8Path> {
+        self.config.as_deref()
+    }
+
+    #[must_use]
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
     #[must_use]
     pub const fn command(&self) -> &Command {
         &self.command
@@ -35,6 +70,17 @@ impl Cli {
 pub enum Command {
     /// Import a DAT, scan sources, and write merged ZIP outputs.
     Build(BuildArgs),
+    /// Scan sources once and build every DAT in a directory or in the cache.
+    BuildAll(BuildAllArgs),
+    /// Rewrite the wrong ZIPs of an existing collection in place.
+    Fix(FixArgs),
+    /// Rehash the outputs in a destination against a DAT.
+    Verify(VerifyArgs),
+    /// Generate and inspect Logiqx DATs.
+    Dat {
+        #[command(subcommand)]
+        command: DatCommand,
+    },
     /// Manage the persistent cache explicitly.
     Cache {
         #[command(subcommand)]
@@ -42,38 +88,393 @@ pub enum Command {
     },
 }
 
+#[derive(Debug, Subcommand)]
+pub enum DatCommand {
+    /// Scan a source and write a DAT with one game per archive or directory.
+    Create(DatCreateArgs),
+    /// Report problems in a DAT file, failing when any is an error.
+    Check {
+        #[arg(value_name = "dat", help = "Logiqx DAT file to check")]
+        dat: Utf8PathBuf,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text, help = "Output format")]
+        format: OutputFormat,
+    },
+    /// Report games and ROMs that changed between two versions of a DAT.
+    Diff {
+        #[arg(
+            value_name = "old",
+            help = "Older Logiqx DAT file, or imported DAT header name"
+        )]
+        old: Utf8PathBuf,
+        #[arg(
+            value_name = "new",
+            help = "Newer Logiqx DAT file, or imported DAT header name"
+        )]
+        new: Utf8PathBuf,
+        #[arg(
+            long,
+            value_name = "version-or-sha1",
+            help = "Imported version of <old> to compare [default: latest]"
+        )]
+        old_version: Option<String>,
+        #[arg(
+            long,
+            value_name = "version-or-sha1",
+            help = "Imported version of <new> to compare [default: latest]"
+        )]
+        new_version: Option<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text, help = "Output format")]
+        format: OutputFormat,
+    },
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct DatCreateArgs {
+    #[arg(value_name = "source", help = "ROM source directory to describe")]
+    pub source: Utf8PathBuf,
+    #[arg(value_name = "out", help = "Logiqx DAT file to write")]
+    pub out: Utf8PathBuf,
+    #[arg(long, help = "Header name (default: the source directory's name)")]
+    pub name: Option<String>,
+    #[arg(long, help = "Header description (default: the header name)")]
+    pub description: Option<String>,
+    #[arg(long, help = "Header version (default: today as YYYYMMDD)")]
+    pub version: Option<String>,
+    #[arg(long, help = "Header author")]
+    pub author: Option<String>,
+    #[arg(long, help = "Header homepage")]
+    pub homepage: Option<String>,
+    #[arg(long, help = "Header URL")]
+    pub url: Option<String>,
+    #[arg(short, long, help = "Scan worker count [default: 0]")]
+    pub jobs: Option<usize>,
+    #[arg(
+        long,
+        help = "How many levels of archives inside archives to scan [default: 0]"
+    )]
+    pub nested_depth: Option<usize>,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum CacheCommand {
     /// Import or replace a DAT in the cache.
     Import {
-        #[arg(value_name = "dat", help = "Logiqx DAT file to import")]
+        #[arg(
+            value_name = "dat",
+            help = "Logiqx DAT or MAME software list to import"
+        )]
         dat: Utf8PathBuf,
     },
     /// Refresh cached ROM-file rows for a source root.
     Scan {
         #[arg(value_name = "source", help = "ROM source directory to scan")]
         source: Utf8PathBuf,
-        #[arg(short, long, default_value_t = 0, help = "Scan worker count")]
-        jobs: usize,
+        #[arg(short, long, help = "Scan worker count [default: 0]")]
+        jobs: Option<usize>,
+        #[arg(
+            long,
+            help = "How many levels of archives inside archives to scan [default: 0]"
+        )]
+        nested_depth: Option<usize>,
     },
     /// Build from DAT and source rows already present in the cache.
-    Build(CacheBuildArgs),
+    Build(Box<CacheBuildArgs>),
+    /// Write an imported DAT back out as Logiqx XML.
+    ExportDat {
+        #[arg(
+            value_name = "dat-or-name",
+            help = "Imported DAT header name or file path"
+        )]
+        dat: Utf8PathBuf,
+        #[arg(
+            long,
+            value_name = "version-or-sha1",
+            help = "Export this imported version instead of the latest"
+        )]
+        dat_version: Option<String>,
+        #[arg(
+            short,
+            long,
+            value_name = "out",
+            help = "File to write [default: stdout]"
+        )]
+        out: Option<Utf8PathBuf>,
+    },
+    /// List imported DATs with game and ROM counts.
+    ListDats {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text, help = "Output format")]
+        format: OutputFormat,
+    },
+    /// List scanned source roots with file counts.
+    ListSources {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text, help = "Output format")]
+        format: OutputFormat,
+    },
+    /// Show matched ROM files and per-DAT completeness.
+    Stats {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text, help = "Output format")]
+        format: OutputFormat,
+    },
+    /// Find which DATs expect a ROM and which scanned files provide it.
+    Find {
+        #[command(flatten)]
+        query: FindQueryArgs,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text, help = "Output format")]
+        format: OutputFormat,
+    },
+    /// Remove an imported DAT with its games and ROMs.
+    RemoveDat {
+        #[arg(
+            value_name = "dat-or-name",
+            help = "Imported DAT file path or DAT header name"
+        )]
+        dat: Utf8PathBuf,
+        #[arg(
+            long,
+            value_name = "version-or-sha1",
+            help = "Remove only this version [default: every version]"
+        )]
+        dat_version: Option<String>,
+    },
+    /// Remove cached ROM-file rows for a scanned source root.
+    RemoveSource {
+        #[arg(value_name = "source", help = "Previously scanned source directory")]
+        source: Utf8PathBuf,
+    },
+    /// Drop rows for source files that no longer exist and compact the cache.
+    Gc,
 }
 
 #[derive(Clone, Debug, Args)]
 pub struct BuildArgs {
-    #[arg(value_name = "dat", help = "Logiqx DAT file to import")]
-    pub dat: Utf8PathBuf,
+    #[arg(
+        value_name = "dat",
+        help = "Logiqx DAT or MAME software list to import"
+    )]
+    pub dat: Option<Utf8PathBuf>,
     #[arg(value_name = "source", help = "ROM source directory to scan")]
-    pub source: Utf8PathBuf,
+    pub source: Option<Utf8PathBuf>,
     #[arg(value_name = "out", help = "Destination directory for output ZIPs")]
+    pub out: Option<Utf8PathBuf>,
+    #[arg(short, long, help = "Scan worker count [default: 0]")]
+    pub jobs: Option<usize>,
+    #[arg(
+        long,
+        help = "How many levels of archives inside archives to scan [default: 0]"
+    )]
+    pub nested_depth: Option<usize>,
+    #[command(flatten)]
+    pub after_build: AfterBuildArgs,
+    #[command(flatten)]
+    pub options: BuildOptions,
+}
+
+impl BuildArgs {
+    /// Fills arguments missing from the command line with `profile` values.
+    #[must_use]
+    pub fn with_profile(&self, profile: &Profile) -> Self {
+        Self {
+            dat: self.dat.clone().or_else(|| profile.dat.clone()),
+            source: self.source.clone().or_else(|| profile.source.clone()),
+            out: self.out.clone().or_else(|| profile.out.clone()),
+            jobs: self.jobs.or(profile.jobs),
+            nested_depth: self.nested_depth.or(profile.nested_depth),
+            after_build: self.after_build.clone(),
+            options: self.options.with_profile(profile),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct AfterBuildArgs {
+    #[arg(
+        long,
+        value_enum,
+        requires_if("move", "move_to"),
+        help = "What to do with source files fully consumed by a verified build [default: keep]"
+    )]
+    pub after_build: Option<AfterBuildArg>,
+    #[arg(
+        long,
+        value_name = "dir",
+        help = "Directory receiving consumed sources for --after-build move"
+    )]
+    pub move_to: Option<Utf8PathBuf>,
+}
+
+impl AfterBuildArgs {
+    pub fn after_build(&self) -> mame_coalesce::Result<AfterBuild> {
+        match (self.after_build.unwrap_or_default(), &self.move_to) {
+            (AfterBuildArg::Keep, _) => Ok(AfterBuild::Keep),
+            (AfterBuildArg::Move, Some(move_to)) => Ok(AfterBuild::Move(move_to.clone())),
+            // The parser rejects `--after-build move` without `--move-to`.
+            (AfterBuildArg::Move, None) => Err(mame_coalesce::Error::Config(
+                "--after-build move requires --move-to".to_owned(),
+            )),
+            (AfterBuildArg::Delete, _) => Ok(AfterBuild::Delete),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct BuildAllArgs {
+    #[arg(value_name = "source", help = "ROM source directory to scan")]
+    pub source: Utf8PathBuf,
+    #[arg(
+        value_name = "out",
+        help = "Destination directory; each DAT is written to <out>/<DAT name>"
+    )]
     pub out: Utf8PathBuf,
-    #[arg(short, long, default_value_t = 0, help = "Scan worker count")]
-    pub jobs: usize,
+    #[arg(
+        long,
+        value_name = "dir",
+        help = "Import and build the .dat and .xml files in this directory instead of every cached DAT"
+    )]
+    pub dats: Option<Utf8PathBuf>,
+    #[arg(short, long, help = "Scan worker count [default: 0]")]
+    pub jobs: Option<usize>,
+    #[arg(
+        long,
+        help = "How many levels of archives inside archives to scan [default: 0]"
+    )]
+    pub nested_depth: Option<usize>,
     #[command(flatten)]
     pub options: BuildOptions,
 }
 
+impl BuildAllArgs {
+    /// Fills options missing from the command line with `profile` values.
+    #[must_use]
+    pub fn with_profile(&self, profile: &Profile) -> Self {
+        Self {
+            source: self.source.clone(),
+            out: self.out.clone(),
+            dats: self.dats.clone(),
+            jobs: self.jobs.or(profile.jobs),
+            nested_depth: self.nested_depth.or(profile.nested_depth),
+            options: self.options.with_profile(profile),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct FixArgs {
+    #[arg(value_name = "dat", help = "Logiqx DAT file to import")]
+    pub dat: Utf8PathBuf,
+    #[arg(
+        value_name = "collection",
+        help = "Collection directory scanned as a source and rewritten in place"
+    )]
+    pub collection: Utf8PathBuf,
+    #[arg(
+        long = "source",
+        value_name = "dir",
+        help = "Additional ROM source directory to scan; may be repeated"
+    )]
+    pub sources: Vec<Utf8PathBuf>,
+    #[arg(
+        long,
+        value_name = "dir",
+        help = "Directory receiving replaced and unexpected files [default: <collection>.backup]"
+    )]
+    pub backup: Option<Utf8PathBuf>,
+    #[arg(short, long, help = "Scan worker count [default: 0]")]
+    pub jobs: Option<usize>,
+    #[arg(
+        long,
+        help = "How many levels of archives inside archives to scan [default: 0]"
+    )]
+    pub nested_depth: Option<usize>,
+    #[command(flatten)]
+    pub options: BuildOptions,
+}
+
+impl FixArgs {
+    /// Fills options missing from the command line with `profile` values.
+    #[must_use]
+    pub fn with_profile(&self, profile: &Profile) -> Self {
+        Self {
+            dat: self.dat.clone(),
+            collection: self.collection.clone(),
+            sources: self.sources.clone(),
+            backup: self.backup.clone(),
+            jobs: self.jobs.or(profile.jobs),
+            nested_depth: self.nested_depth.or(profile.nested_depth),
+            options: self.options.with_profile(profile),
+        }
+    }
+
+    /// `--backup`, or a `.backup` sibling of the collection directory.
+    #[must_use]
+    pub fn backup_path(&self) -> Utf8PathBuf {
+        self.backup.clone().unwrap_or_else(|| {
+            let name = self.collection.file_name().unwrap_or("collection");
+            self.collection.with_file_name(format!("{name}.backup"))
+        })
+    }
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct VerifyArgs {
+    #[arg(
+        value_name = "dat-or-name",
+        help = "Logiqx DAT file to import, or imported DAT header name"
+    )]
+    pub dat: Utf8PathBuf,
+    #[arg(value_name = "out", help = "Destination directory holding the outputs")]
+    pub out: Utf8PathBuf,
+    #[arg(
+        long,
+        value_enum,
+        help = "Output ZIP layout [default: the DAT's forcemerging, else parent-bundles]"
+    )]
+    pub layout: Option<LayoutArg>,
+    #[command(flatten)]
+    pub release: ReleaseArgs,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+    #[arg(
+        long,
+        value_enum,
+        help = "Undumped ROM policy [default: the DAT's forcenodump, else required]"
+    )]
+    pub nodump: Option<NodumpArg>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Output format [default: the DAT's forcepacking, else zip]"
+    )]
+    pub output: Option<OutputArg>,
+}
+
+impl VerifyArgs {
+    /// Fills the layout, release, filter, nodump and output settings missing
+    /// from the command line with `profile` values.
+    #[must_use]
+    pub fn with_profile(&self, profile: &Profile) -> Self {
+        Self {
+            dat: self.dat.clone(),
+            out: self.out.clone(),
+            layout: self.layout.or(profile.layout),
+            release: self.release.with_profile(profile),
+            filter: self.filter.with_profile(profile),
+            nodump: self.nodump.or(profile.nodump),
+            output: self.output.or(profile.output),
+        }
+    }
+
+    /// Only the output's file name matters when verifying, so link and
+    /// solid settings are left at their defaults.
+    #[must_use]
+    pub fn output(&self) -> Option<OutputFormatKind> {
+        self.output.map(|output| match output {
+            OutputArg::Zip => OutputFormatKind::Zip,
+            OutputArg::Directory => OutputFormatKind::Directory(LinkMode::Copy),
+            OutputArg::SevenZip => OutputFormatKind::SevenZip { solid: true },
+        })
+    }
+}
+
 #[derive(Clone, Debug, Args)]
 pub struct CacheBuildArgs {
     #[arg(
@@ -85,18 +486,230 @@ pub struct CacheBuildArgs {
     pub source: Utf8PathBuf,
     #[arg(value_name = "out", help = "Destination directory for output ZIPs")]
     pub out: Utf8PathBuf,
+    #[arg(
+        long,
+        value_name = "version-or-sha1",
+        help = "Build this imported version instead of the latest"
+    )]
+    pub dat_version: Option<String>,
+    #[command(flatten)]
+    pub after_build: AfterBuildArgs,
     #[command(flatten)]
     pub options: BuildOptions,
 }
 
+impl CacheBuildArgs {
+    /// Fills options missing from the command line with `profile` values.
+    #[must_use]
+    pub fn with_profile(&self, profile: &Profile) -> Self {
+        Self {
+            dat: self.dat.clone(),
+            source: self.source.clone(),
+            out: self.out.clone(),
+            dat_version: self.dat_version.clone(),
+            after_build: self.after_build.clone(),
+            options: self.options.with_profile(profile),
+        }
+    }
+}
+
+/// Preferences for `--layout 1g1r`, most wanted first.
+#[derive(Clone, Debug, Default, Args)]
+pub struct ReleaseArgs {
+    #[arg(
+        long,
+        value_name = "regions",
+        value_delimiter = ',',
+        help = "Regions to prefer with --layout 1g1r, most wanted first, e.g. USA,Europe"
+    )]
+    pub region: Vec<String>,
+    #[arg(
+        long,
+        value_name = "languages",
+        value_delimiter = ',',
+        help = "Languages to prefer with --layout 1g1r, most wanted first, e.g. En,Fr"
+    )]
+    pub language: Vec<String>,
+}
+
+impl ReleaseArgs {
+    #[must_use]
+    pub fn with_profile(&self, profile: &Profile) -> Self {
+        let or_profile = |values: &Vec<String>, profile_values: &Option<Vec<String>>| {
+            if values.is_empty() {
+                profile_values.clone().unwrap_or_default()
+            } else {
+                values.clone()
+            }
+        };
+        Self {
+            region: or_profile(&self.region, &profile.region),
+            language: or_profile(&self.language, &profile.language),
+        }
+    }
+
+    #[must_use]
+    pub fn preferences(&self) -> ReleasePreferences {
+        ReleasePreferences {
+            regions: self.region.clone(),
+            languages: self.language.clone(),
+        }
+    }
+}
+
+/// Which of a DAT's games are planned and reported.
+#[derive(Clone, Debug, Default, Args)]
+pub struct FilterArgs {
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        help = "Leave out these kinds of game, e.g. clones,bios"
+    )]
+    pub exclude: Vec<GameKindArg>,
+    #[arg(
+        long,
+        value_name = "regex",
+        help = "Only games whose name matches this regular expression"
+    )]
+    pub name_regex: Option<String>,
+    #[arg(
+        long,
+        value_name = "name",
+        help = "Only games whose manufacturer contains this, ignoring case; may be repeated"
+    )]
+    pub manufacturer: Vec<String>,
+    #[arg(
+        long,
+        value_name = "range",
+        help = "Only games from these years, e.g. 1985, 1980-1989, 1980- or -1989"
+    )]
+    pub year: Option<String>,
+    #[arg(
+        long,
+        value_name = "file",
+        help = "Only games named in this file, one per line"
+    )]
+    pub games_from: Option<Utf8PathBuf>,
+}
+
+impl FilterArgs {
+    #[must_use]
+    pub fn with_profile(&self, profile: &Profile) -> Self {
+        Self {
+            exclude: if self.exclude.is_empty() {
+                profile.exclude.clone().unwrap_or_default()
+            } else {
+                self.exclude.clone()
+            },
+            name_regex: self
+                .name_regex
+                .clone()
+                .or_else(|| profile.name_regex.clone()),
+            manufacturer: if self.manufacturer.is_empty() {
+                profile.manufacturer.clone().unwrap_or_default()
+            } else {
+                self.manufacturer.clone()
+            },
+            year: self.year.clone().or_else(|| profile.year.clone()),
+            games_from: self
+                .games_from
+                .clone()
+                .or_else(|| profile.games_from.clone()),
+        }
+    }
+
+    pub fn game_filter(&self) -> mame_coalesce::Result<GameFilter> {
+        Ok(GameFilter {
+            excluded: self.exclude.iter().copied().map(Into::into).collect(),
+            name_pattern: self
+                .name_regex
+                .as_deref()
+                .map(NamePattern::new)
+                .transpose()?,
+            manufacturers: self.manufacturer.clone(),
+            years: self.year.as_deref().map(YearRange::parse).transpose()?,
+            game_names: self
+                .games_from
+                .as_deref()
+                .map(mame_coalesce::app::read_game_list)
+                .transpose()?,
+        })
+    }
+}
+
 #[derive(Clone, Debug, Args)]
 pub struct BuildOptions {
-    #[arg(long, value_enum, default_value_t = LayoutArg::ParentBundles, help = "Output ZIP layout")]
-    pub layout: LayoutArg,
-    #[arg(long, value_enum, default_value_t = CompressionArg::Deflate, help = "Output ZIP compression")]
-    pub compression: CompressionArg,
-    #[arg(long, value_enum, default_value_t = MissingArg::Warn, help = "Missing ROM policy")]
-    pub missing: MissingArg,
+    #[arg(
+        long,
+        value_enum,
+        help = "Output ZIP layout [default: the DAT's forcemerging, else parent-bundles]"
+    )]
+    pub layout: Option<LayoutArg>,
+    #[command(flatten)]
+    pub release: ReleaseArgs,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+    #[arg(
+        long,
+        value_enum,
+        help = "Output format [default: the DAT's forcepacking, else zip]"
+    )]
+    pub output: Option<OutputArg>,
+    #[arg(
+        long,
+        value_enum,
+        help = "How bare source files are placed in --output directory [default: copy]"
+    )]
+    pub link: Option<LinkArg>,
+    #[arg(
+        long,
+        value_name = "bool",
+        help = "Compress --output 7z archives as one solid block [default: true]"
+    )]
+    pub solid: Option<bool>,
+    #[arg(
+        long,
+        value_name = "dir",
+        help = "Also write each sample set the DAT's games play to <dir>/<set>.zip"
+    )]
+    pub samples: Option<Utf8PathBuf>,
+    #[arg(long, value_enum, help = "Output ZIP compression [default: deflate]")]
+    pub compression: Option<CompressionArg>,
+    #[arg(
+        long,
+        value_name = "level",
+        allow_negative_numbers = true,
+        help = "Deflate level 0-9 (10-264 use Zopfli) or zstd level -7 to 22"
+    )]
+    pub compression_level: Option<i64>,
+    #[arg(long, value_enum, help = "Missing ROM policy [default: warn]")]
+    pub missing: Option<MissingArg>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Undumped ROM policy [default: the DAT's forcenodump, else required]"
+    )]
+    pub nodump: Option<NodumpArg>,
+    #[arg(
+        long,
+        value_enum,
+        requires_if("path-glob", "prefer_glob"),
+        help = "Duplicate source selection policy [default: kind]"
+    )]
+    pub prefer: Option<PreferArg>,
+    #[arg(
+        long,
+        value_name = "glob",
+        help = "Source path glob, relative to the source root, for --prefer path-glob"
+    )]
+    pub prefer_glob: Option<String>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Reread every written output and check each entry's SHA1"
+    )]
+    pub verify: bool,
     #[arg(
         long,
         default_value_t = false,
@@ -105,11 +718,227 @@ pub struct BuildOptions {
     pub dry_run: bool,
 }
 
-#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+impl BuildOptions {
+    #[must_use]
+    pub fn with_profile(&self, profile: &Profile) -> Self {
+        Self {
+            layout: self.layout.or(profile.layout),
+            release: self.release.with_profile(profile),
+            filter: self.filter.with_profile(profile),
+            output: self.output.or(profile.output),
+            link: self.link.or(profile.link),
+            solid: self.solid.or(profile.solid),
+            samples: self.samples.clone().or_else(|| profile.samples.clone()),
+            compression: self.compression.or(profile.compression),
+            compression_level: self.compression_level.or(profile.compression_level),
+            missing: self.missing.or(profile.missing),
+            nodump: self.nodump.or(profile.nodump),
+            prefer: self.prefer.or(profile.prefer),
+            prefer_glob: self
+                .prefer_glob
+                .clone()
+                .or_else(|| profile.prefer_glob.clone()),
+            verify: self.verify || profile.verify.unwrap_or_default(),
+            dry_run: self.dry_run,
+        }
+    }
+
+    /// `None` leaves the layout to the DAT's directives.
+    #[must_use]
+    pub fn mode(&self) -> Option<BuildMode> {
+        self.layout.map(Into::into)
+    }
+
+    #[must_use]
+    pub fn nodump(&self) -> Option<NodumpPolicy> {
+        self.nodump.map(Into::into)
+    }
+
+    /// `None` leaves the output format to the DAT's directives.
+    #[must_use]
+    pub fn output(&self) -> Option<OutputFormatKind> {
+        self.output.map(|output| match output {
+            OutputArg::Zip => OutputFormatKind::Zip,
+            OutputArg::Directory => {
+                OutputFormatKind::Directory(self.link.unwrap_or_default().into())
+            }
+            OutputArg::SevenZip => OutputFormatKind::SevenZip {
+                solid: self.solid.unwrap_or(true),
+            },
+        })
+    }
+
+    pub fn compression(&self) -> mame_coalesce::Result<ZipCompression> {
+        let level = self.compression_level;
+        match self.compression.unwrap_or_default() {
+            CompressionArg::Deflate => ZipCompression::deflate(level),
+            CompressionArg::Zstd => ZipCompression::zstd(level),
+            CompressionArg::Store if level.is_none() => Ok(ZipCompression::Store),
+            CompressionArg::Store => Err(mame_coalesce::Error::Compression(
+                "--compression store does not take a level".to_owned(),
+            )),
+        }
+    }
+
+    #[must_use]
+    pub fn strict(&self) -> bool {
+        self.missing.unwrap_or_default().strict()
+    }
+
+    pub fn source_preference(&self) -> mame_coalesce::Result<SourcePreference> {
+        match (self.prefer.unwrap_or_default(), self.prefer_glob.as_deref()) {
+            (PreferArg::Kind, _) => Ok(SourcePreference::Kind),
+            (PreferArg::Zip, _) => Ok(SourcePreference::Zip),
+            (PreferArg::PathGlob, Some(pattern)) => SourcePreference::path_glob(pattern),
+            // The parser rejects `--prefer path-glob` alone; only a profile
+            // can leave the glob out.
+            (PreferArg::PathGlob, None) => Err(mame_coalesce::Error::Config(
+                "prefer = \"path-glob\" requires prefer-glob".to_owned(),
+            )),
+            (PreferArg::FewestArchives, _) => Ok(SourcePreference::FewestArchives),
+        }
+    }
+}
+
+/// Settings read from the TOML config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Cache database used when neither `--cache` nor the profile sets one.
+    pub cache: Option<Utf8PathBuf>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Config {
+    /// Reads `path`, or the default config file when it exists.
+    pub fn load(path: Option<&Utf8Path>) -> mame_coalesce::Result<Self> {
+        path.map(Utf8Path::to_path_buf)
+            .or_else(|| default_config_path().filter(|path| path.is_file()))
+            .map_or_else(|| Ok(Self::default()), |path| Self::read(&path))
+    }
+
+    fn read(path: &Utf8Path) -> mame_coalesce::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| mame_coalesce::Error::Config(format!("{path}: {error}")))?;
+        toml::from_str(&text)
+            .map_err(|error| mame_coalesce::Error::Config(format!("{path}: {error}")))
+    }
+
+    /// The named profile, or an empty one when no name is given.
+    pub fn profile(&self, name: Option<&str>) -> mame_coalesce::Result<Profile> {
+        name.map_or_else(
+            || Ok(Profile::default()),
+            |name| {
+                self.profiles
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| mame_coalesce::Error::Config(format!("no profile named {name}")))
+            },
+        )
+    }
+
+    /// The cache database from `--cache`, then the profile, then the file.
+    #[must_use]
+    pub fn cache_path(&self, cli: &Cli, profile: &Profile) -> Option<Utf8PathBuf> {
+        cli.cache()
+            .or(profile.cache.as_ref())
+            .or(self.cache.as_ref())
+            .cloned()
+    }
+}
+
+/// A `[profiles.<name>]` table; every key may be overridden on the command
+/// line.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    pub dat: Option<Utf8PathBuf>,
+    pub source: Option<Utf8PathBuf>,
+    pub out: Option<Utf8PathBuf>,
+    pub cache: Option<Utf8PathBuf>,
+    pub jobs: Option<usize>,
+    pub nested_depth: Option<usize>,
+    pub layout: Option<LayoutArg>,
+    pub region: Option<Vec<String>>,
+    pub language: Option<Vec<String>>,
+    pub exclude: Option<Vec<GameKindArg>>,
+    pub name_regex: Option<String>,
+    pub manufacturer: Option<Vec<String>>,
+    pub year: Option<String>,
+    pub games_from: Option<Utf8PathBuf>,
+    pub output: Option<OutputArg>,
+    pub link: Option<LinkArg>,
+    pub solid: Option<bool>,
+    pub samples: Option<Utf8PathBuf>,
+    pub compression: Option<CompressionArg>,
+    pub compression_level: Option<i64>,
+    pub missing: Option<MissingArg>,
+    pub nodump: Option<NodumpArg>,
+    pub prefer: Option<PreferArg>,
+    pub prefer_glob: Option<String>,
+    pub verify: Option<bool>,
+}
+
+fn default_config_path() -> Option<Utf8PathBuf> {
+    std::env::var("XDG_CONFIG_HOME")
+        .map(Utf8PathBuf::from)
+        .ok()
+        .or_else(|| {
+            std::env::var("HOME")
+                .ok()
+                .map(|home| Utf8PathBuf::from(home).join(".config"))
+        })
+        .map(|config_root| config_root.join("mame_coalesce").join("config.toml"))
+}
+
+/// A `build` argument given on the command line or by the profile.
+pub fn required(value: Option<&Utf8PathBuf>, name: &str) -> mame_coalesce::Result<Utf8PathBuf> {
+    value.cloned().ok_or_else(|| {
+        mame_coalesce::Error::Config(format!(
+            "build requires <{name}> on the command line or from --profile"
+        ))
+    })
+}
+
+#[derive(Clone, Debug, Args)]
+#[group(required = true, multiple = false)]
+pub struct FindQueryArgs {
+    #[arg(long, value_name = "hex", help = "ROM SHA1")]
+    pub sha1: Option<String>,
+    #[arg(long, value_name = "hex", help = "ROM CRC32")]
+    pub crc: Option<String>,
+    #[arg(long, value_name = "hex", help = "ROM MD5")]
+    pub md5: Option<String>,
+    #[arg(long, value_name = "glob", help = "ROM or source entry name glob")]
+    pub name: Option<String>,
+}
+
+impl FindQueryArgs {
+    pub fn rom_query(&self) -> mame_coalesce::Result<RomQuery> {
+        match (&self.sha1, &self.crc, &self.md5, &self.name) {
+            (Some(sha1), _, _, _) => RomQuery::sha1(sha1),
+            (_, Some(crc), _, _) => RomQuery::crc32(crc),
+            (_, _, Some(md5), _) => RomQuery::md5(md5),
+            (_, _, _, Some(name)) => RomQuery::name(name),
+            (None, None, None, None) => Err(mame_coalesce::Error::InvalidHash(
+                "cache find requires --sha1, --crc, --md5, or --name".to_owned(),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
 pub enum LayoutArg {
     #[default]
     ParentBundles,
     PerGame,
+    /// One output per game, without the ROMs a clone shares with its parent
+    Split,
+    /// One game per parent/clone family, chosen by --region and --language
+    #[value(name = "1g1r")]
+    #[serde(rename = "1g1r")]
+    OneGameOneRom,
 }
 
 impl From<LayoutArg> for BuildMode {
@@ -117,27 +946,82 @@ impl From<LayoutArg> for BuildMode {
         match layout {
             LayoutArg::ParentBundles => Self::ParentBundles,
             LayoutArg::PerGame => Self::PerGame,
+            LayoutArg::Split => Self::Split,
+            LayoutArg::OneGameOneRom => Self::OneGameOneRom,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, ValueEnum)]
-pub enum CompressionArg {
+#[derive(Clone, Copy, Debug, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum GameKindArg {
+    /// Games with a cloneof parent
+    Clones,
+    /// BIOS sets
+    Bios,
+    /// Device machines
+    Devices,
+    /// Mechanical machines
+    Mechanical,
+}
+
+impl From<GameKindArg> for GameKind {
+    fn from(kind: GameKindArg) -> Self {
+        match kind {
+            GameKindArg::Clones => Self::Clone,
+            GameKindArg::Bios => Self::Bios,
+            GameKindArg::Devices => Self::Device,
+            GameKindArg::Mechanical => Self::Mechanical,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputArg {
+    /// One ZIP per bundle
     #[default]
-    Deflate,
-    Store,
+    Zip,
+    /// A folder of loose ROM files per bundle
+    Directory,
+    /// One 7z archive per bundle
+    #[value(name = "7z")]
+    #[serde(rename = "7z")]
+    SevenZip,
 }
 
-impl From<CompressionArg> for ZipCompression {
-    fn from(compression: CompressionArg) -> Self {
-        match compression {
-            CompressionArg::Deflate => Self::Deflate,
-            CompressionArg::Store => Self::Store,
+#[derive(Clone, Copy, Debug, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkArg {
+    #[default]
+    Copy,
+    /// Hardlink, copying across filesystems
+    Hardlink,
+    /// Reflink where supported, otherwise copy
+    Reflink,
+}
+
+impl From<LinkArg> for LinkMode {
+    fn from(link: LinkArg) -> Self {
+        match link {
+            LinkArg::Copy => Self::Copy,
+            LinkArg::Hardlink => Self::Hardlink,
+            LinkArg::Reflink => Self::Reflink,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompressionArg {
+    #[default]
+    Deflate,
+    Store,
+    Zstd,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
 pub enum MissingArg {
     #[default]
     Warn,
@@ -153,3 +1037,56 @@ impl MissingArg {
         }
     }
 }
+
+#[derive(Clone, Copy, Debug, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum NodumpArg {
+    /// Build undumped ROMs and report them when missing
+    Required,
+    /// Leave undumped ROMs out
+    Obsolete,
+    /// Build undumped ROMs a source has, without reporting missing ones
+    Ignore,
+}
+
+impl From<NodumpArg> for NodumpPolicy {
+    fn from(nodump: NodumpArg) -> Self {
+        match nodump {
+            NodumpArg::Required => Self::Required,
+            NodumpArg::Obsolete => Self::Obsolete,
+            NodumpArg::Ignore => Self::Ignore,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreferArg {
+    /// Bare files, then ZIP entries, then other containers
+    #[default]
+    Kind,
+    /// ZIP entries first
+    Zip,
+    /// Sources matching --prefer-glob first
+    PathGlob,
+    /// Sources that supply the most ROMs first
+    FewestArchives,
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum AfterBuildArg {
+    /// Leave sources in place
+    #[default]
+    Keep,
+    /// Move consumed sources under --move-to
+    Move,
+    /// Delete consumed sources
+    Delete,
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
@@ -0,0 +1,202 @@
+use std::io::{self, Write};
+
+use mame_coalesce::domain::{
+    CacheStats, DatDiff, DatIssue, DatRomHashes, DatSummary, IssueSeverity, RomLookup,
+    SourceSummary,
+};
+use serde::Serialize;
+
+use crate::options::OutputFormat;
+
+const NOT_RECORDED: &str = "-";
+
+pub fn dats(dats: &[DatSummary], format: OutputFormat) -> mame_coalesce::Result<()> {
+    let mut stdout = io::stdout().lock();
+    match format {
+        OutputFormat::Json => json(&mut stdout, dats),
+        OutputFormat::Text => {
+            writeln!(
+                stdout,
+                "NAME\tVERSION\tSHA1\tLATEST\tGAMES\tROMS\tIMPORTED\tFILE"
+            )?;
+            for dat in dats {
+                writeln!(
+                    stdout,
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    dat.name,
+                    dat.version.as_deref().unwrap_or(NOT_RECORDED),
+                    dat.sha1.as_deref().unwrap_or(NOT_RECORDED),
+                    if dat.latest { "yes" } else { "no" },
+                    dat.games,
+                    dat.roms,
+                    dat.imported_at
+                        .map_or_else(|| NOT_RECORDED.to_owned(), |time| time.to_string()),
+                    dat.file_name.as_deref().unwrap_or(NOT_RECORDED),
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn sources(sources: &[SourceSummary], format: OutputFormat) -> mame_coalesce::Result<()> {
+    let mut stdout = io::stdout().lock();
+    match format {
+        OutputFormat::Json => json(&mut stdout, sources),
+        OutputFormat::Text => {
+            writeln!(stdout, "PATH\tROM FILES\tARCHIVES\tSCANNED")?;
+            for source in sources {
+                writeln!(
+                    stdout,
+                    "{}\t{}\t{}\t{}",
+                    source.path, source.rom_files, source.archives, source.scanned_at
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn stats(stats: &CacheStats, format: OutputFormat) -> mame_coalesce::Result<()> {
+    let mut stdout = io::stdout().lock();
+    match format {
+        OutputFormat::Json => json(&mut stdout, stats),
+        OutputFormat::Text => {
+            writeln!(
+                stdout,
+                "rom files: {} ({} matched, {} unmatched)",
+                stats.rom_files, stats.matched_rom_files, stats.unmatched_rom_files
+            )?;
+            for dat in &stats.dats {
+                writeln!(
+                    stdout,
+                    "{}: {}/{} ROMs available ({}%)",
+                    dat.name,
+                    dat.available_roms,
+                    dat.roms,
+                    dat.percent()
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn rom_lookup(lookup: &RomLookup, format: OutputFormat) -> mame_coalesce::Result<()> {
+    let mut stdout = io::stdout().lock();
+    match format {
+        OutputFormat::Json => json(&mut stdout, lookup),
+        OutputFormat::Text => {
+            writeln!(stdout, "expected by {} DAT ROMs:", lookup.expected.len())?;
+            for rom in &lookup.expected {
+                writeln!(
+                    stdout,
+                    "  {}: {}/{} {}",
+                    rom.dat_name,
+                    rom.game_name,
+                    rom.rom_name,
+                    hex::encode(rom.sha1)
+                )?;
+            }
+            writeln!(stdout, "provided by {} source files:", lookup.sources.len())?;
+            for source in &lookup.sources {
+                writeln!(stdout, "  {} {}", source.location, hex::encode(source.sha1))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn dat_diff(diff: &DatDiff, format: OutputFormat) -> mame_coalesce::Result<()> {
+    let mut stdout = io::stdout().lock();
+    match format {
+        OutputFormat::Json => json(&mut stdout, diff),
+        OutputFormat::Text => {
+            writeln!(stdout, "added {} games:", diff.added_games.len())?;
+            for game in &diff.added_games {
+                writeln!(stdout, "  {game}")?;
+            }
+            writeln!(stdout, "removed {} games:", diff.removed_games.len())?;
+            for game in &diff.removed_games {
+                writeln!(stdout, "  {game}")?;
+            }
+            writeln!(stdout, "renamed {} games:", diff.renamed_games.len())?;
+            for rename in &diff.renamed_games {
+                writeln!(stdout, "  {} -> {}", rename.from, rename.to)?;
+            }
+            writeln!(stdout, "changed {} ROMs:", diff.rom_changes.len())?;
+            for change in &diff.rom_changes {
+                writeln!(
+                    stdout,
+                    "  {}/{}: {} -> {}",
+                    change.game_name,
+                    change.rom_name,
+                    rom_hashes(change.old.as_ref()),
+                    rom_hashes(change.new.as_ref())
+                )?;
+            }
+            writeln!(stdout, "changed {} parents:", diff.parent_changes.len())?;
+            for change in &diff.parent_changes {
+                writeln!(
+                    stdout,
+                    "  {}: {} -> {}",
+                    change.game_name,
+                    change.old_parent.as_deref().unwrap_or("none"),
+                    change.new_parent.as_deref().unwrap_or("none")
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn dat_issues(issues: &[DatIssue], format: OutputFormat) -> mame_coalesce::Result<()> {
+    let mut stdout = io::stdout().lock();
+    match format {
+        OutputFormat::Json => json(&mut stdout, issues),
+        OutputFormat::Text => {
+            for issue in issues {
+                let severity = match issue.severity {
+                    IssueSeverity::Error => "error",
+                    IssueSeverity::Warning => "warning",
+                };
+                let line = issue
+                    .line
+                    .map_or_else(String::new, |line| format!("line {line}: "));
+                let element = issue.rom_name.as_ref().map_or_else(
+                    || issue.game_name.clone(),
+                    |rom_name| format!("{}/{rom_name}", issue.game_name),
+                );
+                writeln!(stdout, "{line}{severity}: {element}: {}", issue.message)?;
+            }
+            let errors = issues
+                .iter()
+                .filter(|issue| issue.severity == IssueSeverity::Error)
+                .count();
+            writeln!(
+                stdout,
+                "{errors} errors, {} warnings",
+                issues.len() - errors
+            )?;
+            Ok(())
+        }
+    }
+}
+
+fn rom_hashes(hashes: Option<&DatRomHashes>) -> String {
+    hashes.map_or_else(
+        || "absent".to_owned(),
+        |hashes| {
+            format!(
+                "size {} crc {} sha1 {}",
+                hashes.size, hashes.crc, hashes.sha1
+            )
+        },
+    )
+}
+
+fn json(writer: &mut impl Write, value: &(impl Serialize + ?Sized)) -> mame_coalesce::Result<()> {
+    serde_json::to_writer_pretty(&mut *writer, value)?;
+    writeln!(writer)?;
+    Ok(())
+}
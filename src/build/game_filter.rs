@@ -0,0 +1,152 @@
+use crate::domain::{DatGame, GameFilter, GameKind};
+
+/// The games a build keeps. A game is kept only when it passes every
+/// setting of the filter; a manufacturer or year filter leaves out games
+/// the DAT gives no manufacturer or year.
+#[must_use]
+pub fn filter_games(games: Vec<DatGame>, filter: &GameFilter) -> Vec<DatGame> {
+    games
+        .into_iter()
+        .filter(|game| keeps(filter, game))
+        .collect()
+}
+
+fn keeps(filter: &GameFilter, game: &DatGame) -> bool {
+    !filter.excluded.iter().any(|kind| is_kind(game, *kind))
+        && filter
+            .name_pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(&game.game_name))
+        && (filter.manufacturers.is_empty()
+            || game.manufacturer.as_deref().is_some_and(|manufacturer| {
+                let manufacturer = manufacturer.to_lowercase();
+                filter
+                    .manufacturers
+                    .iter()
+                    .any(|wanted| manufacturer.contains(&wanted.to_lowercase()))
+            }))
+        && filter.years.is_none_or(|years| {
+            game.year
+                .as_deref()
+                .is_some_and(|year| years.contains(year))
+        })
+        && filter
+            .game_names
+            .as_ref()
+            .is_none_or(|names| names.contains(&game.game_name))
+}
+
+const fn is_kind(game: &DatGame, kind: GameKind) -> bool {
+    match kind {
+        GameKind::Clone => game.parent_name.is_some(),
+        GameKind::Bios => game.is_bios,
+        GameKind::Device => game.is_device,
+        GameKind::Mechanical => game.is_mechanical,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{NamePattern, YearRange};
+
+    use super::*;
+
+    fn game(game_name: &str, year: &str, manufacturer: &str) -> DatGame {
+        DatGame {
+            game_name: game_name.to_owned(),
+            year: Some(year.to_owned()),
+            manufacturer: Some(manufacturer.to_owned()),
+            ..DatGame::default()
+        }
+    }
+
+    fn kept(games: &[DatGame], filter: &GameFilter) -> Vec<String> {
+        filter_games(games.to_vec(), filter)
+            .into_iter()
+            .map(|game| game.game_name)
+            .collect()
+    }
+
+    #[test]
+    fn games_are_kept_only_when_they_pass_every_filter() -> Result<(), Box<dyn std::error::Error>> {
+        let games = [
+            game("pacman", "1980", "Namco (Midway license)"),
+            DatGame {
+                parent_name: Some("pacman".to_owned()),
+                ..game("puckman", "1980", "Namco")
+            },
+            DatGame {
+                is_bios: true,
+                ..game("neogeo", "1990", "SNK")
+            },
+            DatGame {
+                is_device: true,
+                ..game("z80", "", "")
+            },
+            DatGame {
+                is_mechanical: true,
+                ..game("pinball", "197?", "Bally")
+            },
+            game("galaga", "1981", "Namco"),
+        ];
+
+        assert_eq!(kept(&games, &GameFilter::default()).len(), games.len());
+        let flags = GameFilter {
+            excluded: [
+                GameKind::Clone,
+                GameKind::Bios,
+                GameKind::Device,
+                GameKind::Mechanical,
+            ]
+            .into(),
+            ..GameFilter::default()
+        };
+        assert_eq!(kept(&games, &flags), vec!["pacman", "galaga"]);
+        let metadata = GameFilter {
+            name_pattern: Some(NamePattern::new("^(pac|puck|gal)")?),
+            manufacturers: vec!["namco (midway".to_owned()],
+            ..GameFilter::default()
+        };
+        assert_eq!(kept(&games, &metadata), vec!["pacman"]);
+        let years = GameFilter {
+            years: Some(YearRange::parse("-1980")?),
+            ..GameFilter::default()
+        };
+        assert_eq!(kept(&games, &years), vec!["pacman", "puckman", "pinball"]);
+        let listed = GameFilter {
+            game_names: Some(["galaga".to_owned(), "missing".to_owned()].into()),
+            ..GameFilter::default()
+        };
+        assert_eq!(kept(&games, &listed), vec!["galaga"]);
+        Ok(())
+    }
+
+    #[test]
+    fn year_ranges_parse_open_ends_and_match_unsure_digits()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let eighties = YearRange::parse("1980-1989")?;
+        assert!(eighties.contains("1985"));
+        assert!(eighties.contains("198?"));
+        assert!(eighties.contains("19??"));
+        assert!(!eighties.contains("197?"));
+        assert!(!eighties.contains("1990"));
+        assert!(!eighties.contains(""));
+        assert_eq!(
+            YearRange::parse("1985")?,
+            YearRange {
+                from: Some(1985),
+                to: Some(1985)
+            }
+        );
+        assert_eq!(
+            YearRange::parse("1990-")?,
+            YearRange {
+                from: Some(1990),
+                to: None
+            }
+        );
+        assert!(YearRange::parse("-").is_err());
+        assert!(YearRange::parse("eighties").is_err());
+        Ok(())
+    }
+}
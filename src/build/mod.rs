@@ -1,2 +1,4 @@
+pub mod game_filter;
+pub mod one_game_one_rom;
 pub mod planner;
 pub mod writer;
@@ -0,0 +1,316 @@
+use std::collections::BTreeMap;
+
+use crate::domain::{DatGame, FamilyChoice, RejectedGame, ReleasePreferences};
+
+/// No-Intro tags marking releases nobody wants in a 1G1R set, matched
+/// against a tag's first word so `(Beta 2)` counts as `(Beta)`.
+const EXCLUDED_TAGS: [&str; 3] = ["Beta", "Proto", "Demo"];
+
+/// Choose one game from each parent/clone family. Betas, prototypes and
+/// demos are never chosen. Among the rest, the best-ranked region wins,
+/// then the best-ranked language, then the parent, then name order.
+///
+/// Regions and languages come from a game's `<release>` elements, or when
+/// it has none, from its No-Intro name: the first tag holds the regions,
+/// as in `Game (USA, Europe)`, and a tag of language codes such as
+/// `(En,Fr,De)` holds the languages.
+#[must_use]
+pub fn choose_games(games: &[DatGame], preferences: &ReleasePreferences) -> Vec<FamilyChoice> {
+    let families = games.iter().fold(
+        BTreeMap::<&str, Vec<&DatGame>>::new(),
+        |mut families, game| {
+            families
+                .entry(game.parent_name.as_deref().unwrap_or(&game.game_name))
+                .or_default()
+                .push(game);
+            families
+        },
+    );
+
+    families
+        .into_iter()
+        .map(|(family, members)| choose_family_game(family, &members, preferences))
+        .collect()
+}
+
+fn choose_family_game(
+    family: &str,
+    members: &[&DatGame],
+    preferences: &ReleasePreferences,
+) -> FamilyChoice {
+    let mut ranked = members
+        .iter()
+        .map(|game| Rank::new(game, preferences))
+        .collect::<Vec<_>>();
+    ranked.sort_by(|left, right| left.key().cmp(&right.key()));
+
+    let Some(selected) = ranked.iter().find(|rank| rank.excluded_tag.is_none()) else {
+        return FamilyChoice {
+            family: family.to_owned(),
+            selected: None,
+            reason: "every game is a beta, prototype or demo".to_owned(),
+            rejected: ranked.iter().filter_map(Rank::excluded).collect(),
+        };
+    };
+
+    FamilyChoice {
+        family: family.to_owned(),
+        selected: Some(selected.game.game_name.clone()),
+        reason: selected.reason(preferences),
+        rejected: ranked
+            .iter()
+            .filter(|rank| rank.game.game_name != selected.game.game_name)
+            .map(|rank| rank.rejected(selected))
+            .collect(),
+    }
+}
+
+/// Where a game falls in each preference list. A missing position ranks
+/// after every listed entry.
+struct Rank<'a> {
+    game: &'a DatGame,
+    excluded_tag: Option<&'a str>,
+    region: Option<(usize, String)>,
+    language: Option<(usize, String)>,
+    regions: Vec<&'a str>,
+    languages: Vec<&'a str>,
+}
+
+impl<'a> Rank<'a> {
+    fn new(game: &'a DatGame, preferences: &ReleasePreferences) -> Self {
+        let tags = name_tags(&game.game_name).collect::<Vec<_>>();
+        let regions: Vec<&str> = if game.regions.is_empty() {
+            tags.first()
+                .map(|tag| tag.split(',').map(str::trim).collect())
+                .unwrap_or_default()
+        } else {
+            game.regions.iter().map(String::as_str).collect()
+        };
+        let languages: Vec<&str> = if game.languages.is_empty() {
+            tags.iter()
+                .map(|tag| tag.split(',').map(str::trim).collect::<Vec<_>>())
+                .find(|codes| codes.iter().all(|code| is_language_code(code)))
+                .unwrap_or_default()
+        } else {
+            game.languages.iter().map(String::as_str).collect()
+        };
+
+        Self {
+            game,
+            excluded_tag: tags.iter().copied().find(|tag| {
+                tag.split_whitespace()
+                    .next()
+                    .is_some_and(|word| EXCLUDED_TAGS.contains(&word))
+            }),
+            region: best_position(&preferences.regions, &regions),
+            language: best_position(&preferences.languages, &languages),
+            regions,
+            languages,
+        }
+    }
+
+    fn key(&self) -> (bool, usize, usize, bool, &str) {
+        (
+            self.excluded_tag.is_some(),
+            self.region.as_ref().map_or(usize::MAX, |(index, _)| *index),
+            self.language
+                .as_ref()
+                .map_or(usize::MAX, |(index, _)| *index),
+            self.game.parent_name.is_some(),
+            &self.game.game_name,
+        )
+    }
+
+    fn reason(&self, preferences: &ReleasePreferences) -> String {
+        let parts = [
+            (!preferences.regions.is_empty())
+                .then(|| describe("region", self.region.as_ref(), &self.regions)),
+            (!preferences.languages.is_empty())
+                .then(|| describe("language", self.language.as_ref(), &self.languages)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        if parts.is_empty() {
+            if self.game.parent_name.is_none() {
+                "the parent".to_owned()
+            } else {
+                "first by name".to_owned()
+            }
+        } else {
+            parts.join(", ")
+        }
+    }
+
+    /// Why the game was passed over for `selected`.
+    fn rejected(&self, selected: &Rank<'_>) -> RejectedGame {
+        self.excluded().unwrap_or_else(|| {
+            let (_, region, language, is_clone, _) = self.key();
+            let (_, selected_region, selected_language, _, _) = selected.key();
+            let reason = if region > selected_region {
+                describe("region", self.region.as_ref(), &self.regions)
+            } else if language > selected_language {
+                describe("language", self.language.as_ref(), &self.languages)
+            } else if is_clone && selected.game.parent_name.is_none() {
+                "the parent ranks the same".to_owned()
+            } else {
+                format!(
+                    "ranks the same as {}, which sorts first",
+                    selected.game.game_name
+                )
+            };
+            RejectedGame {
+                game_name: self.game.game_name.clone(),
+                reason,
+            }
+        })
+    }
+
+    /// The game is a beta, prototype or demo, which are never chosen.
+    fn excluded(&self) -> Option<RejectedGame> {
+        self.excluded_tag.map(|tag| RejectedGame {
+            game_name: self.game.game_name.clone(),
+            reason: format!("({tag}) releases are excluded"),
+        })
+    }
+}
+
+fn describe(kind: &str, position: Option<&(usize, String)>, values: &[&str]) -> String {
+    match (position, values.is_empty()) {
+        (Some((index, value)), _) => format!("{kind} {value} is priority {}", index + 1),
+        (None, true) => format!("no {kind} is known"),
+        (None, false) => format!("{kind} {} is not wanted", values.join(", ")),
+    }
+}
+
+/// The earliest preference matching any of `values`, ignoring case, and
+/// the value it matched.
+fn best_position(preferences: &[String], values: &[&str]) -> Option<(usize, String)> {
+    preferences
+        .iter()
+        .enumerate()
+        .find_map(|(index, preference)| {
+            values
+                .iter()
+                .any(|value| value.eq_ignore_ascii_case(preference))
+                .then(|| (index, preference.clone()))
+        })
+}
+
+/// Parenthesized tags in a No-Intro name, such as `USA, Europe` and `Beta`
+/// in `Game (USA, Europe) (Beta)`.
+fn name_tags(name: &str) -> impl Iterator<Item = &str> {
+    name.split('(')
+        .skip(1)
+        .filter_map(|rest| rest.split_once(')').map(|(tag, _)| tag.trim()))
+}
+
+/// Codes like `En`, `Fr` or `Zh-Hant`.
+fn is_language_code(code: &str) -> bool {
+    let (language, _) = code.split_once('-').unwrap_or((code, ""));
+    let mut chars = language.chars();
+    matches!(
+        (chars.next(), chars.next(), chars.next()),
+        (Some(first), Some(second), None) if first.is_ascii_uppercase() && second.is_ascii_lowercase()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(game_name: &str, parent_name: Option<&str>) -> DatGame {
+        DatGame {
+            game_name: game_name.to_owned(),
+            parent_name: parent_name.map(str::to_owned),
+            ..DatGame::default()
+        }
+    }
+
+    fn preferences(regions: &[&str], languages: &[&str]) -> ReleasePreferences {
+        ReleasePreferences {
+            regions: regions.iter().map(|region| (*region).to_owned()).collect(),
+            languages: languages
+                .iter()
+                .map(|language| (*language).to_owned())
+                .collect(),
+        }
+    }
+
+    fn rejected(choice: &FamilyChoice) -> Vec<(&str, &str)> {
+        choice
+            .rejected
+            .iter()
+            .map(|rejected| (rejected.game_name.as_str(), rejected.reason.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn one_game_is_chosen_per_family_by_region_then_language() {
+        let parent = "Game (Japan)";
+        let games = [
+            game(parent, None),
+            game("Game (USA) (Beta)", Some(parent)),
+            game("Game (Europe) (En,Fr,De)", Some(parent)),
+            game("Game (Europe) (Fr,De)", Some(parent)),
+            DatGame {
+                regions: vec!["USA".to_owned()],
+                languages: vec!["en".to_owned()],
+                ..game("Game (Rev 1)", Some(parent))
+            },
+            game("Other (Proto)", None),
+        ];
+
+        let choices = choose_games(&games, &preferences(&["usa", "Europe"], &["En"]));
+
+        assert_eq!(choices.len(), 2);
+        assert_eq!(choices[0].family, parent);
+        assert_eq!(choices[0].selected.as_deref(), Some("Game (Rev 1)"));
+        assert_eq!(
+            choices[0].reason,
+            "region usa is priority 1, language En is priority 1"
+        );
+        assert_eq!(
+            rejected(&choices[0]),
+            vec![
+                ("Game (Europe) (En,Fr,De)", "region Europe is priority 2"),
+                ("Game (Europe) (Fr,De)", "region Europe is priority 2"),
+                ("Game (Japan)", "region Japan is not wanted"),
+                ("Game (USA) (Beta)", "(Beta) releases are excluded"),
+            ]
+        );
+        assert_eq!(choices[1].family, "Other (Proto)");
+        assert_eq!(choices[1].selected, None);
+        assert_eq!(
+            rejected(&choices[1]),
+            vec![("Other (Proto)", "(Proto) releases are excluded")]
+        );
+
+        let europe = choose_games(&games[..4], &preferences(&["Europe"], &["En"]));
+        assert_eq!(
+            europe[0].selected.as_deref(),
+            Some("Game (Europe) (En,Fr,De)")
+        );
+        assert_eq!(
+            rejected(&europe[0])[0],
+            ("Game (Europe) (Fr,De)", "language Fr, De is not wanted")
+        );
+    }
+
+    #[test]
+    fn without_preferences_the_parent_is_chosen() {
+        let games = [
+            game("Game (USA)", None),
+            game("Game (Europe)", Some("Game (USA)")),
+        ];
+
+        let choices = choose_games(&games, &ReleasePreferences::default());
+
+        assert_eq!(choices[0].selected.as_deref(), Some("Game (USA)"));
+        assert_eq!(choices[0].reason, "the parent");
+        assert_eq!(
+            rejected(&choices[0]),
+            vec![("Game (Europe)", "the parent ranks the same")]
+        );
+    }
+}
@@ -1,11 +1,12 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use camino::Utf8Path;
 
 use crate::{
     domain::{
-        BuildMode, BuildPlan, BuildReport, BuildRequest, DatRom, DuplicateMatch, MissingRom,
-        SourceFile, ZipEntrySpec, ZipSpec,
+        BuildMode, BuildPlan, BuildReport, BuildRequest, DatRom, DatSample, DuplicateMatch,
+        MissingRom, MissingSample, NodumpPolicy, SourceFile, SourceKind, SourcePreference,
+        ZipEntrySpec, ZipSpec,
     },
     hashes::Sha1Digest,
 };
@@ -16,10 +17,22 @@ pub fn plan_build(
     source_files: &[SourceFile],
     request: &BuildRequest,
 ) -> BuildPlan {
-    let source_by_sha1 = sources_for_root(source_files, &request.source_root);
-    let resolutions = selected_dat_roms(dat_roms, &request.dat_name)
+    let selected_roms = selected_dat_roms(dat_roms, request);
+    let mut source_by_sha1 = sources_for_roots(source_files, &request.source_roots);
+    let ranking = source_ranking(
+        &request.prefer,
+        &request.source_roots,
+        &selected_roms,
+        &source_by_sha1,
+    );
+    rank_candidates(&mut source_by_sha1, ranking.as_ref());
+    let resolutions = selected_roms
         .into_iter()
         .map(|rom| resolve_rom(rom, &source_by_sha1))
+        .filter(|resolution| {
+            request.nodump != NodumpPolicy::Ignore
+                || !matches!(resolution, RomResolution::Missing(rom) if rom.nodump)
+        })
         .collect::<Vec<_>>();
     let report = build_report(&resolutions, request.strict);
 
@@ -41,6 +54,117 @@ pub fn plan_build(
     }
 }
 
+/// File extensions MAME loads samples from.
+const SAMPLE_EXTENSIONS: [&str; 2] = ["wav", "flac"];
+
+/// Plan one zip per sample set. Samples have no hashes, so a source file is
+/// a sample when it is named after it, with or without a sample extension,
+/// and sits in an archive or directory named after its set. Each sample set
+/// is planned once however many games share it.
+#[must_use]
+pub fn plan_samples(
+    dat_samples: &[DatSample],
+    source_files: &[SourceFile],
+    source_roots: &[String],
+    dry_run: bool,
+) -> BuildPlan {
+    let mut sources_by_set = BTreeMap::<&str, Vec<&SourceFile>>::new();
+    for source in source_files.iter().filter(|source| {
+        source_roots
+            .iter()
+            .any(|source_root| source_in_root(source, source_root))
+    }) {
+        if let Some(container) = sample_container(source) {
+            sources_by_set.entry(container).or_default().push(source);
+        }
+    }
+
+    let wanted = dat_samples
+        .iter()
+        .map(|sample| (sample.sample_set.as_str(), sample.sample_name.as_str()))
+        .collect::<BTreeSet<_>>();
+    let mut entries_by_set = BTreeMap::<&str, Vec<ZipEntrySpec>>::new();
+    let mut report = BuildReport::default();
+    for (sample_set, sample_name) in wanted {
+        let found = sources_by_set
+            .get(sample_set)
+            .into_iter()
+            .flatten()
+            .filter_map(|source| {
+                let file_name = source_file_name(source)?;
+                is_sample_file(file_name, sample_name).then_some((*source, file_name))
+            })
+            .min_by_key(|(source, _)| (source.kind.priority(), source.display_name()));
+        if let Some((source, file_name)) = found {
+            report.matched_samples += 1;
+            entries_by_set
+                .entry(sample_set)
+                .or_default()
+                .push(ZipEntrySpec {
+                    output_name: sample_output_name(file_name, sample_name),
+                    source: source.clone(),
+                });
+        } else {
+            report.missing_samples.push(MissingSample {
+                sample_set: sample_set.to_owned(),
+                sample_name: sample_name.to_owned(),
+            });
+        }
+    }
+
+    BuildPlan {
+        zips: entries_by_set
+            .into_iter()
+            .map(|(sample_set, entries)| ZipSpec {
+                file_name: format!("{sample_set}.zip"),
+                entries,
+            })
+            .collect(),
+        report,
+        dry_run,
+    }
+}
+
+/// The name of the archive or directory holding a source file, without
+/// its extension.
+fn sample_container(source: &SourceFile) -> Option<&str> {
+    let container = match (&source.entry_name, source.archive_chain.last()) {
+        (None, _) => Utf8Path::new(&source.canonical_path)
+            .parent()?
+            .file_name()?,
+        (Some(_), Some(nested_archive)) => Utf8Path::new(nested_archive).file_stem()?,
+        (Some(_), None) => Utf8Path::new(&source.canonical_path).file_stem()?,
+    };
+    Some(container.strip_suffix(".tar").unwrap_or(container))
+}
+
+fn source_file_name(source: &SourceFile) -> Option<&str> {
+    Utf8Path::new(source.entry_name.as_ref().unwrap_or(&source.canonical_path)).file_name()
+}
+
+fn is_sample_file(file_name: &str, sample_name: &str) -> bool {
+    file_name == sample_name
+        || Utf8Path::new(file_name)
+            .extension()
+            .is_some_and(|extension| {
+                SAMPLE_EXTENSIONS
+                    .iter()
+                    .any(|sample_extension| extension.eq_ignore_ascii_case(sample_extension))
+            })
+            && Utf8Path::new(file_name).file_stem() == Some(sample_name)
+}
+
+/// The sample's own name with its file's extension in lowercase, which
+/// is how MAME looks it up on case-sensitive filesystems.
+fn sample_output_name(file_name: &str, sample_name: &str) -> String {
+    match Utf8Path::new(file_name).extension() {
+        Some(extension) if file_name != sample_name => {
+            format!("{sample_name}.{}", extension.to_ascii_lowercase())
+        }
+        _ => sample_name.to_owned(),
+    }
+}
+
 enum RomResolution<'a> {
     Matched(MatchedRom<'a>),
     Missing(&'a DatRom),
@@ -52,38 +176,146 @@ struct MatchedRom<'a> {
     candidates: &'a [&'a SourceFile],
 }
 
-fn selected_dat_roms<'a>(dat_roms: &'a [DatRom], dat_name: &str) -> Vec<&'a DatRom> {
+fn selected_dat_roms<'a>(dat_roms: &'a [DatRom], request: &BuildRequest) -> Vec<&'a DatRom> {
     let mut selected = dat_roms
         .iter()
-        .filter(|rom| rom.dat_name == dat_name)
+        .filter(|rom| rom.dat_name == request.dat_name)
+        .filter(|rom| !(rom.nodump && request.nodump == NodumpPolicy::Obsolete))
         .collect::<Vec<_>>();
     selected.sort();
     selected
 }
 
-fn sources_for_root<'a>(
+fn sources_for_roots<'a>(
     source_files: &'a [SourceFile],
-    source_root: &str,
+    source_roots: &[String],
 ) -> BTreeMap<Sha1Digest, Vec<&'a SourceFile>> {
     let mut source_by_sha1 = BTreeMap::<Sha1Digest, Vec<&SourceFile>>::new();
-    for source in source_files
-        .iter()
-        .filter(|source| source_in_root(source, source_root))
-    {
+    for source in source_files.iter().filter(|source| {
+        source_roots
+            .iter()
+            .any(|source_root| source_in_root(source, source_root))
+    }) {
         source_by_sha1.entry(source.sha1).or_default().push(source);
     }
 
-    for candidates in source_by_sha1.values_mut() {
+    source_by_sha1
+}
+
+/// Orders the candidates for a ROM; the lowest-ranked candidate is used.
+/// Ties fall back to source kind priority and then path order, so every
+/// policy stays deterministic.
+trait SourceRanking {
+    fn rank(&self, source: &SourceFile) -> usize;
+}
+
+struct KindPriority;
+
+impl SourceRanking for KindPriority {
+    fn rank(&self, source: &SourceFile) -> usize {
+        usize::from(source.kind.priority())
+    }
+}
+
+struct PreferZip;
+
+impl SourceRanking for PreferZip {
+    fn rank(&self, source: &SourceFile) -> usize {
+        usize::from(source.kind != SourceKind::ZipEntry)
+    }
+}
+
+struct PreferPathGlob<'a> {
+    pattern: &'a glob::Pattern,
+    source_roots: &'a [String],
+}
+
+impl SourceRanking for PreferPathGlob<'_> {
+    fn rank(&self, source: &SourceFile) -> usize {
+        let path = Utf8Path::new(&source.canonical_path);
+        let relative_path = self
+            .source_roots
+            .iter()
+            .find_map(|source_root| path.strip_prefix(source_root).ok())
+            .unwrap_or(path);
+        usize::from(!self.pattern.matches(relative_path.as_str()))
+    }
+}
+
+/// Ranks each archive by how many of the build's ROMs it cannot supply.
+/// Bare files open no archive, so they supply nothing here and only win
+/// when no archive holds the ROM.
+struct PreferFewestArchives<'a> {
+    wanted_roms: usize,
+    roms_by_path: BTreeMap<&'a str, usize>,
+}
+
+impl SourceRanking for PreferFewestArchives<'_> {
+    fn rank(&self, source: &SourceFile) -> usize {
+        let supplied = self
+            .roms_by_path
+            .get(source.canonical_path.as_str())
+            .copied()
+            .unwrap_or_default();
+        self.wanted_roms.saturating_sub(supplied)
+    }
+}
+
+fn source_ranking<'a>(
+    preference: &'a SourcePreference,
+    source_roots: &'a [String],
+    selected_roms: &[&DatRom],
+    source_index: &BTreeMap<Sha1Digest, Vec<&'a SourceFile>>,
+) -> Box<dyn SourceRanking + 'a> {
+    match preference {
+        SourcePreference::Kind => Box::new(KindPriority),
+        SourcePreference::Zip => Box::new(PreferZip),
+        SourcePreference::PathGlob(pattern) => Box::new(PreferPathGlob {
+            pattern,
+            source_roots,
+        }),
+        SourcePreference::FewestArchives => {
+            let wanted_sha1s = selected_roms
+                .iter()
+                .map(|rom| rom.sha1)
+                .collect::<BTreeSet<_>>();
+            let roms_by_path = wanted_sha1s
+                .iter()
+                .filter_map(|sha1| source_index.get(sha1))
+                .flat_map(|candidates| {
+                    candidates
+                        .iter()
+                        .filter(|source| source.kind != SourceKind::BareFile)
+                        .map(|source| source.canonical_path.as_str())
+                        .collect::<BTreeSet<_>>()
+                })
+                .fold(BTreeMap::new(), |mut roms_by_path, path| {
+                    *roms_by_path.entry(path).or_default() += 1;
+                    roms_by_path
+                });
+            Box::new(PreferFewestArchives {
+                wanted_roms: wanted_sha1s.len(),
+                roms_by_path,
+            })
+        }
+    }
+}
+
+fn rank_candidates(
+    source_index: &mut BTreeMap<Sha1Digest, Vec<&SourceFile>>,
+    ranking: &dyn SourceRanking,
+) {
+    for candidates in source_index.values_mut() {
         candidates.sort_by(|left, right| {
-            left.kind
-                .priority()
-                .cmp(&right.kind.priority())
+            ranking
+                .rank(left)
+                .cmp(&ranking.rank(right))
+                .then_with(|| left.kind.priority().cmp(&right.kind.priority()))
                 .then_with(|| left.canonical_path.cmp(&right.canonical_path))
                 .then_with(|| left.entry_name.cmp(&right.entry_name))
+                .then_with(|| left.archive_chain.cmp(&right.archive_chain))
         });
     }
-
-    source_by_sha1
 }
 
 fn resolve_rom<'a>(
@@ -174,7 +406,7 @@ fn source_in_root(source: &SourceFile, source_root: &str) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::{BuildMode, SourceKind};
+    use crate::domain::{BuildMode, SourceKind, SourcePreference};
     use proptest::prelude::*;
 
     fn digest(value: &str) -> crate::hashes::Sha1Digest {
@@ -184,8 +416,10 @@ mod tests {
     fn request(mode: BuildMode) -> BuildRequest {
         BuildRequest {
             dat_name: "dat-a".to_owned(),
-            source_root: "/src-a".to_owned(),
+            source_roots: vec!["/src-a".to_owned()],
             mode,
+            nodump: NodumpPolicy::Required,
+            prefer: SourcePreference::Kind,
             dry_run: false,
             strict: false,
         }
@@ -198,6 +432,8 @@ mod tests {
             parent_name: parent_name.map(str::to_owned),
             rom_name: rom_name.to_owned(),
             sha1: digest(sha1),
+            nodump: false,
+            merged: false,
         }
     }
 
@@ -212,6 +448,7 @@ mod tests {
             source_root: root.to_owned(),
             canonical_path: path.to_owned(),
             entry_name: entry_name.map(str::to_owned),
+            archive_chain: Vec::new(),
             sha1: digest(sha1),
             kind,
         }
@@ -240,6 +477,41 @@ mod tests {
         assert!(plan.writes_files());
     }
 
+    #[test]
+    fn nodump_policy_decides_whether_undumped_roms_are_built_or_missing() {
+        let undumped = |rom_name: &str, sha1: &str| DatRom {
+            nodump: true,
+            merged: false,
+            ..rom("game", None, rom_name, sha1)
+        };
+        let dat_roms = [
+            undumped("present.rom", "sha1-present"),
+            undumped("missing.rom", "sha1-missing"),
+        ];
+        let source_files = [source(
+            "/src-a",
+            "/src-a/present.rom",
+            None,
+            "sha1-present",
+            SourceKind::BareFile,
+        )];
+        let plan_with = |nodump| {
+            let plan = plan_build(
+                &dat_roms,
+                &source_files,
+                &BuildRequest {
+                    nodump,
+                    ..request(BuildMode::ParentBundles)
+                },
+            );
+            (plan.report.matched_roms, plan.report.missing_roms.len())
+        };
+
+        assert_eq!(plan_with(NodumpPolicy::Required), (1, 1));
+        assert_eq!(plan_with(NodumpPolicy::Ignore), (1, 0));
+        assert_eq!(plan_with(NodumpPolicy::Obsolete), (0, 0));
+    }
+
     #[test]
     fn no_selected_dat_rows_produces_empty_successful_plan() {
         let dat_roms = [DatRom {
@@ -248,6 +520,8 @@ mod tests {
             parent_name: None,
             rom_name: "game.rom".to_owned(),
             sha1: digest("sha1"),
+            nodump: false,
+            merged: false,
         }];
 
         let plan = plan_build(&dat_roms, &[], &request(BuildMode::ParentBundles));
@@ -379,6 +653,170 @@ mod tests {
         );
     }
 
+    fn plan_with_preference(
+        dat_roms: &[DatRom],
+        source_files: &[SourceFile],
+        prefer: SourcePreference,
+    ) -> BuildPlan {
+        let mut build_request = request(BuildMode::ParentBundles);
+        build_request.prefer = prefer;
+        plan_build(dat_roms, source_files, &build_request)
+    }
+
+    fn selected_paths(plan: &BuildPlan) -> Vec<&str> {
+        plan.zips
+            .iter()
+            .flat_map(|zip| &zip.entries)
+            .map(|entry| entry.source.canonical_path.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn prefer_zip_ranks_zip_entries_before_bare_files() {
+        let dat_roms = [rom("parent", None, "dup.rom", "sha1-dup")];
+        let source_files = [
+            source(
+                "/src-a",
+                "/src-a/dup.rom",
+                None,
+                "sha1-dup",
+                SourceKind::BareFile,
+            ),
+            source(
+                "/src-a",
+                "/src-a/b.zip",
+                Some("dup.rom"),
+                "sha1-dup",
+                SourceKind::ZipEntry,
+            ),
+            source(
+                "/src-a",
+                "/src-a/a.7z",
+                Some("dup.rom"),
+                "sha1-dup",
+                SourceKind::ArchiveEntry,
+            ),
+        ];
+
+        let plan = plan_with_preference(&dat_roms, &source_files, SourcePreference::Zip);
+
+        assert_eq!(selected_paths(&plan), vec!["/src-a/b.zip"]);
+        assert_eq!(
+            plan.report.duplicate_matches[0]
+                .candidates
+                .iter()
+                .map(|candidate| candidate.canonical_path.as_str())
+                .collect::<Vec<_>>(),
+            vec!["/src-a/b.zip", "/src-a/dup.rom", "/src-a/a.7z"]
+        );
+    }
+
+    #[test]
+    fn prefer_path_glob_ranks_matching_sources_first() -> Result<(), Box<dyn std::error::Error>> {
+        let dat_roms = [rom("parent", None, "dup.rom", "sha1-dup")];
+        let source_files = [
+            source(
+                "/src-a",
+                "/src-a/a/dup.rom",
+                None,
+                "sha1-dup",
+                SourceKind::BareFile,
+            ),
+            source(
+                "/src-a",
+                "/src-a/trusted/set.7z",
+                Some("dup.rom"),
+                "sha1-dup",
+                SourceKind::ArchiveEntry,
+            ),
+        ];
+
+        let plan = plan_with_preference(
+            &dat_roms,
+            &source_files,
+            SourcePreference::path_glob("trusted/**")?,
+        );
+
+        assert_eq!(selected_paths(&plan), vec!["/src-a/trusted/set.7z"]);
+        Ok(())
+    }
+
+    #[test]
+    fn prefer_fewest_archives_concentrates_on_covering_sources() {
+        let dat_roms = [
+            rom("parent", None, "a.rom", "sha1-a"),
+            rom("parent", None, "b.rom", "sha1-b"),
+        ];
+        let source_files = [
+            source(
+                "/src-a",
+                "/src-a/a.rom",
+                None,
+                "sha1-a",
+                SourceKind::BareFile,
+            ),
+            source(
+                "/src-a",
+                "/src-a/b.rom",
+                None,
+                "sha1-b",
+                SourceKind::BareFile,
+            ),
+            source(
+                "/src-a",
+                "/src-a/set.7z",
+                Some("a.rom"),
+                "sha1-a",
+                SourceKind::ArchiveEntry,
+            ),
+            source(
+                "/src-a",
+                "/src-a/set.7z",
+                Some("b.rom"),
+                "sha1-b",
+                SourceKind::ArchiveEntry,
+            ),
+        ];
+
+        let default_plan = plan_with_preference(&dat_roms, &source_files, SourcePreference::Kind);
+        let fewest_plan =
+            plan_with_preference(&dat_roms, &source_files, SourcePreference::FewestArchives);
+
+        assert_eq!(
+            selected_paths(&default_plan),
+            vec!["/src-a/a.rom", "/src-a/b.rom"]
+        );
+        assert_eq!(
+            selected_paths(&fewest_plan),
+            vec!["/src-a/set.7z", "/src-a/set.7z"]
+        );
+    }
+
+    #[test]
+    fn prefer_fewest_archives_does_not_count_bare_files_as_archives() {
+        let dat_roms = [rom("parent", None, "a.rom", "sha1-a")];
+        let source_files = [
+            source(
+                "/src-a",
+                "/src-a/a.rom",
+                None,
+                "sha1-a",
+                SourceKind::BareFile,
+            ),
+            source(
+                "/src-a",
+                "/src-a/set.zip",
+                Some("a.rom"),
+                "sha1-a",
+                SourceKind::ZipEntry,
+            ),
+        ];
+
+        let plan = plan_with_preference(&dat_roms, &source_files, SourcePreference::FewestArchives);
+
+        assert_eq!(selected_paths(&plan), vec!["/src-a/set.zip"]);
+    }
+
     #[test]
     fn duplicate_matches_use_full_source_priority_order() {
         let dat_roms = [rom("parent", None, "dup.rom", "sha1-dup")];
@@ -505,6 +943,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn build_planning_matches_sources_from_every_listed_root() {
+        let dat_roms = [
+            rom("parent", None, "a.rom", "sha1-a"),
+            rom("parent", None, "b.rom", "sha1-b"),
+        ];
+        let source_files = [
+            source(
+                "/src-a",
+                "/src-a/a.rom",
+                None,
+                "sha1-a",
+                SourceKind::BareFile,
+            ),
+            source(
+                "/src-b",
+                "/src-b/b.rom",
+                None,
+                "sha1-b",
+                SourceKind::BareFile,
+            ),
+        ];
+        let request = BuildRequest {
+            source_roots: vec!["/src-a".to_owned(), "/src-b".to_owned()],
+            ..request(BuildMode::ParentBundles)
+        };
+
+        let plan = plan_build(&dat_roms, &source_files, &request);
+
+        assert_eq!(plan.report.matched_roms, 2);
+        assert!(plan.report.missing_roms.is_empty());
+    }
+
     #[test]
     fn parent_bundle_mode_groups_clones_under_parent_zip() {
         let dat_roms = [
@@ -574,6 +1045,8 @@ mod tests {
                 parent_name: None,
                 rom_name: "shared.rom".to_owned(),
                 sha1: digest("sha1-a"),
+                nodump: false,
+                merged: false,
             },
             DatRom {
                 dat_name: "dat-b".to_owned(),
@@ -581,6 +1054,8 @@ mod tests {
                 parent_name: None,
                 rom_name: "shared.rom".to_owned(),
                 sha1: digest("sha1-b"),
+                nodump: false,
+                merged: false,
             },
         ];
         let source_files = [source(
@@ -607,6 +1082,8 @@ mod tests {
                 parent_name: None,
                 rom_name: "a.rom".to_owned(),
                 sha1: digest("sha1-shared"),
+                nodump: false,
+                merged: false,
             },
             DatRom {
                 dat_name: "dat-b".to_owned(),
@@ -614,6 +1091,8 @@ mod tests {
                 parent_name: None,
                 rom_name: "b.rom".to_owned(),
                 sha1: digest("sha1-shared"),
+                nodump: false,
+                merged: false,
             },
         ];
         let source_files = [source(
@@ -686,6 +1165,82 @@ mod tests {
         assert_eq!(first_plan, second_plan);
     }
 
+    #[test]
+    fn samples_are_matched_by_name_within_their_sample_set() {
+        let sample = |game_name: &str, sample_set: &str, sample_name: &str| DatSample {
+            game_name: game_name.to_owned(),
+            sample_set: sample_set.to_owned(),
+            sample_name: sample_name.to_owned(),
+        };
+        let dat_samples = [
+            sample("galaga", "galaga", "bang"),
+            sample("galaga", "galaga", "shot"),
+            sample("gallag", "galaga", "bang"),
+            sample("invaders", "invaders", "ufo"),
+        ];
+        let source_files = [
+            source(
+                "/src-a",
+                "/src-a/galaga.zip",
+                Some("BANG.wav"),
+                "sha1-wrong-case",
+                SourceKind::ZipEntry,
+            ),
+            source(
+                "/src-a",
+                "/src-a/galaga.zip",
+                Some("bang.WAV"),
+                "sha1-bang",
+                SourceKind::ZipEntry,
+            ),
+            source(
+                "/src-a",
+                "/src-a/samples/galaga/shot.flac",
+                None,
+                "sha1-shot",
+                SourceKind::BareFile,
+            ),
+            source(
+                "/src-a",
+                "/src-a/other.zip",
+                Some("ufo.wav"),
+                "sha1-ufo",
+                SourceKind::ZipEntry,
+            ),
+        ];
+
+        let plan = plan_samples(&dat_samples, &source_files, &["/src-a".to_owned()], false);
+
+        assert_eq!(plan.report.matched_samples, 2);
+        assert_eq!(
+            plan.report.missing_samples,
+            vec![MissingSample {
+                sample_set: "invaders".to_owned(),
+                sample_name: "ufo".to_owned(),
+            }]
+        );
+        assert_eq!(plan.report.exit_code, 0);
+        assert_eq!(
+            plan.zips
+                .iter()
+                .map(|zip| (
+                    zip.file_name.as_str(),
+                    zip.entries
+                        .iter()
+                        .map(|entry| (entry.output_name.as_str(), entry.source.sha1))
+                        .collect::<Vec<_>>()
+                ))
+                .collect::<Vec<_>>(),
+            vec![(
+                "galaga.zip",
+                vec![
+                    ("bang.wav", digest("sha1-bang")),
+                    ("shot.flac", digest("sha1-shot"))
+                ]
+            )]
+        );
+    }
+
     proptest! {
         #[test]
         fn planner_output_is_stable_under_reversed_equivalent_rows(reverse in any::<bool>()) {
@@ -1,53 +1,520 @@
 use std::{
-    collections::BTreeSet,
-    fs::{File, OpenOptions, create_dir_all},
-    io::{BufReader, BufWriter, Read, Seek, Write},
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fs::{File, OpenOptions, create_dir_all, hard_link, remove_dir, remove_file, rename},
+    io::{BufReader, BufWriter, Cursor, Read, Seek, Write},
     path::{Component, Path, PathBuf},
 };
 
 use camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, warn};
+use walkdir::WalkDir;
 use zip::{ZipWriter, result::ZipError, write::SimpleFileOptions};
 
-use crate::domain::{BuildPlan, SourceFile, SourceKind, ZipCompression, ZipEntrySpec};
+use crate::{
+    archive::{ArchiveKind, TAR_HEADER_LEN, with_spooled_file},
+    domain::{
+        BuildPlan, LinkMode, OutputFormat, SourceFile, SourceKind, ZipCompression, ZipEntrySpec,
+        ZipSpec,
+    },
+    hashes::{self, Sha1Digest},
+};
+
+/// An output zip or bundle directory produced by a build.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WrittenOutput {
+    pub path: Utf8PathBuf,
+    /// SHA1 of a written zip; directories are not hashed as a whole.
+    pub sha1: Option<Sha1Digest>,
+    /// The destination already held identical contents, which were kept.
+    pub unchanged: bool,
+}
+
+/// Where copied ROM data goes: an entry in an output zip, or a loose file.
+trait EntrySink {
+    fn start_entry(&mut self, name: &str) -> crate::Result<&mut dyn Write>;
+}
+
+struct ZipSink {
+    writer: ZipWriter<BufWriter<File>>,
+    options: SimpleFileOptions,
+}
+
+impl EntrySink for ZipSink {
+    fn start_entry(&mut self, name: &str) -> crate::Result<&mut dyn Write> {
+        self.writer.start_file(name, self.options)?;
+        Ok(&mut self.writer)
+    }
+}
+
+/// A loose file holds exactly one entry, already named by its path.
+impl EntrySink for BufWriter<File> {
+    fn start_entry(&mut self, _name: &str) -> crate::Result<&mut dyn Write> {
+        Ok(self)
+    }
+}
+
+/// One entry buffered for an archive that is built in memory.
+impl EntrySink for Vec<u8> {
+    fn start_entry(&mut self, _name: &str) -> crate::Result<&mut dyn Write> {
+        Ok(self)
+    }
+}
 
 #[cfg(test)]
 pub fn write_plan(plan: &BuildPlan, destination: &Utf8Path) -> crate::Result<Vec<Utf8PathBuf>> {
-    write_plan_with_compression(plan, destination, ZipCompression::Deflate)
+    write_plan_with_compression(
+        plan,
+        destination,
+        ZipCompression::Deflate(None),
+        hash_existing_output,
+    )
+    .map(|written_zips| written_zips.into_iter().map(|zip| zip.path).collect())
 }
 
+/// Write every zip in `plan`. Each zip is written beside its destination
+/// first, and an existing output whose SHA1 (as reported by `existing_sha1`)
+/// matches is left untouched.
 pub fn write_plan_with_compression(
     plan: &BuildPlan,
     destination: &Utf8Path,
     compression: ZipCompression,
-) -> crate::Result<Vec<Utf8PathBuf>> {
+    existing_sha1: impl Fn(&Utf8Path) -> crate::Result<Sha1Digest>,
+) -> crate::Result<Vec<WrittenOutput>> {
+    let options = file_options(compression);
+    write_archives(
+        plan,
+        destination,
+        OutputFormat::Zip,
+        existing_sha1,
+        |zip_spec, partial_path| write_zip(zip_spec, partial_path, options),
+    )
+}
+
+/// Write every bundle in `plan` as a 7z archive, replacing outputs the same
+/// way as [`write_plan_with_compression`]. `r7z` builds archives in memory,
+/// so each bundle's ROMs are read in full before it is written.
+pub fn write_plan_as_7z(
+    plan: &BuildPlan,
+    destination: &Utf8Path,
+    solid: bool,
+    existing_sha1: impl Fn(&Utf8Path) -> crate::Result<Sha1Digest>,
+) -> crate::Result<Vec<WrittenOutput>> {
+    write_archives(
+        plan,
+        destination,
+        OutputFormat::SevenZip { solid },
+        existing_sha1,
+        |zip_spec, partial_path| write_7z(zip_spec, partial_path, solid),
+    )
+}
+
+fn write_archives(
+    plan: &BuildPlan,
+    destination: &Utf8Path,
+    output: OutputFormat,
+    existing_sha1: impl Fn(&Utf8Path) -> crate::Result<Sha1Digest>,
+    write_partial: impl Fn(&ZipSpec, &Utf8Path) -> crate::Result<Sha1Digest>,
+) -> crate::Result<Vec<WrittenOutput>> {
     if !plan.writes_files() {
         return Ok(Vec::new());
     }
 
     validate_plan_paths(plan)?;
-    let options = file_options(compression);
     create_dir_all(destination)?;
-    let mut written_paths = Vec::with_capacity(plan.zips.len());
-    plan.zips.iter().try_for_each(|zip_spec| {
-        let zip_path = destination.join(&zip_spec.file_name);
-        let mut writer = open_destination_zip(&zip_path)?;
-        zip_spec.entries.iter().try_for_each(|entry| {
-            write_entry(entry, &mut writer, options)?;
-            Ok::<_, crate::Error>(())
-        })?;
-        writer.finish()?;
-        written_paths.push(zip_path);
-        Ok::<_, crate::Error>(())
+    plan.zips
+        .iter()
+        .map(|zip_spec| {
+            let file_name = output.file_name(zip_spec);
+            let output_path = destination.join(&file_name);
+            let partial_path = destination.join(format!(".{file_name}.partial"));
+            let written = write_partial(zip_spec, &partial_path)
+                .and_then(|sha1| replace_output(&partial_path, &output_path, sha1, &existing_sha1));
+            if written.is_err() {
+                discard_partial(&partial_path);
+            }
+            written
+        })
+        .collect()
+}
+
+/// Write every bundle in `plan` as a `<bundle>/` folder of loose files. A
+/// file that already hashes to its ROM's SHA1 is left untouched, files the
+/// bundle no longer holds are removed, and bare source files are linked
+/// instead of copied when `link` asks for it.
+pub fn write_plan_as_directories(
+    plan: &BuildPlan,
+    destination: &Utf8Path,
+    link: LinkMode,
+) -> crate::Result<Vec<WrittenOutput>> {
+    if !plan.writes_files() {
+        return Ok(Vec::new());
+    }
+
+    validate_plan_paths(plan)?;
+    create_dir_all(destination)?;
+    plan.zips
+        .iter()
+        .map(|zip_spec| {
+            let bundle_path = destination.join(OutputFormat::Directory(link).file_name(zip_spec));
+            let unchanged = zip_spec.entries.iter().try_fold(true, |unchanged, entry| {
+                Ok::<_, crate::Error>(write_loose_file(entry, &bundle_path, link)? && unchanged)
+            })?;
+            let removed = remove_stale_files(zip_spec, &bundle_path)?;
+            Ok(WrittenOutput {
+                path: bundle_path,
+                sha1: None,
+                unchanged: unchanged && !removed,
+            })
+        })
+        .collect()
+}
+
+/// Read and hash an output zip left by an earlier build.
+pub fn hash_existing_output(path: &Utf8Path) -> crate::Result<Sha1Digest> {
+    Ok(hashes::stream_sha1(&hashes::mmap_path(path)?))
+}
+
+/// An output entry that does not hold the ROM it should.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryMismatch {
+    pub output_path: Utf8PathBuf,
+    pub entry_name: String,
+    pub expected: Sha1Digest,
+    /// `None` when the entry is absent.
+    pub actual: Option<Sha1Digest>,
+}
+
+impl fmt::Display for EntryMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.output_path, self.entry_name)?;
+        match self.actual {
+            Some(actual) => write!(
+                f,
+                " has SHA1 {}, expected {}",
+                hex::encode(actual),
+                hex::encode(self.expected)
+            ),
+            None => write!(f, " is missing"),
+        }
+    }
+}
+
+/// Reopen every output written for `plan` and check that each entry hashes
+/// to the SHA1 of the ROM it was copied from.
+pub fn verify_outputs(
+    plan: &BuildPlan,
+    destination: &Utf8Path,
+    output: OutputFormat,
+) -> crate::Result<()> {
+    let mismatches = plan
+        .zips
+        .iter()
+        .map(|zip_spec| {
+            let entries = zip_spec
+                .entries
+                .iter()
+                .map(|entry| (entry.output_name.as_str(), entry.source.sha1))
+                .collect::<Vec<_>>();
+            check_output(
+                &destination.join(output.file_name(zip_spec)),
+                output,
+                &entries,
+            )
+        })
+        .collect::<crate::Result<Vec<_>>>()?
+        .concat();
+    verification_result(&mismatches)
+}
+
+/// Rehash the named entries of one output, returning those whose SHA1 is
+/// not the expected one. An output that cannot be read fails verification.
+pub fn check_output(
+    output_path: &Utf8Path,
+    output: OutputFormat,
+    entries: &[(&str, Sha1Digest)],
+) -> crate::Result<Vec<EntryMismatch>> {
+    let unreadable = |error: &dyn fmt::Display| {
+        crate::Error::Verification(format!("{output_path} could not be read: {error}"))
+    };
+    let mut opened = match output {
+        OutputFormat::Zip => OpenedOutput::Zip(
+            File::open(output_path)
+                .map_err(|error| unreadable(&error))
+                .and_then(|file| {
+                    zip::ZipArchive::new(BufReader::new(file)).map_err(|error| unreadable(&error))
+                })?,
+        ),
+        OutputFormat::SevenZip { .. } => {
+            let names = entries.iter().map(|&(name, _)| name).collect::<Vec<_>>();
+            OpenedOutput::SevenZip(
+                hash_7z_entries(output_path, &names).map_err(|error| unreadable(&error))?,
+            )
+        }
+        OutputFormat::Directory(_) => OpenedOutput::Directory,
+    };
+    entries
+        .iter()
+        .map(|&(name, expected)| {
+            let actual = match &mut opened {
+                OpenedOutput::Zip(zip_archive) => hash_zip_entry(zip_archive, name),
+                OpenedOutput::SevenZip(hashed) => Ok(hashed.get(name).copied()),
+                OpenedOutput::Directory => hash_directory_entry(output_path, name),
+            }
+            .map_err(|error| unreadable(&format_args!("{name}: {error}")))?;
+            Ok((actual != Some(expected)).then(|| EntryMismatch {
+                output_path: output_path.to_path_buf(),
+                entry_name: name.to_owned(),
+                expected,
+                actual,
+            }))
+        })
+        .filter_map(Result::transpose)
+        .collect()
+}
+
+/// Fail with every mismatch, one per line.
+pub fn verification_result(mismatches: &[EntryMismatch]) -> crate::Result<()> {
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+    Err(crate::Error::Verification(
+        mismatches
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    ))
+}
+
+fn hash_zip_entry<R: Read + Seek>(
+    zip_archive: &mut zip::ZipArchive<R>,
+    name: &str,
+) -> crate::Result<Option<Sha1Digest>> {
+    match zip_archive.by_name(name) {
+        Ok(file) => Ok(Some(hashes::hash_reader_chunks(file)?.sha1)),
+        Err(ZipError::FileNotFound) => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// An output opened once to check all of its entries.
+enum OpenedOutput {
+    Zip(zip::ZipArchive<BufReader<File>>),
+    /// The SHA1 of each expected entry the 7z archive holds.
+    SevenZip(BTreeMap<String, Sha1Digest>),
+    Directory,
+}
+
+/// Hash the named entries of a 7z output in one pass, so a solid block is
+/// decompressed once rather than once per entry.
+fn hash_7z_entries(
+    output_path: &Utf8Path,
+    names: &[&str],
+) -> crate::Result<BTreeMap<String, Sha1Digest>> {
+    let wanted = names
+        .iter()
+        .filter_map(|&name| Some((r7z::safe_archive_name(name).ok()?, name)))
+        .collect::<BTreeMap<_, _>>();
+    let archive = r7z::Archive::open(output_path.as_std_path())?;
+    let mut hashed = Vec::new();
+    archive.stream_files(|entry, reader| {
+        if let Some(&name) = entry.safe_path().and_then(|path| wanted.get(path)) {
+            hashed.push((name, hashes::hash_reader_chunks(reader)));
+        }
+        Ok(())
     })?;
-    Ok(written_paths)
+    hashed
+        .into_iter()
+        .map(|(name, hashes)| Ok((name.to_owned(), hashes?.sha1)))
+        .collect()
+}
+
+/// Hash a loose file of a directory output.
+fn hash_directory_entry(output_path: &Utf8Path, name: &str) -> crate::Result<Option<Sha1Digest>> {
+    let file_path = output_path.join(name);
+    if !file_path.is_file() {
+        return Ok(None);
+    }
+    Ok(Some(
+        hashes::hash_reader_chunks(File::open(file_path)?)?.sha1,
+    ))
 }
 
+/// `ZipWriter` seeks back to fill in each local header once an entry is
+/// written, so the finished file is reread to hash it rather than hashing
+/// the bytes as they are written.
+fn write_zip(
+    zip_spec: &ZipSpec,
+    partial_path: &Utf8Path,
+    options: SimpleFileOptions,
+) -> crate::Result<Sha1Digest> {
+    let mut sink = ZipSink {
+        writer: open_destination_zip(partial_path)?,
+        options,
+    };
+    zip_spec
+        .entries
+        .iter()
+        .try_for_each(|entry| write_entry(entry, &mut sink))?;
+    sink.writer.finish()?.flush()?;
+    hash_existing_output(partial_path)
+}
+
+/// 7z archives are built in memory, so they are hashed before writing.
+fn write_7z(zip_spec: &ZipSpec, partial_path: &Utf8Path, solid: bool) -> crate::Result<Sha1Digest> {
+    let builder = zip_spec.entries.iter().try_fold(
+        r7z::ArchiveBuilder::new().solid(solid),
+        |builder, entry| {
+            let mut data = Vec::new();
+            write_entry(entry, &mut data)?;
+            Ok::<_, crate::Error>(builder.add_file(&entry.output_name, &data))
+        },
+    )?;
+    let archive = builder.build()?;
+    std::fs::write(partial_path, &archive)?;
+    Ok(hashes::sha1_bytes(&archive))
+}
+
+/// Move a freshly written output into place unless the existing file is
+/// byte-identical, in which case the existing file is kept.
+fn replace_output(
+    partial_path: &Utf8Path,
+    output_path: &Utf8Path,
+    sha1: Sha1Digest,
+    existing_sha1: impl Fn(&Utf8Path) -> crate::Result<Sha1Digest>,
+) -> crate::Result<WrittenOutput> {
+    let unchanged = output_path.is_file() && existing_sha1(output_path)? == sha1;
+    if unchanged {
+        remove_file(partial_path)?;
+    } else {
+        rename(partial_path, output_path)?;
+    }
+    Ok(WrittenOutput {
+        path: output_path.to_path_buf(),
+        sha1: Some(sha1),
+        unchanged,
+    })
+}
+
+/// Remove what a failed write left behind. Failing to remove it is only
+/// logged, so the error reported is the one that failed the write.
+fn discard_partial(partial_path: &Utf8Path) {
+    if partial_path.exists()
+        && let Err(error) = remove_file(partial_path)
+    {
+        warn!("couldn't remove {partial_path}: {error}");
+    }
+}
+
+/// Returns whether the file already held the ROM.
+fn write_loose_file(
+    entry: &ZipEntrySpec,
+    bundle_path: &Utf8Path,
+    link: LinkMode,
+) -> crate::Result<bool> {
+    let file_path = bundle_path.join(&entry.output_name);
+    if file_path.is_file() && hash_existing_output(&file_path)? == entry.source.sha1 {
+        return Ok(true);
+    }
+
+    let parent = file_path.parent().unwrap_or(bundle_path);
+    create_dir_all(parent)?;
+    let partial_path = parent.join(format!(
+        ".{}.partial",
+        file_path.file_name().unwrap_or(&entry.output_name)
+    ));
+    let written = place_loose_file(entry, &partial_path, link)
+        .and_then(|()| Ok(rename(&partial_path, &file_path)?));
+    if written.is_err() {
+        discard_partial(&partial_path);
+    }
+    written.map(|()| false)
+}
+
+/// Remove files left in a bundle folder by an earlier build that `zip_spec`
+/// no longer holds, then the subfolders they leave empty. Returns whether
+/// anything was removed.
+fn remove_stale_files(zip_spec: &ZipSpec, bundle_path: &Utf8Path) -> crate::Result<bool> {
+    if !bundle_path.is_dir() {
+        return Ok(false);
+    }
+    let expected = zip_spec
+        .entries
+        .iter()
+        .map(|entry| entry.output_name.as_str())
+        .collect::<BTreeSet<_>>();
+    let mut removed = false;
+    for dir_entry in WalkDir::new(bundle_path).min_depth(1).contents_first(true) {
+        let dir_entry = dir_entry.map_err(std::io::Error::from)?;
+        let path = dir_entry.path();
+        if dir_entry.file_type().is_dir() {
+            if path.read_dir()?.next().is_none() {
+                remove_dir(path)?;
+            }
+            continue;
+        }
+        let entry_name = path.strip_prefix(bundle_path).ok().map(|relative| {
+            relative
+                .components()
+                .filter_map(|component| component.as_os_str().to_str())
+                .collect::<Vec<_>>()
+                .join("/")
+        });
+        if !entry_name.is_some_and(|name| expected.contains(name.as_str())) {
+            debug!("removing stale bundle file {}", path.display());
+            remove_file(path)?;
+            removed = true;
+        }
+    }
+    Ok(removed)
+}
+
+fn place_loose_file(
+    entry: &ZipEntrySpec,
+    partial_path: &Utf8Path,
+    link: LinkMode,
+) -> crate::Result<()> {
+    if partial_path.exists() {
+        remove_file(partial_path)?;
+    }
+    let source_path = Path::new(&entry.source.canonical_path);
+    match (entry.source.kind, link) {
+        (SourceKind::BareFile, LinkMode::Hardlink) => match hard_link(source_path, partial_path) {
+            Ok(()) => return Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::CrossesDevices => {
+                debug!(
+                    "copying {} instead of hardlinking across filesystems",
+                    entry.source.canonical_path
+                );
+            }
+            Err(error) => return Err(error.into()),
+        },
+        (SourceKind::BareFile, LinkMode::Reflink) => {
+            reflink_copy::reflink_or_copy(source_path, partial_path)?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut writer = BufWriter::new(File::create(partial_path)?);
+    write_entry(entry, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Entry timestamps are pinned so that rebuilding the same plan reproduces
+/// the same bytes.
 fn file_options(compression: ZipCompression) -> SimpleFileOptions {
-    let method = match compression {
-        ZipCompression::Deflate => zip::CompressionMethod::Deflated,
-        ZipCompression::Store => zip::CompressionMethod::Stored,
+    let (method, level) = match compression {
+        ZipCompression::Deflate(level) => (zip::CompressionMethod::Deflated, level),
+        ZipCompression::Store => (zip::CompressionMethod::Stored, None),
+        ZipCompression::Zstd(level) => (zip::CompressionMethod::Zstd, level),
     };
-    SimpleFileOptions::default().compression_method(method)
+    SimpleFileOptions::default()
+        .compression_method(method)
+        .compression_level(level)
+        .last_modified_time(zip::DateTime::DEFAULT)
 }
 
 fn validate_plan_paths(plan: &BuildPlan) -> crate::Result<()> {
@@ -123,42 +590,35 @@ fn open_destination_zip(zip_file_path: &Utf8Path) -> crate::Result<ZipWriter<Buf
     Ok(zip_writer)
 }
 
-fn write_entry(
-    entry: &ZipEntrySpec,
-    zip_writer: &mut ZipWriter<BufWriter<File>>,
-    options: SimpleFileOptions,
-) -> crate::Result<()> {
+fn write_entry(entry: &ZipEntrySpec, sink: &mut dyn EntrySink) -> crate::Result<()> {
     match entry.source.kind {
-        SourceKind::BareFile => {
-            copy_bare_file(&entry.source, &entry.output_name, zip_writer, options)
-        }
-        SourceKind::ZipEntry => {
-            copy_from_zip_entry(&entry.source, &entry.output_name, zip_writer, options)
-        }
-        SourceKind::ArchiveEntry => {
-            copy_from_archive_entry(&entry.source, &entry.output_name, zip_writer, options)
+        SourceKind::BareFile => copy_bare_file(&entry.source, &entry.output_name, sink),
+        SourceKind::ZipEntry => copy_from_zip_entry(&entry.source, &entry.output_name, sink),
+        SourceKind::ArchiveEntry
+        | SourceKind::GzipEntry
+        | SourceKind::TarEntry
+        | SourceKind::TarGzEntry => {
+            copy_from_archive_entry(&entry.source, &entry.output_name, sink)
         }
+        SourceKind::NestedEntry => copy_from_nested_entry(&entry.source, &entry.output_name, sink),
     }
 }
 
 fn copy_bare_file(
     source: &SourceFile,
     destination_name: &str,
-    zip_writer: &mut ZipWriter<BufWriter<File>>,
-    options: SimpleFileOptions,
+    sink: &mut dyn EntrySink,
 ) -> crate::Result<()> {
     let input_file = File::open(&source.canonical_path)?;
     let mut input_reader = BufReader::new(input_file);
-    zip_writer.start_file(destination_name, options)?;
-    std::io::copy(&mut input_reader, zip_writer)?;
+    std::io::copy(&mut input_reader, sink.start_entry(destination_name)?)?;
     Ok(())
 }
 
 fn copy_from_zip_entry(
     source: &SourceFile,
     destination_name: &str,
-    zip_writer: &mut ZipWriter<BufWriter<File>>,
-    options: SimpleFileOptions,
+    sink: &mut dyn EntrySink,
 ) -> crate::Result<()> {
     let entry_name = source.entry_name.as_deref().ok_or_else(|| {
         crate::Error::InvalidPath(format!(
@@ -170,13 +630,10 @@ fn copy_from_zip_entry(
     let input_reader = BufReader::new(input_file);
     let mut archive = zip::ZipArchive::new(input_reader)?;
 
-    if copy_zip_entry_by_enclosed_name(
-        &mut archive,
-        entry_name,
-        destination_name,
-        zip_writer,
-        options,
-    )? {
+    if copy_zip_entry_by_enclosed_name(&mut archive, entry_name, |file| {
+        std::io::copy(file, sink.start_entry(destination_name)?)?;
+        Ok(())
+    })? {
         Ok(())
     } else {
         Err(crate::Error::InvalidPath(format!(
@@ -189,17 +646,14 @@ fn copy_from_zip_entry(
 fn copy_zip_entry_by_enclosed_name<R: Read + Seek>(
     archive: &mut zip::ZipArchive<R>,
     entry_name: &str,
-    destination_name: &str,
-    zip_writer: &mut ZipWriter<BufWriter<File>>,
-    options: SimpleFileOptions,
+    copy: impl FnOnce(&mut dyn Read) -> crate::Result<()>,
 ) -> crate::Result<bool> {
     let requested_path = Path::new(entry_name);
 
     match archive.by_name(entry_name) {
         Ok(mut file) => {
             if zip_entry_enclosed_name_matches(&file, requested_path)? {
-                zip_writer.start_file(destination_name, options)?;
-                std::io::copy(&mut file, zip_writer)?;
+                copy(&mut file)?;
                 return Ok(true);
             }
         }
@@ -213,8 +667,7 @@ fn copy_zip_entry_by_enclosed_name<R: Read + Seek>(
             continue;
         }
         if zip_entry_enclosed_name_matches(&file, requested_path)? {
-            zip_writer.start_file(destination_name, options)?;
-            std::io::copy(&mut file, zip_writer)?;
+            copy(&mut file)?;
             return Ok(true);
         }
     }
@@ -240,14 +693,100 @@ fn zip_entry_enclosed_name_matches<R: Read>(
 fn copy_from_archive_entry(
     source: &SourceFile,
     destination_name: &str,
-    zip_writer: &mut ZipWriter<BufWriter<File>>,
-    options: SimpleFileOptions,
+    sink: &mut dyn EntrySink,
 ) -> crate::Result<()> {
-    if archive_path_is_rar(Path::new(&source.canonical_path)) {
-        copy_from_rar_archive(source, destination_name, zip_writer, options)
+    let archive_path = Path::new(&source.canonical_path);
+    match ArchiveKind::of_path(archive_path)? {
+        Some(ArchiveKind::Gzip) => {
+            copy_from_stream_archive(source, destination_name, sink, copy_gzip_entry)
+        }
+        Some(ArchiveKind::Tar) => {
+            copy_from_stream_archive(source, destination_name, sink, copy_tar_entry)
+        }
+        _ if archive_path_is_rar(archive_path) => {
+            copy_from_rar_archive(source, destination_name, sink)
+        }
+        _ => copy_from_7z_archive(source, destination_name, sink),
+    }
+}
+
+/// Copies a named entry out of an archive that can only be read front to
+/// back, returning whether the entry was found.
+type StreamEntryCopier<R> =
+    fn(R, &str, &mut dyn FnMut(&mut dyn Read) -> crate::Result<()>) -> crate::Result<bool>;
+
+fn copy_from_stream_archive(
+    source: &SourceFile,
+    destination_name: &str,
+    sink: &mut dyn EntrySink,
+    copy_entry: StreamEntryCopier<BufReader<File>>,
+) -> crate::Result<()> {
+    let entry_name = source.entry_name.as_deref().ok_or_else(|| {
+        crate::Error::InvalidPath(format!(
+            "archive source has no entry name: {}",
+            source.display_name()
+        ))
+    })?;
+    let input_reader = BufReader::new(File::open(&source.canonical_path)?);
+
+    if copy_entry(input_reader, entry_name, &mut |entry| {
+        std::io::copy(entry, sink.start_entry(destination_name)?)?;
+        Ok(())
+    })? {
+        Ok(())
     } else {
-        copy_from_7z_archive(source, destination_name, zip_writer, options)
+        Err(crate::Error::InvalidPath(format!(
+            "archive entry not found: {}",
+            source.display_name()
+        )))
+    }
+}
+
+/// A plain gzip stream holds exactly one file, so any entry name matches it;
+/// a `.tar.gz` stream is searched like a tar archive.
+fn copy_gzip_entry<R: Read>(
+    reader: R,
+    entry_name: &str,
+    copy: &mut dyn FnMut(&mut dyn Read) -> crate::Result<()>,
+) -> crate::Result<bool> {
+    let mut decoder = flate2::read::GzDecoder::new(reader);
+    let mut head = Vec::new();
+    (&mut decoder).take(TAR_HEADER_LEN).read_to_end(&mut head)?;
+    let is_tar = infer::archive::is_tar(&head);
+    let mut contents = Cursor::new(head).chain(decoder);
+    if is_tar {
+        return copy_tar_entry(contents, entry_name, copy);
+    }
+
+    copy(&mut contents)?;
+    Ok(true)
+}
+
+fn copy_tar_entry<R: Read>(
+    reader: R,
+    entry_name: &str,
+    copy: &mut dyn FnMut(&mut dyn Read) -> crate::Result<()>,
+) -> crate::Result<bool> {
+    let requested_path = Path::new(entry_name);
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let matches = entry
+            .path()?
+            .components()
+            .filter(|component| !matches!(component, Component::CurDir))
+            .eq(requested_path.components());
+        if matches {
+            copy(&mut entry)?;
+            return Ok(true);
+        }
     }
+
+    Ok(false)
 }
 
 fn archive_path_is_rar(path: &Path) -> bool {
@@ -259,8 +798,7 @@ fn archive_path_is_rar(path: &Path) -> bool {
 fn copy_from_rar_archive(
     source: &SourceFile,
     destination_name: &str,
-    zip_writer: &mut ZipWriter<BufWriter<File>>,
-    options: SimpleFileOptions,
+    sink: &mut dyn EntrySink,
 ) -> crate::Result<()> {
     let entry_name = source.entry_name.as_deref().ok_or_else(|| {
         crate::Error::InvalidPath(format!(
@@ -268,25 +806,30 @@ fn copy_from_rar_archive(
             source.display_name()
         ))
     })?;
+    let data = read_rar_entry(Path::new(&source.canonical_path), entry_name)?.ok_or_else(|| {
+        crate::Error::InvalidPath(format!(
+            "archive entry not found: {}",
+            source.display_name()
+        ))
+    })?;
+    sink.start_entry(destination_name)?.write_all(&data)?;
+    Ok(())
+}
+
+fn read_rar_entry(archive_path: &Path, entry_name: &str) -> crate::Result<Option<Vec<u8>>> {
     let requested_path = safe_rar_entry_path(entry_name)?;
-    let mut archive =
-        unrar::Archive::new(Path::new(&source.canonical_path)).open_for_processing()?;
+    let mut archive = unrar::Archive::new(archive_path).open_for_processing()?;
 
     while let Some(header) = archive.read_header()? {
         archive = if rar_header_matches_entry(header.entry(), &requested_path)? {
             let (data, _rest) = header.read()?;
-            zip_writer.start_file(destination_name, options)?;
-            zip_writer.write_all(&data)?;
-            return Ok(());
+            return Ok(Some(data));
         } else {
             header.skip()?
         };
     }
 
-    Err(crate::Error::InvalidPath(format!(
-        "archive entry not found: {}",
-        source.display_name()
-    )))
+    Ok(None)
 }
 
 fn rar_header_matches_entry(
@@ -331,8 +874,7 @@ fn safe_rar_entry_path_from_path(path: &Path) -> crate::Result<PathBuf> {
 fn copy_from_7z_archive(
     source: &SourceFile,
     destination_name: &str,
-    zip_writer: &mut ZipWriter<BufWriter<File>>,
-    options: SimpleFileOptions,
+    sink: &mut dyn EntrySink,
 ) -> crate::Result<()> {
     let entry_name = source.entry_name.as_deref().ok_or_else(|| {
         crate::Error::InvalidPath(format!(
@@ -348,8 +890,8 @@ fn copy_from_7z_archive(
         )));
     }
 
-    zip_writer.start_file(destination_name, options)?;
-    archive.extract_by_name(entry_name, zip_writer)?;
+    let mut writer = sink.start_entry(destination_name)?;
+    archive.extract_by_name(entry_name, &mut writer)?;
     Ok(())
 }
 
@@ -369,13 +911,128 @@ fn has_extractable_7z_entry(archive: &r7z::Archive, entry_name: &str) -> bool {
         .any(|entry| entry.is_file() && entry.safe_path() == Some(requested_path.as_path()))
 }
 
+fn read_7z_entry(archive_path: &Path, entry_name: &str) -> crate::Result<Option<Vec<u8>>> {
+    let archive = r7z::Archive::open(archive_path)?;
+    if !has_extractable_7z_entry(&archive, entry_name) {
+        return Ok(None);
+    }
+
+    let mut data = Vec::new();
+    archive.extract_by_name(entry_name, &mut data)?;
+    Ok(Some(data))
+}
+
+fn copy_from_nested_entry(
+    source: &SourceFile,
+    destination_name: &str,
+    sink: &mut dyn EntrySink,
+) -> crate::Result<()> {
+    let not_found = || {
+        crate::Error::InvalidPath(format!(
+            "archive entry not found: {}",
+            source.display_name()
+        ))
+    };
+    let entry_name = source.entry_name.as_deref().ok_or_else(|| {
+        crate::Error::InvalidPath(format!(
+            "archive source has no entry name: {}",
+            source.display_name()
+        ))
+    })?;
+    let (outer_name, inner_names) = source.archive_chain.split_first().ok_or_else(|| {
+        crate::Error::InvalidPath(format!(
+            "nested archive source has no archive chain: {}",
+            source.display_name()
+        ))
+    })?;
+
+    let mut container = read_archive_file_entry(Path::new(&source.canonical_path), outer_name)?
+        .ok_or_else(not_found)?;
+    for inner_name in inner_names {
+        container = read_nested_archive_entry(&container, inner_name)?.ok_or_else(not_found)?;
+    }
+    let data = read_nested_archive_entry(&container, entry_name)?.ok_or_else(not_found)?;
+
+    sink.start_entry(destination_name)?.write_all(&data)?;
+    Ok(())
+}
+
+fn read_archive_file_entry(
+    archive_path: &Path,
+    entry_name: &str,
+) -> crate::Result<Option<Vec<u8>>> {
+    match ArchiveKind::of_path(archive_path)? {
+        Some(ArchiveKind::Zip) => {
+            let mut archive = zip::ZipArchive::new(BufReader::new(File::open(archive_path)?))?;
+            read_zip_entry(&mut archive, entry_name)
+        }
+        Some(ArchiveKind::Rar) => read_rar_entry(archive_path, entry_name),
+        Some(ArchiveKind::SevenZip) => read_7z_entry(archive_path, entry_name),
+        Some(ArchiveKind::Gzip) => read_stream_entry(
+            BufReader::new(File::open(archive_path)?),
+            entry_name,
+            copy_gzip_entry,
+        ),
+        Some(ArchiveKind::Tar) => read_stream_entry(
+            BufReader::new(File::open(archive_path)?),
+            entry_name,
+            copy_tar_entry,
+        ),
+        _ => Ok(None),
+    }
+}
+
+fn read_nested_archive_entry(container: &[u8], entry_name: &str) -> crate::Result<Option<Vec<u8>>> {
+    match ArchiveKind::of_bytes(container) {
+        Some(ArchiveKind::Zip) => {
+            let mut archive = zip::ZipArchive::new(Cursor::new(container))?;
+            read_zip_entry(&mut archive, entry_name)
+        }
+        Some(ArchiveKind::Rar) => with_spooled_file(container, |path| {
+            read_rar_entry(path.as_std_path(), entry_name)
+        }),
+        Some(ArchiveKind::SevenZip) => with_spooled_file(container, |path| {
+            read_7z_entry(path.as_std_path(), entry_name)
+        }),
+        Some(ArchiveKind::Gzip) => read_stream_entry(container, entry_name, copy_gzip_entry),
+        Some(ArchiveKind::Tar) => read_stream_entry(container, entry_name, copy_tar_entry),
+        _ => Ok(None),
+    }
+}
+
+fn read_zip_entry<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    entry_name: &str,
+) -> crate::Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    let found = copy_zip_entry_by_enclosed_name(archive, entry_name, |file| {
+        file.read_to_end(&mut data)?;
+        Ok(())
+    })?;
+    Ok(found.then_some(data))
+}
+
+fn read_stream_entry<R: Read>(
+    reader: R,
+    entry_name: &str,
+    copy_entry: StreamEntryCopier<R>,
+) -> crate::Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    let found = copy_entry(reader, entry_name, &mut |entry| {
+        entry.read_to_end(&mut data)?;
+        Ok(())
+    })?;
+    Ok(found.then_some(data))
+}
+
 #[cfg(test)]
 mod tests {
-    use std::io::{self, Read, Write};
+    use std::io::{self, Read};
 
     use proptest::prelude::*;
 
     use crate::domain::{BuildReport, ZipSpec};
+    use crate::test_helpers::{gzip_bytes, tar_bytes, write_version_rar, zip_bytes};
 
     use super::*;
 
@@ -390,6 +1047,7 @@ mod tests {
                 .map_or_else(String::new, |parent| parent.as_str().to_owned()),
             canonical_path: path.as_str().to_owned(),
             entry_name: None,
+            archive_chain: Vec::new(),
             sha1: crate::hashes::sha1_bytes(b"sha1"),
             kind: SourceKind::BareFile,
         }
@@ -406,6 +1064,7 @@ mod tests {
                 .map_or_else(String::new, |parent| parent.as_str().to_owned()),
             canonical_path: path.as_str().to_owned(),
             entry_name: entry_name.map(str::to_owned),
+            archive_chain: Vec::new(),
             sha1: crate::hashes::sha1_bytes(b"sha1"),
             kind,
         }
@@ -422,22 +1081,7 @@ mod tests {
         path: &Utf8Path,
         entries: &[(&str, &[u8])],
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let file = File::create(path)?;
-        let mut zip = zip::ZipWriter::new(file);
-        let options = zip::write::SimpleFileOptions::default();
-        for (name, contents) in entries {
-            zip.start_file(*name, options)?;
-            zip.write_all(contents)?;
-        }
-        zip.finish()?;
-        Ok(())
-    }
-
-    fn write_version_rar(path: &Utf8Path) -> Result<(), Box<dyn std::error::Error>> {
-        let archive = hex::decode(
-            "526172211a0700cf907300000d000000000000000f0c7420802700150000000b0000000345f37dc6a48a07471d330700a481000056455253494f4e0c008fec8a45cc23c848088362fe5fdd5c5388f072c43d7b00400700",
-        )?;
-        std::fs::write(path, archive)?;
+        std::fs::write(path, zip_bytes(entries)?)?;
         Ok(())
     }
 
@@ -813,18 +1457,326 @@ mod tests {
             dry_run: false,
         };
 
-        let written_paths =
-            write_plan_with_compression(&plan, &destination, ZipCompression::Store)?;
-        let zip_path = written_paths
+        let written_zips = write_plan_with_compression(
+            &plan,
+            &destination,
+            ZipCompression::Store,
+            hash_existing_output,
+        )?;
+        let written_zip = written_zips
             .first()
             .ok_or_else(|| io::Error::other("expected written zip"))?;
-        let mut zip = zip::ZipArchive::new(File::open(zip_path)?)?;
+        let mut zip = zip::ZipArchive::new(File::open(&written_zip.path)?)?;
         let entry = zip.by_name("game.rom")?;
 
         assert_eq!(entry.compression(), zip::CompressionMethod::Stored);
         Ok(())
     }
 
+    #[test]
+    fn write_plan_with_zstd_level_writes_readable_zstd_entries()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let source_path = utf8_path(temp_dir.path())?.join("source.rom");
+        std::fs::write(&source_path, b"rom")?;
+        let destination = utf8_path(temp_dir.path())?.join("output");
+        let plan = BuildPlan {
+            zips: vec![ZipSpec {
+                file_name: "safe.zip".to_owned(),
+                entries: vec![ZipEntrySpec {
+                    output_name: "game.rom".to_owned(),
+                    source: source_file(&source_path),
+                }],
+            }],
+            report: BuildReport::default(),
+            dry_run: false,
+        };
+
+        let written_zips = write_plan_with_compression(
+            &plan,
+            &destination,
+            ZipCompression::zstd(Some(19))?,
+            hash_existing_output,
+        )?;
+        let written_zip = written_zips
+            .first()
+            .ok_or_else(|| io::Error::other("expected written zip"))?;
+        let mut zip = zip::ZipArchive::new(File::open(&written_zip.path)?)?;
+        let mut entry = zip.by_name("game.rom")?;
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+
+        assert_eq!(entry.compression(), zip::CompressionMethod::Zstd);
+        assert_eq!(contents, b"rom");
+        Ok(())
+    }
+
+    #[test]
+    fn compression_levels_outside_the_method_range_are_rejected() {
+        assert!(ZipCompression::deflate(Some(9)).is_ok());
+        assert!(ZipCompression::deflate(Some(-1)).is_err());
+        assert!(ZipCompression::zstd(Some(-7)).is_ok());
+        assert!(ZipCompression::zstd(Some(23)).is_err());
+    }
+
+    #[test]
+    fn rewriting_identical_plan_keeps_existing_zip() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let source_path = utf8_path(temp_dir.path())?.join("source.rom");
+        std::fs::write(&source_path, b"rom")?;
+        let destination = utf8_path(temp_dir.path())?.join("output");
+        let plan = BuildPlan {
+            zips: vec![ZipSpec {
+                file_name: "game.zip".to_owned(),
+                entries: vec![ZipEntrySpec {
+                    output_name: "game.rom".to_owned(),
+                    source: source_file(&source_path),
+                }],
+            }],
+            report: BuildReport::default(),
+            dry_run: false,
+        };
+
+        let first = write_plan_with_compression(
+            &plan,
+            &destination,
+            ZipCompression::Deflate(None),
+            hash_existing_output,
+        )?;
+        let second = write_plan_with_compression(
+            &plan,
+            &destination,
+            ZipCompression::Deflate(None),
+            hash_existing_output,
+        )?;
+        std::fs::write(&source_path, b"new rom")?;
+        let third = write_plan_with_compression(
+            &plan,
+            &destination,
+            ZipCompression::Deflate(None),
+            hash_existing_output,
+        )?;
+
+        assert_eq!(
+            [&first, &second, &third].map(|written| written.iter().all(|zip| zip.unchanged)),
+            [false, true, false]
+        );
+        assert_eq!(first[0].sha1, second[0].sha1);
+        assert_ne!(second[0].sha1, third[0].sha1);
+        assert_eq!(std::fs::read_dir(&destination)?.count(), 1);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directory_output_writes_loose_files_and_hardlinks_bare_sources()
+    -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = tempfile::tempdir()?;
+        let root = utf8_path(temp_dir.path())?;
+        let bare_path = root.join("source.rom");
+        std::fs::write(&bare_path, b"rom")?;
+        let zip_path = root.join("source.zip");
+        std::fs::write(&zip_path, zip_bytes(&[("inner.rom", b"zipped")])?)?;
+        let destination = root.join("output");
+        let plan = BuildPlan {
+            zips: vec![ZipSpec {
+                file_name: "game.zip".to_owned(),
+                entries: vec![
+                    ZipEntrySpec {
+                        output_name: "game.rom".to_owned(),
+                        source: SourceFile {
+                            sha1: crate::hashes::sha1_bytes(b"rom"),
+                            ..source_file(&bare_path)
+                        },
+                    },
+                    ZipEntrySpec {
+                        output_name: "sub/zipped.rom".to_owned(),
+                        source: SourceFile {
+                            sha1: crate::hashes::sha1_bytes(b"zipped"),
+                            ..archive_source_file(
+                                &zip_path,
+                                Some("inner.rom"),
+                                SourceKind::ZipEntry,
+                            )
+                        },
+                    },
+                ],
+            }],
+            report: BuildReport::default(),
+            dry_run: false,
+        };
+
+        let first = write_plan_as_directories(&plan, &destination, LinkMode::Hardlink)?;
+        let second = write_plan_as_directories(&plan, &destination, LinkMode::Hardlink)?;
+
+        let bundle = destination.join("game");
+        assert_eq!(first[0].path, bundle);
+        assert_eq!(
+            [&first, &second].map(|written| written[0].unchanged),
+            [false, true]
+        );
+        assert_eq!(std::fs::read(bundle.join("sub/zipped.rom"))?, b"zipped");
+        assert_eq!(
+            std::fs::metadata(bundle.join("game.rom"))?.ino(),
+            std::fs::metadata(&bare_path)?.ino()
+        );
+        assert_eq!(std::fs::read_dir(&bundle)?.count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn directory_output_removes_files_the_bundle_no_longer_holds()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = utf8_path(temp_dir.path())?;
+        let source_path = root.join("source.rom");
+        std::fs::write(&source_path, b"rom")?;
+        let destination = root.join("output");
+        let bundle = destination.join("game");
+        std::fs::create_dir_all(bundle.join("old/deeper"))?;
+        std::fs::write(bundle.join("stale.rom"), b"stale")?;
+        std::fs::write(bundle.join("old/deeper/stale.rom"), b"stale")?;
+        let plan = BuildPlan {
+            zips: vec![ZipSpec {
+                file_name: "game.zip".to_owned(),
+                entries: vec![ZipEntrySpec {
+                    output_name: "sub/game.rom".to_owned(),
+                    source: SourceFile {
+                        sha1: crate::hashes::sha1_bytes(b"rom"),
+                        ..source_file(&source_path)
+                    },
+                }],
+            }],
+            report: BuildReport::default(),
+            dry_run: false,
+        };
+
+        let first = write_plan_as_directories(&plan, &destination, LinkMode::Copy)?;
+        std::fs::write(bundle.join("stale.rom"), b"stale")?;
+        let second = write_plan_as_directories(&plan, &destination, LinkMode::Copy)?;
+        let third = write_plan_as_directories(&plan, &destination, LinkMode::Copy)?;
+
+        assert_eq!(
+            [&first, &second, &third].map(|written| written[0].unchanged),
+            [false, false, true]
+        );
+        assert_eq!(std::fs::read(bundle.join("sub/game.rom"))?, b"rom");
+        assert!(!bundle.join("stale.rom").exists());
+        assert!(!bundle.join("old").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn seven_zip_output_holds_entries_and_keeps_identical_archive()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = utf8_path(temp_dir.path())?;
+        let source_path = root.join("source.rom");
+        std::fs::write(&source_path, b"rom")?;
+        let destination = root.join("output");
+        let plan = BuildPlan {
+            zips: vec![ZipSpec {
+                file_name: "game.zip".to_owned(),
+                entries: vec![ZipEntrySpec {
+                    output_name: "sub/game.rom".to_owned(),
+                    source: source_file(&source_path),
+                }],
+            }],
+            report: BuildReport::default(),
+            dry_run: false,
+        };
+
+        let first = write_plan_as_7z(&plan, &destination, false, hash_existing_output)?;
+        let second = write_plan_as_7z(&plan, &destination, false, hash_existing_output)?;
+
+        let archive_path = destination.join("game.7z");
+        assert_eq!(first[0].path, archive_path);
+        assert_eq!(
+            [&first, &second].map(|written| written[0].unchanged),
+            [false, true]
+        );
+        let archive = r7z::Archive::open(archive_path.as_std_path())?;
+        let mut data = Vec::new();
+        archive.extract_by_name("sub/game.rom", &mut data)?;
+        assert_eq!(data, b"rom");
+        assert_eq!(std::fs::read_dir(&destination)?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn verify_outputs_names_the_entry_that_does_not_match() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let temp_dir = tempfile::tempdir()?;
+        let root = utf8_path(temp_dir.path())?;
+        let source_path = root.join("source.rom");
+        std::fs::write(&source_path, b"rom")?;
+        let destination = root.join("output");
+        let plan = BuildPlan {
+            zips: vec![ZipSpec {
+                file_name: "game.zip".to_owned(),
+                entries: vec![ZipEntrySpec {
+                    output_name: "game.rom".to_owned(),
+                    source: SourceFile {
+                        sha1: hashes::sha1_bytes(b"rom"),
+                        ..source_file(&source_path)
+                    },
+                }],
+            }],
+            report: BuildReport::default(),
+            dry_run: false,
+        };
+
+        write_plan(&plan, &destination)?;
+        verify_outputs(&plan, &destination, OutputFormat::Zip)?;
+        write_source_zip(&destination.join("game.zip"), &[("game.rom", b"bad")])?;
+
+        let Err(error) = verify_outputs(&plan, &destination, OutputFormat::Zip) else {
+            return Err("expected verification to fail".into());
+        };
+        assert!(error.to_string().contains(&format!(
+            "{}:game.rom has SHA1",
+            destination.join("game.zip")
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn check_output_rehashes_every_entry_of_a_7z_output() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let temp_dir = tempfile::tempdir()?;
+        let archive_path = utf8_path(temp_dir.path())?.join("game.7z");
+        let archive_data = r7z::ArchiveBuilder::new()
+            .solid(true)
+            .add_file("a.rom", b"a")
+            .add_file("sub/b.rom", b"wrong")
+            .build()?;
+        std::fs::write(&archive_path, archive_data)?;
+
+        let mismatches = check_output(
+            &archive_path,
+            OutputFormat::SevenZip { solid: true },
+            &[
+                ("a.rom", hashes::sha1_bytes(b"a")),
+                ("sub/b.rom", hashes::sha1_bytes(b"b")),
+                ("c.rom", hashes::sha1_bytes(b"c")),
+            ],
+        )?;
+
+        assert_eq!(
+            mismatches
+                .iter()
+                .map(|mismatch| (mismatch.entry_name.as_str(), mismatch.actual))
+                .collect::<Vec<_>>(),
+            vec![
+                ("sub/b.rom", Some(hashes::sha1_bytes(b"wrong"))),
+                ("c.rom", None)
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn missing_zip_entry_errors_clearly() -> Result<(), Box<dyn std::error::Error>> {
         let temp_dir = tempfile::tempdir()?;
@@ -995,4 +1947,133 @@ mod tests {
         assert!(message.contains("missing.rom"));
         Ok(())
     }
+
+    fn written_entry(
+        written_paths: &[Utf8PathBuf],
+        name: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let zip_path = written_paths
+            .first()
+            .ok_or_else(|| io::Error::other("expected written zip"))?;
+        let mut zip = zip::ZipArchive::new(File::open(zip_path)?)?;
+        let mut entry = zip.by_name(name)?;
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    #[test]
+    fn gzip_source_entry_writes_decompressed_content() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let archive_path = utf8_path(temp_dir.path())?.join("game.rom.gz");
+        std::fs::write(&archive_path, gzip_bytes(b"rom", None)?)?;
+        let destination = utf8_path(temp_dir.path())?.join("output");
+        let plan =
+            single_zip_entry_plan(&archive_path, "game.rom", "game.rom", SourceKind::GzipEntry);
+
+        let written_paths = write_plan(&plan, &destination)?;
+
+        assert_eq!(written_entry(&written_paths, "game.rom")?, b"rom");
+        Ok(())
+    }
+
+    #[test]
+    fn tar_gz_source_entry_writes_member_content() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let archive_path = utf8_path(temp_dir.path())?.join("pack.tar.gz");
+        let tar_data = tar_bytes(&[
+            ("other.rom", b"other" as &[u8]),
+            ("./nested/game.rom", b"rom" as &[u8]),
+        ])?;
+        std::fs::write(&archive_path, gzip_bytes(&tar_data, None)?)?;
+        let destination = utf8_path(temp_dir.path())?.join("output");
+        let plan = single_zip_entry_plan(
+            &archive_path,
+            "nested/game.rom",
+            "game.rom",
+            SourceKind::TarGzEntry,
+        );
+
+        let written_paths = write_plan(&plan, &destination)?;
+
+        assert_eq!(written_entry(&written_paths, "game.rom")?, b"rom");
+        Ok(())
+    }
+
+    #[test]
+    fn missing_tar_archive_entry_errors_clearly() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let archive_path = utf8_path(temp_dir.path())?.join("pack.tar");
+        std::fs::write(
+            &archive_path,
+            tar_bytes(&[("present.rom", b"rom" as &[u8])])?,
+        )?;
+        let destination = utf8_path(temp_dir.path())?.join("output");
+        let plan = single_zip_entry_plan(
+            &archive_path,
+            "missing.rom",
+            "game.rom",
+            SourceKind::TarEntry,
+        );
+
+        let message = error_message(write_plan(&plan, &destination))?;
+
+        assert!(message.contains("archive entry not found"));
+        assert!(message.contains("missing.rom"));
+        Ok(())
+    }
+
+    fn nested_entry_plan(archive_path: &Utf8Path, chain: &[&str], entry_name: &str) -> BuildPlan {
+        let mut plan = single_zip_entry_plan(
+            archive_path,
+            entry_name,
+            "game.rom",
+            SourceKind::NestedEntry,
+        );
+        plan.zips[0].entries[0].source.archive_chain =
+            chain.iter().map(|name| (*name).to_owned()).collect();
+        plan
+    }
+
+    #[test]
+    fn nested_source_entry_writes_content_through_archive_chain()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let archive_path = utf8_path(temp_dir.path())?.join("pack.zip");
+        let seven_zip = r7z::ArchiveBuilder::new()
+            .add_file("nested/deep.rom", b"deep")
+            .build()?;
+        let inner = zip_bytes(&[("inner.7z", seven_zip.as_slice())])?;
+        write_source_zip(&archive_path, &[("inner.zip", inner.as_slice())])?;
+        let destination = utf8_path(temp_dir.path())?.join("output");
+        let plan = nested_entry_plan(&archive_path, &["inner.zip", "inner.7z"], "nested/deep.rom");
+
+        let written_paths = write_plan(&plan, &destination)?;
+        let zip_path = written_paths
+            .first()
+            .ok_or_else(|| io::Error::other("expected written zip"))?;
+        let mut zip = zip::ZipArchive::new(File::open(zip_path)?)?;
+        let mut entry = zip.by_name("game.rom")?;
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+
+        assert_eq!(contents, b"deep");
+        Ok(())
+    }
+
+    #[test]
+    fn missing_nested_archive_entry_errors_clearly() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let archive_path = utf8_path(temp_dir.path())?.join("pack.zip");
+        let inner = zip_bytes(&[("present.rom", b"rom")])?;
+        write_source_zip(&archive_path, &[("inner.zip", inner.as_slice())])?;
+        let destination = utf8_path(temp_dir.path())?.join("output");
+        let plan = nested_entry_plan(&archive_path, &["inner.zip"], "missing.rom");
+
+        let message = error_message(write_plan(&plan, &destination))?;
+
+        assert!(message.contains("archive entry not found"));
+        assert!(message.contains("pack.zip:inner.zip:missing.rom"));
+        Ok(())
+    }
 }
@@ -1,4 +1,10 @@
-use crate::hashes::Sha1Digest;
+use std::{collections::BTreeSet, fmt, ops::RangeInclusive};
+
+use camino::Utf8PathBuf;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::hashes::{Crc32Digest, Md5Digest, Sha1Digest};
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct DatRom {
@@ -7,6 +13,11 @@ pub struct DatRom {
     pub parent_name: Option<String>,
     pub rom_name: String,
     pub sha1: Sha1Digest,
+    /// The DAT marks the ROM `status="nodump"`.
+    pub nodump: bool,
+    /// The DAT gives the ROM a `merge` name: a clone shares it with its
+    /// parent.
+    pub merged: bool,
 }
 
 impl DatRom {
@@ -14,30 +25,344 @@ impl DatRom {
     pub fn bundle_name(&self, mode: BuildMode) -> &str {
         match mode {
             BuildMode::ParentBundles => self.parent_name.as_deref().unwrap_or(&self.game_name),
-            BuildMode::PerGame => &self.game_name,
+            BuildMode::PerGame | BuildMode::Split | BuildMode::OneGameOneRom => &self.game_name,
         }
     }
 }
 
+/// A sample a game plays, found by name in the set named by the game's
+/// `sampleof`, or in the game's own set.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DatSample {
+    pub game_name: String,
+    pub sample_set: String,
+    pub sample_name: String,
+}
+
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum BuildMode {
     #[default]
     ParentBundles,
     PerGame,
+    /// Each game in its own output, leaving out the ROMs a clone shares
+    /// with its parent.
+    Split,
+    /// One game from each parent/clone family, chosen by
+    /// [`ReleasePreferences`], each in its own output.
+    OneGameOneRom,
+}
+
+/// A game's parent/clone family, the regions and languages of its
+/// `<release>` elements, and the metadata game filters look at.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DatGame {
+    pub game_name: String,
+    pub parent_name: Option<String>,
+    pub regions: Vec<String>,
+    pub languages: Vec<String>,
+    pub is_bios: bool,
+    pub is_device: bool,
+    pub is_mechanical: bool,
+    pub year: Option<String>,
+    pub manufacturer: Option<String>,
+}
+
+/// Which of a DAT's games a build plans and reports. The default keeps
+/// every game; each setting narrows it further.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameFilter {
+    pub excluded: BTreeSet<GameKind>,
+    pub name_pattern: Option<NamePattern>,
+    /// Kept games' manufacturer contains one of these, ignoring case.
+    pub manufacturers: Vec<String>,
+    pub years: Option<YearRange>,
+    /// Only games named in a `--games-from` list.
+    pub game_names: Option<BTreeSet<String>>,
+}
+
+impl GameFilter {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Kinds of game a filter can leave out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GameKind {
+    Clone,
+    Bios,
+    Device,
+    Mechanical,
+}
+
+/// A regular expression matched against game names.
+#[derive(Clone, Debug)]
+pub struct NamePattern(regex::Regex);
+
+impl NamePattern {
+    pub fn new(pattern: &str) -> crate::Result<Self> {
+        Ok(Self(regex::Regex::new(pattern)?))
+    }
+
+    #[must_use]
+    pub fn is_match(&self, name: &str) -> bool {
+        self.0.is_match(name)
+    }
+}
+
+impl PartialEq for NamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for NamePattern {}
+
+/// Release years to keep, either end open.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct YearRange {
+    pub from: Option<u16>,
+    pub to: Option<u16>,
+}
+
+impl YearRange {
+    /// Parse `1985`, `1980-1989`, `1980-` or `-1989`.
+    pub fn parse(range: &str) -> crate::Result<Self> {
+        let invalid = || {
+            crate::Error::Config(format!(
+                "year range must look like 1985, 1980-1989, 1980- or -1989: {range}"
+            ))
+        };
+        let year = |value: &str| {
+            let value = value.trim();
+            if value.is_empty() {
+                Ok(None)
+            } else {
+                value.parse().map(Some).map_err(|_| invalid())
+            }
+        };
+        let (from, to) = if let Some((from, to)) = range.split_once('-') {
+            (year(from)?, year(to)?)
+        } else {
+            let year = year(range)?;
+            (year, year)
+        };
+        if from.is_none() && to.is_none() {
+            return Err(invalid());
+        }
+        Ok(Self { from, to })
+    }
+
+    /// Whether a DAT year falls in the range. MAME writes unsure digits as
+    /// `?`, so `198?` is kept when any year from 1980 to 1989 would be.
+    #[must_use]
+    pub fn contains(&self, year: &str) -> bool {
+        let bound = |digit| {
+            year.trim()
+                .chars()
+                .map(|c| if c == '?' { digit } else { c })
+                .collect::<String>()
+                .parse::<u16>()
+                .ok()
+        };
+        match (bound('0'), bound('9')) {
+            (Some(earliest), Some(latest)) => {
+                self.from.is_none_or(|from| latest >= from)
+                    && self.to.is_none_or(|to| earliest <= to)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Regions and languages for the 1G1R layout, most wanted first. Either
+/// list may be empty to leave it out of the choice.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReleasePreferences {
+    pub regions: Vec<String>,
+    pub languages: Vec<String>,
+}
+
+/// The game the 1G1R layout kept from a parent/clone family.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FamilyChoice {
+    /// The parent's name, shared by the whole family.
+    pub family: String,
+    /// `None` when every game in the family is excluded.
+    pub selected: Option<String>,
+    pub reason: String,
+    pub rejected: Vec<RejectedGame>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RejectedGame {
+    pub game_name: String,
+    pub reason: String,
+}
+
+/// How ROMs a DAT marks `status="nodump"` are built, after clrmamepro's
+/// `forcenodump`. Undumped ROMs listed without a SHA1 are never built.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
-pub enum ZipCompression {
+pub enum NodumpPolicy {
+    /// Built like any other ROM and reported when missing.
     #[default]
-    Deflate,
+    Required,
+    /// Left out of the build.
+    Obsolete,
+    /// Built when a source has them, but never reported missing.
+    Ignore,
+}
+
+/// A DAT header's clrmamepro directives as written, which supply build
+/// defaults the command line can override.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DatDirectives {
+    pub merging: Option<String>,
+    pub nodump: Option<String>,
+    pub packing: Option<String>,
+}
+
+impl DatDirectives {
+    /// `full` keeps clones with their parent, `split` gives each game its own
+    /// output without the ROMs it shares with its parent, and `none` gives
+    /// each game its own complete output.
+    #[must_use]
+    pub fn mode(&self) -> Option<BuildMode> {
+        match self.merging.as_deref()? {
+            "full" => Some(BuildMode::ParentBundles),
+            "split" => Some(BuildMode::Split),
+            "none" => Some(BuildMode::PerGame),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn nodump_policy(&self) -> Option<NodumpPolicy> {
+        match self.nodump.as_deref()? {
+            "required" => Some(NodumpPolicy::Required),
+            "obsolete" => Some(NodumpPolicy::Obsolete),
+            "ignore" => Some(NodumpPolicy::Ignore),
+            _ => None,
+        }
+    }
+
+    /// `unzip` writes each bundle as a directory of copied files.
+    #[must_use]
+    pub fn output(&self) -> Option<OutputFormat> {
+        match self.packing.as_deref()? {
+            "zip" => Some(OutputFormat::Zip),
+            "unzip" => Some(OutputFormat::Directory(LinkMode::Copy)),
+            _ => None,
+        }
+    }
+}
+
+/// Entry compression for zip outputs. A `None` level uses the method's
+/// default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZipCompression {
+    Deflate(Option<i64>),
     Store,
+    Zstd(Option<i64>),
+}
+
+impl Default for ZipCompression {
+    fn default() -> Self {
+        Self::Deflate(None)
+    }
+}
+
+impl ZipCompression {
+    /// Levels 0-9 use zlib; 10 and above select the much slower Zopfli.
+    pub const DEFLATE_LEVELS: RangeInclusive<i64> = 0..=264;
+    pub const ZSTD_LEVELS: RangeInclusive<i64> = -7..=22;
+
+    pub fn deflate(level: Option<i64>) -> crate::Result<Self> {
+        checked_level("deflate", level, &Self::DEFLATE_LEVELS).map(Self::Deflate)
+    }
+
+    pub fn zstd(level: Option<i64>) -> crate::Result<Self> {
+        checked_level("zstd", level, &Self::ZSTD_LEVELS).map(Self::Zstd)
+    }
+}
+
+fn checked_level(
+    method: &str,
+    level: Option<i64>,
+    levels: &RangeInclusive<i64>,
+) -> crate::Result<Option<i64>> {
+    match level {
+        Some(level) if !levels.contains(&level) => Err(crate::Error::Compression(format!(
+            "{method} level must be between {} and {}: {level}",
+            levels.start(),
+            levels.end()
+        ))),
+        _ => Ok(level),
+    }
+}
+
+/// How each planned bundle is written to the destination.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One zip per bundle.
+    #[default]
+    Zip,
+    /// A `<bundle>/` folder of loose ROM files.
+    Directory(LinkMode),
+    /// One 7z archive per bundle, optionally compressed as a single solid
+    /// block.
+    SevenZip { solid: bool },
+}
+
+impl OutputFormat {
+    /// The file or folder name written for a bundle.
+    #[must_use]
+    pub fn file_name(self, zip_spec: &ZipSpec) -> String {
+        self.bundle_file_name(zip_spec.bundle_name())
+    }
+
+    #[must_use]
+    pub fn bundle_file_name(self, bundle_name: &str) -> String {
+        match self {
+            Self::Zip => format!("{bundle_name}.zip"),
+            Self::Directory(_) => bundle_name.to_owned(),
+            Self::SevenZip { .. } => format!("{bundle_name}.7z"),
+        }
+    }
+}
+
+/// How bare source files are placed into directory outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LinkMode {
+    #[default]
+    Copy,
+    /// Hardlink, falling back to a copy across filesystems.
+    Hardlink,
+    /// Share extents where the filesystem supports it, otherwise copy.
+    Reflink,
+}
+
+/// What a build does with source files whose every entry went into a
+/// verified output.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum AfterBuild {
+    #[default]
+    Keep,
+    /// Move them below this directory, at their path under the source root.
+    Move(Utf8PathBuf),
+    Delete,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SourceKind {
     BareFile,
     ZipEntry,
+    GzipEntry,
+    TarEntry,
+    TarGzEntry,
     ArchiveEntry,
+    NestedEntry,
 }
 
 impl SourceKind {
@@ -46,7 +371,11 @@ impl SourceKind {
         match self {
             Self::BareFile => 0,
             Self::ZipEntry => 1,
-            Self::ArchiveEntry => 2,
+            Self::GzipEntry => 2,
+            Self::TarEntry => 3,
+            Self::TarGzEntry => 4,
+            Self::ArchiveEntry => 5,
+            Self::NestedEntry => 6,
         }
     }
 }
@@ -56,6 +385,9 @@ pub struct SourceFile {
     pub source_root: String,
     pub canonical_path: String,
     pub entry_name: Option<String>,
+    /// Entry names of archives nested inside `canonical_path` that must be
+    /// opened, outermost first, before `entry_name` can be read.
+    pub archive_chain: Vec<String>,
     pub sha1: Sha1Digest,
     pub kind: SourceKind,
 }
@@ -65,16 +397,66 @@ impl SourceFile {
     pub fn display_name(&self) -> String {
         self.entry_name.as_ref().map_or_else(
             || self.canonical_path.clone(),
-            |entry| format!("{}:{entry}", self.canonical_path),
+            |entry| {
+                std::iter::once(self.canonical_path.as_str())
+                    .chain(self.archive_chain.iter().map(String::as_str))
+                    .chain(std::iter::once(entry.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(":")
+            },
         )
     }
 }
 
+/// A scanned source file with the size and checksums a DAT records for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScannedRom {
+    pub source: SourceFile,
+    pub size: u64,
+    pub crc32: Crc32Digest,
+    pub md5: Md5Digest,
+}
+
+/// How to choose between several source files that match the same ROM.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SourcePreference {
+    /// Bare files, then ZIP entries, then other containers.
+    #[default]
+    Kind,
+    /// ZIP entries first, since they can be copied without recompressing.
+    Zip,
+    /// Files whose path below the source root matches the glob first.
+    PathGlob(glob::Pattern),
+    /// Files that supply the most ROMs for the build first, so fewer
+    /// archives are opened overall.
+    FewestArchives,
+}
+
+impl SourcePreference {
+    pub fn path_glob(pattern: &str) -> crate::Result<Self> {
+        Ok(Self::PathGlob(glob::Pattern::new(pattern)?))
+    }
+}
+
+impl fmt::Display for SourcePreference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Kind => f.write_str("kind"),
+            Self::Zip => f.write_str("zip"),
+            Self::PathGlob(pattern) => write!(f, "path-glob {pattern}"),
+            Self::FewestArchives => f.write_str("fewest-archives"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BuildRequest {
     pub dat_name: String,
-    pub source_root: String,
+    /// Only source files below one of these roots are matched.
+    pub source_roots: Vec<String>,
     pub mode: BuildMode,
+    pub nodump: NodumpPolicy,
+    pub prefer: SourcePreference,
     pub dry_run: bool,
     pub strict: bool,
 }
@@ -99,6 +481,16 @@ pub struct ZipSpec {
     pub entries: Vec<ZipEntrySpec>,
 }
 
+impl ZipSpec {
+    /// The bundle name, without the `.zip` extension.
+    #[must_use]
+    pub fn bundle_name(&self) -> &str {
+        self.file_name
+            .strip_suffix(".zip")
+            .unwrap_or(&self.file_name)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ZipEntrySpec {
     pub output_name: String,
@@ -110,6 +502,12 @@ pub struct BuildReport {
     pub missing_roms: Vec<MissingRom>,
     pub duplicate_matches: Vec<DuplicateMatch>,
     pub matched_roms: usize,
+    /// Samples only count when a build writes sample sets. Missing samples
+    /// never fail a build.
+    pub missing_samples: Vec<MissingSample>,
+    pub matched_samples: usize,
+    /// How the 1G1R layout chose each family's game.
+    pub family_choices: Vec<FamilyChoice>,
     pub exit_code: i32,
 }
 
@@ -120,9 +518,205 @@ pub struct MissingRom {
     pub sha1: Sha1Digest,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingSample {
+    pub sample_set: String,
+    pub sample_name: String,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DuplicateMatch {
     pub rom_name: String,
     pub selected: SourceFile,
     pub candidates: Vec<SourceFile>,
 }
+
+/// One imported version of a DAT.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DatSummary {
+    pub name: String,
+    pub version: Option<String>,
+    /// Hex SHA1 of the imported file, when it was read from one.
+    pub sha1: Option<String>,
+    /// Whether this is the most recent import of the DAT's name, which builds
+    /// use unless a version is pinned.
+    pub latest: bool,
+    /// Imported from a MAME software list, so built into a directory named
+    /// after the list.
+    pub software_list: bool,
+    pub file_name: Option<String>,
+    pub games: usize,
+    pub roms: usize,
+    pub imported_at: Option<NaiveDateTime>,
+}
+
+/// A source root scanned into the cache.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SourceSummary {
+    pub path: String,
+    pub rom_files: usize,
+    pub archives: usize,
+    pub scanned_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub rom_files: usize,
+    pub matched_rom_files: usize,
+    pub unmatched_rom_files: usize,
+    pub dats: Vec<DatCompleteness>,
+}
+
+/// How many of a DAT's ROMs some scanned source file can supply.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DatCompleteness {
+    pub name: String,
+    pub roms: usize,
+    pub available_roms: usize,
+}
+
+impl DatCompleteness {
+    /// Whole percentage of available ROMs; an empty DAT counts as complete.
+    #[must_use]
+    pub fn percent(&self) -> usize {
+        (self.available_roms * 100)
+            .checked_div(self.roms)
+            .unwrap_or(100)
+    }
+}
+
+/// What `cache find` searches DAT ROMs and scanned source files for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RomQuery {
+    Sha1(Sha1Digest),
+    Crc32([u8; 4]),
+    Md5([u8; 16]),
+    /// Glob over ROM names and source entry names.
+    Name(glob::Pattern),
+}
+
+impl RomQuery {
+    pub fn sha1(value: &str) -> crate::Result<Self> {
+        decode_digest("SHA1", value).map(Self::Sha1)
+    }
+
+    pub fn crc32(value: &str) -> crate::Result<Self> {
+        decode_digest("CRC32", value).map(Self::Crc32)
+    }
+
+    pub fn md5(value: &str) -> crate::Result<Self> {
+        decode_digest("MD5", value).map(Self::Md5)
+    }
+
+    pub fn name(pattern: &str) -> crate::Result<Self> {
+        Ok(Self::Name(glob::Pattern::new(pattern)?))
+    }
+}
+
+fn decode_digest<const N: usize>(kind: &str, value: &str) -> crate::Result<[u8; N]> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            crate::Error::InvalidHash(format!("{kind} must be {} hex digits: {value}", N * 2))
+        })
+}
+
+/// DAT ROMs that expect a queried ROM and source files that provide it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RomLookup {
+    pub expected: Vec<ExpectedRom>,
+    pub sources: Vec<ProvidedRom>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ExpectedRom {
+    pub dat_name: String,
+    pub game_name: String,
+    pub rom_name: String,
+    #[serde(serialize_with = "hex::serialize")]
+    pub sha1: Sha1Digest,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ProvidedRom {
+    /// The source file's [`SourceFile::display_name`].
+    pub location: String,
+    #[serde(serialize_with = "hex::serialize")]
+    pub sha1: Sha1Digest,
+}
+
+/// What changed between two versions of a DAT. Games are matched by name,
+/// then removed and added games with the same ROM hashes count as renamed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DatDiff {
+    pub added_games: Vec<String>,
+    pub removed_games: Vec<String>,
+    pub renamed_games: Vec<GameRename>,
+    pub rom_changes: Vec<RomChange>,
+    pub parent_changes: Vec<ParentChange>,
+}
+
+impl DatDiff {
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.added_games.is_empty()
+            && self.removed_games.is_empty()
+            && self.renamed_games.is_empty()
+            && self.rom_changes.is_empty()
+            && self.parent_changes.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GameRename {
+    pub from: String,
+    pub to: String,
+}
+
+/// A ROM of a game present in both DATs that was added, removed, or whose
+/// hashes changed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RomChange {
+    /// The game's name in the newer DAT.
+    pub game_name: String,
+    pub rom_name: String,
+    pub old: Option<DatRomHashes>,
+    pub new: Option<DatRomHashes>,
+}
+
+/// A ROM's size and hex-encoded hashes as a DAT lists them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DatRomHashes {
+    pub size: i64,
+    pub crc: String,
+    pub md5: String,
+    pub sha1: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ParentChange {
+    /// The game's name in the newer DAT.
+    pub game_name: String,
+    pub old_parent: Option<String>,
+    pub new_parent: Option<String>,
+}
+
+/// A problem `dat check` found in a DAT.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DatIssue {
+    pub severity: IssueSeverity,
+    /// The line the game or ROM element starts on.
+    pub line: Option<usize>,
+    pub game_name: String,
+    pub rom_name: Option<String>,
+    pub message: String,
+}
+
+/// Errors fail `dat check`; warnings are reported without failing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueSeverity {
+    Error,
+    Warning,
+}
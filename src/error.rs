@@ -8,6 +8,9 @@ pub enum Error {
     #[error("XML parse error: {0}")]
     Xml(#[from] serde_xml_rs::Error),
 
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
     #[error("Diesel error: {0}")]
     Diesel(#[from] diesel::result::Error),
 
@@ -29,9 +32,33 @@ pub enum Error {
     #[error("Invalid path: {0}")]
     InvalidPath(String),
 
+    #[error("Invalid glob pattern: {0}")]
+    Pattern(#[from] glob::PatternError),
+
+    #[error("Invalid regular expression: {0}")]
+    Regex(#[from] regex::Error),
+
+    #[error("Not in cache: {0}")]
+    NotInCache(String),
+
     #[error("Invalid hash: {0}")]
     InvalidHash(String),
 
+    #[error("ROM too large: {0}")]
+    RomSize(String),
+
+    #[error("Invalid compression: {0}")]
+    Compression(String),
+
+    #[error("Verification failed: {0}")]
+    Verification(String),
+
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+
+    #[error("Config error: {0}")]
+    Config(String),
+
     #[error("Migration error: {0}")]
     Migration(String),
 
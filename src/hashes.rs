@@ -1,11 +1,68 @@
+use std::io::{Read, Write};
+
 use camino::Utf8Path;
 use fmmap::{MmapFile, MmapFileExt};
 
+use md5::Md5;
 use sha1::{Digest, Sha1};
 use xxhash_rust::xxh3::Xxh3;
 
 pub type Sha1Digest = [u8; 20];
 pub type Xxh3Digest = [u8; 8];
+pub type Crc32Digest = [u8; 4];
+pub type Md5Digest = [u8; 16];
+
+/// Every hash a scanned ROM file is recorded with, plus its size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RomHashes {
+    pub sha1: Sha1Digest,
+    pub xxhash3: Xxh3Digest,
+    pub crc32: Crc32Digest,
+    pub md5: Md5Digest,
+    pub size: u64,
+}
+
+/// Computes [`RomHashes`] over everything written to it.
+#[derive(Default)]
+pub struct RomHasher {
+    sha1: Sha1,
+    xxhash3: Xxh3,
+    crc32: crc32fast::Hasher,
+    md5: Md5,
+    size: u64,
+}
+
+impl RomHasher {
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.sha1.update(chunk);
+        self.xxhash3.update(chunk);
+        self.crc32.update(chunk);
+        self.md5.update(chunk);
+        self.size += chunk.len() as u64;
+    }
+
+    #[must_use]
+    pub fn finish(self) -> RomHashes {
+        RomHashes {
+            sha1: self.sha1.finalize().into(),
+            xxhash3: self.xxhash3.digest().to_be_bytes(),
+            crc32: self.crc32.finalize().to_be_bytes(),
+            md5: self.md5.finalize().into(),
+            size: self.size,
+        }
+    }
+}
+
+impl Write for RomHasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
 
 pub fn stream_sha1(mmap: &MmapFile) -> Sha1Digest {
     let mut sha1 = Sha1::new();
@@ -40,6 +97,29 @@ pub fn xxhash3_bytes(data: &[u8]) -> Xxh3Digest {
     xxhash3.digest().to_be_bytes()
 }
 
+#[must_use]
+pub fn rom_hashes(data: &[u8]) -> RomHashes {
+    let mut hasher = RomHasher::default();
+    data.chunks(0x4000).for_each(|chunk| hasher.update(chunk));
+    hasher.finish()
+}
+
+/// Hash everything `reader` yields without holding it in memory.
+pub fn hash_reader_chunks<R: Read>(mut reader: R) -> crate::Result<RomHashes> {
+    let mut hasher = RomHasher::default();
+    let mut scratch = vec![0_u8; 64 * 1024];
+
+    loop {
+        let read = reader.read(&mut scratch)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&scratch[..read]);
+    }
+
+    Ok(hasher.finish())
+}
+
 pub fn mmap_path(path: &Utf8Path) -> crate::Result<MmapFile> {
     MmapFile::open(path).map_err(|e| crate::Error::Mmap(e.to_string()))
 }
@@ -77,6 +157,15 @@ mod tests {
         assert_eq!(xxhash3_bytes(b""), xxhash3_bytes(b""));
     }
 
+    #[test]
+    fn rom_hashes_match_logiqx_crc32_and_md5() {
+        let hashes = rom_hashes(b"abc");
+        assert_eq!(hex::encode(hashes.crc32), "352441c2");
+        assert_eq!(hex::encode(hashes.md5), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hashes.sha1, sha1_bytes(b"abc"));
+        assert_eq!(hashes.size, 3);
+    }
+
     #[test]
     fn xxhash3_different_inputs() {
         assert_ne!(xxhash3_bytes(b"abc"), xxhash3_bytes(b"def"));
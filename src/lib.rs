@@ -1,4 +1,5 @@
 pub mod app;
+mod archive;
 mod build;
 pub mod database;
 pub mod domain;
@@ -20,4 +21,54 @@ pub mod test_helpers {
     pub fn in_memory_pool() -> crate::Result<Pool> {
         create_db_pool(":memory:")
     }
+
+    /// A tar archive holding `entries`.
+    pub fn tar_bytes(entries: &[(&str, &[u8])]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data)?;
+        }
+        Ok(builder.into_inner()?)
+    }
+
+    /// A gzip stream of `data`, naming it `header_name` in the gzip header.
+    pub fn gzip_bytes(
+        data: &[u8],
+        header_name: Option<&str>,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        use std::io::Write;
+
+        let builder = header_name.map_or_else(flate2::GzBuilder::new, |name| {
+            flate2::GzBuilder::new().filename(name)
+        });
+        let mut encoder = builder.write(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data)?;
+        Ok(encoder.finish()?)
+    }
+
+    /// A zip archive holding `entries`.
+    pub fn zip_bytes(entries: &[(&str, &[u8])]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        use std::io::Write;
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, data) in entries {
+            zip.start_file(*name, options)?;
+            zip.write_all(data)?;
+        }
+        Ok(zip.finish()?.into_inner())
+    }
+
+    /// Write a RAR archive holding a single `VERSION` entry to `path`.
+    pub fn write_version_rar(path: &camino::Utf8Path) -> Result<(), Box<dyn std::error::Error>> {
+        let archive = hex::decode(
+            "526172211a0700cf907300000d000000000000000f0c7420802700150000000b0000000345f37dc6a48a07471d330700a481000056455253494f4e0c008fec8a45cc23c848088362fe5fdd5c5388f072c43d7b00400700",
+        )?;
+        std::fs::write(path, archive)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,363 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Deserialize;
+
+use crate::domain::{DatIssue, IssueSeverity};
+
+const EMPTY_CRC: &str = "00000000";
+const EMPTY_MD5: &str = "d41d8cd98f00b204e9800998ecf8427e";
+const EMPTY_SHA1: &str = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+
+/// The parts of a DAT that [`check`] looks at. Hashes are kept as the text
+/// the DAT gives, so bad hex is reported instead of failing the parse.
+#[derive(Debug, Deserialize)]
+struct CheckedDat {
+    #[serde(rename = "game", default)]
+    games: Vec<CheckedGame>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckedGame {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@cloneof", default)]
+    cloneof: Option<String>,
+    #[serde(rename = "@romof", default)]
+    romof: String,
+    #[serde(rename = "rom", default)]
+    roms: Vec<CheckedRom>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckedRom {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@size")]
+    size: i32,
+    #[serde(rename = "@crc", default)]
+    crc: String,
+    #[serde(rename = "@md5", default)]
+    md5: String,
+    #[serde(rename = "@sha1", default)]
+    sha1: String,
+    #[serde(rename = "@status", default)]
+    status: String,
+}
+
+/// Report problems in a Logiqx DAT that would otherwise surface as confusing
+/// build errors, with the line each game and ROM starts on.
+pub fn check(xml: &str) -> crate::Result<Vec<DatIssue>> {
+    let data_file: CheckedDat = serde_xml_rs::from_str(xml)?;
+    let lines = element_lines(xml);
+    let games = data_file
+        .games
+        .iter()
+        .fold(BTreeMap::new(), |mut games, game| {
+            games.entry(game.name.as_str()).or_insert(game);
+            games
+        });
+
+    let mut issues = Vec::new();
+    let mut seen_games = BTreeSet::new();
+    for (index, game) in data_file.games.iter().enumerate() {
+        let (game_line, rom_lines) = lines
+            .get(index)
+            .map_or((None, None), |(line, roms)| (Some(*line), Some(roms)));
+        let mut issue = |severity, rom: Option<(&CheckedRom, usize)>, message: String| {
+            issues.push(DatIssue {
+                severity,
+                line: rom.map_or(game_line, |(_, index)| {
+                    rom_lines.and_then(|lines| lines.get(index).copied())
+                }),
+                game_name: game.name.clone(),
+                rom_name: rom.map(|(rom, _)| rom.name.clone()),
+                message,
+            });
+        };
+
+        if !seen_games.insert(game.name.as_str()) {
+            issue(IssueSeverity::Error, None, "duplicate game name".to_owned());
+        }
+        if let Some(parent) = game.cloneof.as_deref() {
+            match games.get(parent) {
+                _ if parent == game.name => {
+                    issue(
+                        IssueSeverity::Error,
+                        None,
+                        "game is a clone of itself".to_owned(),
+                    );
+                }
+                None => issue(
+                    IssueSeverity::Error,
+                    None,
+                    format!("cloneof {parent} is not a game in the DAT"),
+                ),
+                Some(parent_game) => {
+                    if let Some(grandparent) = &parent_game.cloneof {
+                        issue(
+                            IssueSeverity::Error,
+                            None,
+                            format!("cloneof {parent}, which is itself a clone of {grandparent}"),
+                        );
+                    }
+                }
+            }
+        }
+        if !game.romof.is_empty() && !games.contains_key(game.romof.as_str()) {
+            issue(
+                IssueSeverity::Warning,
+                None,
+                format!("romof {} is not a game in the DAT", game.romof),
+            );
+        }
+
+        let mut seen_roms = BTreeMap::new();
+        for (rom_index, rom) in game.roms.iter().enumerate() {
+            if let Some(first) = seen_roms.insert(rom.name.as_str(), rom) {
+                if same_hashes(first, rom) {
+                    issue(
+                        IssueSeverity::Warning,
+                        Some((rom, rom_index)),
+                        "ROM is listed twice in the game".to_owned(),
+                    );
+                } else {
+                    issue(
+                        IssueSeverity::Error,
+                        Some((rom, rom_index)),
+                        "duplicate ROM name with different hashes".to_owned(),
+                    );
+                }
+            }
+            for message in rom_problems(rom) {
+                issue(IssueSeverity::Error, Some((rom, rom_index)), message);
+            }
+        }
+    }
+    Ok(issues)
+}
+
+fn same_hashes(left: &CheckedRom, right: &CheckedRom) -> bool {
+    left.size == right.size
+        && left.crc.eq_ignore_ascii_case(&right.crc)
+        && left.md5.eq_ignore_ascii_case(&right.md5)
+        && left.sha1.eq_ignore_ascii_case(&right.sha1)
+}
+
+fn rom_problems(rom: &CheckedRom) -> Vec<String> {
+    let mut problems = Vec::new();
+    if rom.size < 0 {
+        problems.push(format!("negative size {}", rom.size));
+    }
+    // ROMs known to be undumped have no hashes to check.
+    if rom.status == "nodump" {
+        return problems;
+    }
+    let hashes = [
+        ("crc", &rom.crc, EMPTY_CRC),
+        ("md5", &rom.md5, EMPTY_MD5),
+        ("sha1", &rom.sha1, EMPTY_SHA1),
+    ];
+    // Many DATs give only some hashes; only those given are checked.
+    for (kind, digest, empty) in hashes
+        .into_iter()
+        .filter(|(_, digest, _)| !digest.is_empty())
+    {
+        if !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            problems.push(format!("{kind} {digest} is not hexadecimal"));
+        } else if digest.len() != empty.len() {
+            problems.push(format!(
+                "{kind} has {} hex digits, expected {}",
+                digest.len(),
+                empty.len()
+            ));
+        } else if rom.size == 0 && !digest.eq_ignore_ascii_case(empty) {
+            problems.push(format!(
+                "zero-size ROM has {kind} {}, expected {empty}",
+                digest.to_ascii_lowercase()
+            ));
+        }
+    }
+    problems
+}
+
+/// The line of each `<game>` element and of the `<rom>` elements inside it,
+/// in document order, which is the order [`CheckedDat`] parses them in.
+/// Comments are skipped.
+fn element_lines(xml: &str) -> Vec<(usize, Vec<usize>)> {
+    let mut elements: Vec<(usize, Vec<usize>)> = Vec::new();
+    let mut line = 1;
+    let mut position = 0;
+    while let Some(offset) = xml[position..].find('<') {
+        line += xml[position..position + offset].matches('\n').count();
+        position += offset;
+        let rest = &xml[position..];
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map_or(rest.len(), |end| end + 3);
+            line += rest[..end].matches('\n').count();
+            position += end;
+            continue;
+        }
+        if is_start_tag(rest, "game") {
+            elements.push((line, Vec::new()));
+        } else if is_start_tag(rest, "rom")
+            && let Some((_, roms)) = elements.last_mut()
+        {
+            roms.push(line);
+        }
+        position += 1;
+    }
+    elements
+}
+
+fn is_start_tag(rest: &str, element: &str) -> bool {
+    rest[1..].strip_prefix(element).is_some_and(|after| {
+        after
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_whitespace() || c == '>' || c == '/')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BROKEN_DAT: &str = r#"<?xml version="1.0"?>
+<datafile>
+  <header><name>Check Test</name></header>
+  <!-- <game name="commented"> -->
+  <game name="parent">
+    <rom name="a.rom" size="3" sha1="a9993e364706816aba3e25717850c26c9cd0d89d" md5="900150983cd24fb0d6963f7d28e17f72" crc="352441c2"/>
+    <rom name="a.rom" size="3" sha1="a9993e364706816aba3e25717850c26c9cd0d89d" md5="900150983cd24fb0d6963f7d28e17f72" crc="352441c2"/>
+    <rom name="empty.rom" size="0" sha1="da39a3ee5e6b4b0d3255bfef95601890afd80709" md5="d41d8cd98f00b204e9800998ecf8427e" crc="00000001"/>
+  </game>
+  <game name="clone" cloneof="parent"><rom name="b.rom" size="1" sha1="00000000000000000000000000000000000000" md5="00000000000000000000000000000001" crc="00000001"/></game>
+  <game name="grandclone" cloneof="clone">
+    <rom name="c.rom" size="1" sha1="0000000000000000000000000000000000000002" md5="00000000000000000000000000000002" crc="00000002"/>
+    <rom name="c.rom" size="1" sha1="0000000000000000000000000000000000000003" md5="00000000000000000000000000000003" crc="00000003"/>
+  </game>
+  <game name="orphan" cloneof="missing" romof="missing"/>
+  <game name="parent"/>
+</datafile>"#;
+
+    #[test]
+    fn check_reports_each_problem_with_its_line() -> Result<(), Box<dyn std::error::Error>> {
+        let issues = check(BROKEN_DAT)?
+            .into_iter()
+            .map(|issue| {
+                (
+                    issue.severity,
+                    issue.line,
+                    issue.game_name,
+                    issue.rom_name,
+                    issue.message,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let error = IssueSeverity::Error;
+        let warning = IssueSeverity::Warning;
+        let rom = |name: &str| Some(name.to_owned());
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    warning,
+                    Some(7),
+                    "parent".to_owned(),
+                    rom("a.rom"),
+                    "ROM is listed twice in the game".to_owned()
+                ),
+                (
+                    error,
+                    Some(8),
+                    "parent".to_owned(),
+                    rom("empty.rom"),
+                    "zero-size ROM has crc 00000001, expected 00000000".to_owned()
+                ),
+                (
+                    error,
+                    Some(10),
+                    "clone".to_owned(),
+                    rom("b.rom"),
+                    "sha1 has 38 hex digits, expected 40".to_owned()
+                ),
+                (
+                    error,
+                    Some(11),
+                    "grandclone".to_owned(),
+                    None,
+                    "cloneof clone, which is itself a clone of parent".to_owned()
+                ),
+                (
+                    error,
+                    Some(13),
+                    "grandclone".to_owned(),
+                    rom("c.rom"),
+                    "duplicate ROM name with different hashes".to_owned()
+                ),
+                (
+                    error,
+                    Some(15),
+                    "orphan".to_owned(),
+                    None,
+                    "cloneof missing is not a game in the DAT".to_owned()
+                ),
+                (
+                    warning,
+                    Some(15),
+                    "orphan".to_owned(),
+                    None,
+                    "romof missing is not a game in the DAT".to_owned()
+                ),
+                (
+                    error,
+                    Some(16),
+                    "parent".to_owned(),
+                    None,
+                    "duplicate game name".to_owned()
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn check_reports_hashes_that_are_not_hexadecimal() -> Result<(), Box<dyn std::error::Error>> {
+        let xml = r#"<datafile>
+  <header><name>Bad Hash</name></header>
+  <game name="game">
+    <rom name="ok.rom" size="3" crc="352441C2" md5="900150983cd24fb0d6963f7d28e17f72" sha1="a9993e364706816aba3e25717850c26c9cd0d89d"/>
+    <rom name="bad.rom" size="3" crc="3524zzc2" md5="900150983cd24fb0d6963f7d28e17f72" sha1="a9993e364706816aba3e25717850c26c9cd0d89d"/>
+  </game>
+</datafile>"#;
+
+        let issues = check(xml)?;
+
+        assert_eq!(
+            issues,
+            vec![DatIssue {
+                severity: IssueSeverity::Error,
+                line: Some(5),
+                game_name: "game".to_owned(),
+                rom_name: Some("bad.rom".to_owned()),
+                message: "crc 3524zzc2 is not hexadecimal".to_owned(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn check_accepts_roms_with_only_crc_and_sha1() -> Result<(), Box<dyn std::error::Error>> {
+        let xml = r#"<datafile>
+  <header><name>Partial Hashes</name></header>
+  <game name="game">
+    <rom name="a.rom" size="3" crc="352441c2" sha1="a9993e364706816aba3e25717850c26c9cd0d89d"/>
+    <rom name="empty.rom" size="0" crc="00000000" sha1="da39a3ee5e6b4b0d3255bfef95601890afd80709"/>
+  </game>
+</datafile>"#;
+
+        assert_eq!(check(xml)?, Vec::new());
+        Ok(())
+    }
+}
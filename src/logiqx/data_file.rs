@@ -1,4 +1,4 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
 use camino::Utf8Path;
 use fmmap::MmapFileExt;
@@ -6,10 +6,14 @@ use serde::Deserialize;
 
 use super::game::Game;
 use super::header::Header;
+use super::{software_list, xml};
 
 use crate::hashes;
 
-#[derive(Debug, Deserialize)]
+/// How much of a file is read to tell a software list from a Logiqx DAT.
+const SNIFF_LENGTH: usize = 4096;
+
+#[derive(Debug, Default, Deserialize)]
 pub struct DataFile {
     file_name: Option<String>,
     #[serde(rename = "@build", default)]
@@ -20,13 +24,45 @@ pub struct DataFile {
     sha1: Option<Vec<u8>>,
     #[serde(rename = "game", default)]
     games: Vec<Game>,
+    #[serde(skip)]
+    software_list: bool,
 }
 impl DataFile {
+    #[must_use]
+    pub fn new(header: Header, games: Vec<Game>) -> Self {
+        Self {
+            header,
+            games,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_build(mut self, build: Option<String>) -> Self {
+        self.build = build;
+        self
+    }
+
+    #[must_use]
+    pub fn with_debug(mut self, debug: Option<String>) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Mark the data file as read from a MAME software list, whose builds go
+    /// in a directory named after the list.
+    #[must_use]
+    pub const fn with_software_list(mut self) -> Self {
+        self.software_list = true;
+        self
+    }
+
     pub fn from_reader<R: Read>(reader: R) -> crate::Result<Self> {
         let data_file: Self = serde_xml_rs::from_reader(reader)?;
         Ok(data_file)
     }
 
+    /// Read a Logiqx DAT, or a MAME software list.
     pub fn from_path(path: &Utf8Path) -> crate::Result<Self> {
         let mmap = hashes::mmap_path(path)?;
         let sha1 = hashes::stream_sha1(&mmap).to_vec();
@@ -34,7 +70,12 @@ impl DataFile {
             .reader(0)
             .map_err(|e| crate::Error::Mmap(e.to_string()))?;
 
-        let mut data_file = Self::from_reader(reader)?;
+        let prefix = &mmap.as_slice()[..mmap.len().min(SNIFF_LENGTH)];
+        let mut data_file = if software_list::is_software_list(prefix) {
+            software_list::from_reader(reader)?
+        } else {
+            Self::from_reader(reader)?
+        };
         data_file.file_name = path
             .canonicalize()
             .ok()
@@ -43,6 +84,13 @@ impl DataFile {
         Ok(data_file)
     }
 
+    /// Write the data file as Logiqx XML that [`Self::from_reader`] reads
+    /// back.
+    pub fn write_to<W: Write>(&self, writer: W) -> crate::Result<()> {
+        xml::write_data_file(self, writer)?;
+        Ok(())
+    }
+
     /// Get a reference to the data file's header.
     #[must_use]
     pub const fn header(&self) -> &Header {
@@ -78,6 +126,12 @@ impl DataFile {
     pub fn debug(&self) -> Option<&str> {
         self.debug.as_deref()
     }
+
+    /// Whether the data file was read from a MAME software list.
+    #[must_use]
+    pub const fn is_software_list(&self) -> bool {
+        self.software_list
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+
+use super::{DataFile, Game, Rom};
+use crate::domain::{DatDiff, DatRomHashes, GameRename, ParentChange, RomChange};
+
+/// Compare two DATs. A removed game whose ROM hashes match an added game's
+/// is reported as renamed, and a parent that was renamed along with it is
+/// not a parent change.
+#[must_use]
+pub fn diff(old: &DataFile, new: &DataFile) -> DatDiff {
+    let old_games = games_by_name(old);
+    let new_games = games_by_name(new);
+
+    let mut added_by_hashes = new_games
+        .values()
+        .filter(|game| !old_games.contains_key(game.name()))
+        .fold(BTreeMap::<_, Vec<&Game>>::new(), |mut added, game| {
+            added.entry(sha1s(game)).or_default().push(game);
+            added
+        });
+    let mut renames = BTreeMap::new();
+    let mut removed_games = Vec::new();
+    for game in old_games
+        .values()
+        .filter(|game| !new_games.contains_key(game.name()))
+    {
+        let hashes = sha1s(game);
+        let renamed_to = added_by_hashes
+            .get_mut(&hashes)
+            .filter(|_| !hashes.is_empty())
+            .and_then(|candidates| (!candidates.is_empty()).then(|| candidates.remove(0)));
+        match renamed_to {
+            Some(renamed_to) => {
+                renames.insert(game.name(), renamed_to);
+            }
+            None => removed_games.push(game.name().to_owned()),
+        }
+    }
+    let mut added_games = added_by_hashes
+        .into_values()
+        .flatten()
+        .map(|game| game.name().to_owned())
+        .collect::<Vec<_>>();
+    added_games.sort_unstable();
+
+    let pairs = old_games
+        .values()
+        .filter_map(|old_game| {
+            new_games
+                .get(old_game.name())
+                .or_else(|| renames.get(old_game.name()))
+                .map(|new_game| (*old_game, *new_game))
+        })
+        .collect::<Vec<_>>();
+    let rom_changes = pairs
+        .iter()
+        .flat_map(|(old_game, new_game)| rom_changes(old_game, new_game))
+        .collect();
+    let parent_changes = pairs
+        .iter()
+        .filter_map(|(old_game, new_game)| {
+            let old_parent = old_game.cloneof().map(|parent| {
+                renames
+                    .get(parent)
+                    .map_or(parent, |renamed_to| renamed_to.name())
+            });
+            (old_parent != new_game.cloneof()).then(|| ParentChange {
+                game_name: new_game.name().to_owned(),
+                old_parent: old_game.cloneof().map(str::to_owned),
+                new_parent: new_game.cloneof().map(str::to_owned),
+            })
+        })
+        .collect();
+
+    DatDiff {
+        added_games,
+        removed_games,
+        renamed_games: renames
+            .into_iter()
+            .map(|(from, to)| GameRename {
+                from: from.to_owned(),
+                to: to.name().to_owned(),
+            })
+            .collect(),
+        rom_changes,
+        parent_changes,
+    }
+}
+
+/// The first game of each name, in name order.
+fn games_by_name(data_file: &DataFile) -> BTreeMap<&str, &Game> {
+    data_file
+        .games()
+        .iter()
+        .fold(BTreeMap::new(), |mut games, game| {
+            games.entry(game.name()).or_insert(game);
+            games
+        })
+}
+
+fn sha1s(game: &Game) -> Vec<&[u8]> {
+    let mut sha1s = game.roms().iter().map(Rom::sha1).collect::<Vec<_>>();
+    sha1s.sort_unstable();
+    sha1s
+}
+
+fn rom_changes(old_game: &Game, new_game: &Game) -> Vec<RomChange> {
+    let mut roms = BTreeMap::<&str, (Option<&Rom>, Option<&Rom>)>::new();
+    for rom in old_game.roms() {
+        roms.entry(rom.name()).or_default().0 = Some(rom);
+    }
+    for rom in new_game.roms() {
+        roms.entry(rom.name()).or_default().1 = Some(rom);
+    }
+    roms.into_iter()
+        .filter_map(|(rom_name, (old, new))| {
+            let old = old.map(rom_hashes);
+            let new = new.map(rom_hashes);
+            (old != new).then(|| RomChange {
+                game_name: new_game.name().to_owned(),
+                rom_name: rom_name.to_owned(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+fn rom_hashes(rom: &Rom) -> DatRomHashes {
+    DatRomHashes {
+        size: i64::from(rom.size()),
+        crc: hex::encode(rom.crc()),
+        md5: hex::encode(rom.md5()),
+        sha1: hex::encode(rom.sha1()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD_DAT: &str = r#"<?xml version="1.0"?>
+<datafile>
+  <header><name>Diff Test</name></header>
+  <game name="kept">
+    <rom name="kept.rom" size="3" sha1="a9993e364706816aba3e25717850c26c9cd0d89d" md5="900150983cd24fb0d6963f7d28e17f72" crc="352441c2"/>
+    <rom name="dropped.rom" size="1" sha1="0000000000000000000000000000000000000001" md5="00000000000000000000000000000001" crc="00000001"/>
+  </game>
+  <game name="oldname">
+    <rom name="moved.rom" size="1" sha1="0000000000000000000000000000000000000002" md5="00000000000000000000000000000002" crc="00000002"/>
+  </game>
+  <game name="clone" cloneof="oldname">
+    <rom name="clone.rom" size="1" sha1="0000000000000000000000000000000000000003" md5="00000000000000000000000000000003" crc="00000003"/>
+  </game>
+  <game name="orphan" cloneof="kept">
+    <rom name="orphan.rom" size="1" sha1="0000000000000000000000000000000000000004" md5="00000000000000000000000000000004" crc="00000004"/>
+  </game>
+  <game name="gone">
+    <rom name="gone.rom" size="1" sha1="0000000000000000000000000000000000000005" md5="00000000000000000000000000000005" crc="00000005"/>
+  </game>
+</datafile>"#;
+
+    const NEW_DAT: &str = r#"<?xml version="1.0"?>
+<datafile>
+  <header><name>Diff Test</name></header>
+  <game name="kept">
+    <rom name="kept.rom" size="3" sha1="84983e441c3bd26ebaae4aa1f575527d004816f2" md5="f96b697d7cb7938d525a2f31aaf161d0" crc="bbccddee"/>
+    <rom name="new.rom" size="1" sha1="0000000000000000000000000000000000000006" md5="00000000000000000000000000000006" crc="00000006"/>
+  </game>
+  <game name="newname">
+    <rom name="moved.rom" size="1" sha1="0000000000000000000000000000000000000002" md5="00000000000000000000000000000002" crc="00000002"/>
+  </game>
+  <game name="clone" cloneof="newname">
+    <rom name="clone.rom" size="1" sha1="0000000000000000000000000000000000000003" md5="00000000000000000000000000000003" crc="00000003"/>
+  </game>
+  <game name="orphan">
+    <rom name="orphan.rom" size="1" sha1="0000000000000000000000000000000000000004" md5="00000000000000000000000000000004" crc="00000004"/>
+  </game>
+  <game name="fresh">
+    <rom name="fresh.rom" size="1" sha1="0000000000000000000000000000000000000007" md5="00000000000000000000000000000007" crc="00000007"/>
+  </game>
+</datafile>"#;
+
+    #[test]
+    fn diff_reports_renames_rom_and_parent_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let old = DataFile::from_reader(OLD_DAT.as_bytes())?;
+        let new = DataFile::from_reader(NEW_DAT.as_bytes())?;
+
+        let diff = diff(&old, &new);
+
+        assert_eq!(diff.added_games, vec!["fresh"]);
+        assert_eq!(diff.removed_games, vec!["gone"]);
+        assert_eq!(
+            diff.renamed_games,
+            vec![GameRename {
+                from: "oldname".to_owned(),
+                to: "newname".to_owned(),
+            }]
+        );
+        assert_eq!(
+            diff.rom_changes
+                .iter()
+                .map(|change| (
+                    change.game_name.as_str(),
+                    change.rom_name.as_str(),
+                    change.old.as_ref().map(|hashes| hashes.crc.as_str()),
+                    change.new.as_ref().map(|hashes| hashes.crc.as_str()),
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("kept", "dropped.rom", Some("00000001"), None),
+                ("kept", "kept.rom", Some("352441c2"), Some("bbccddee")),
+                ("kept", "new.rom", None, Some("00000006")),
+            ]
+        );
+        assert_eq!(
+            diff.parent_changes,
+            vec![ParentChange {
+                game_name: "orphan".to_owned(),
+                old_parent: Some("kept".to_owned()),
+                new_parent: None,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn identical_dats_have_an_empty_diff() -> Result<(), Box<dyn std::error::Error>> {
+        let old = DataFile::from_reader(OLD_DAT.as_bytes())?;
+
+        assert!(diff(&old, &old).is_empty());
+        Ok(())
+    }
+}
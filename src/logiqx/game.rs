@@ -1,8 +1,8 @@
 use serde::Deserialize;
 
-use super::Rom;
+use super::{Release, Rom, Sample};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct Game {
     #[serde(rename = "@name")]
     name: String,
@@ -10,6 +10,10 @@ pub struct Game {
     sourcefile: String,
     #[serde(rename = "@isbios", default)]
     isbios: String,
+    #[serde(rename = "@isdevice", default)]
+    isdevice: String,
+    #[serde(rename = "@ismechanical", default)]
+    ismechanical: String,
     #[serde(rename = "@cloneof", default)]
     cloneof: Option<String>,
     #[serde(rename = "@romof", default)]
@@ -21,14 +25,109 @@ pub struct Game {
     #[serde(rename = "@rebuildto", default)]
     rebuildto: String,
     #[serde(default)]
+    description: String,
+    #[serde(default)]
     year: String, // should probably be a DateTime
     #[serde(default)]
     manufacturer: String,
+    #[serde(rename = "release", default)]
+    releases: Vec<Release>,
     #[serde(rename = "rom", default)]
     roms: Vec<Rom>,
+    #[serde(rename = "sample", default)]
+    samples: Vec<Sample>,
 }
 
 impl Game {
+    /// A game with only a name, description and ROMs.
+    #[must_use]
+    pub fn new(name: String, description: String, roms: Vec<Rom>) -> Self {
+        Self {
+            name,
+            description,
+            roms,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_sourcefile(mut self, sourcefile: String) -> Self {
+        self.sourcefile = sourcefile;
+        self
+    }
+
+    #[must_use]
+    pub fn with_isbios(mut self, isbios: String) -> Self {
+        self.isbios = isbios;
+        self
+    }
+
+    #[must_use]
+    pub fn with_isdevice(mut self, isdevice: String) -> Self {
+        self.isdevice = isdevice;
+        self
+    }
+
+    #[must_use]
+    pub fn with_ismechanical(mut self, ismechanical: String) -> Self {
+        self.ismechanical = ismechanical;
+        self
+    }
+
+    #[must_use]
+    pub fn with_cloneof(mut self, cloneof: Option<String>) -> Self {
+        self.cloneof = cloneof;
+        self
+    }
+
+    #[must_use]
+    pub fn with_romof(mut self, romof: String) -> Self {
+        self.romof = romof;
+        self
+    }
+
+    #[must_use]
+    pub fn with_sampleof(mut self, sampleof: String) -> Self {
+        self.sampleof = sampleof;
+        self
+    }
+
+    #[must_use]
+    pub fn with_releases(mut self, releases: Vec<Release>) -> Self {
+        self.releases = releases;
+        self
+    }
+
+    #[must_use]
+    pub fn with_samples(mut self, samples: Vec<Sample>) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    #[must_use]
+    pub fn with_board(mut self, board: String) -> Self {
+        self.board = board;
+        self
+    }
+
+    #[must_use]
+    pub fn with_rebuildto(mut self, rebuildto: String) -> Self {
+        self.rebuildto = rebuildto;
+        self
+    }
+
+    #[must_use]
+    pub fn with_year(mut self, year: String) -> Self {
+        self.year = year;
+        self
+    }
+
+    #[must_use]
+    pub fn with_manufacturer(mut self, manufacturer: String) -> Self {
+        self.manufacturer = manufacturer;
+        self
+    }
+
     /// Get a reference to the game's name.
     #[must_use]
     pub fn name(&self) -> &str {
@@ -47,6 +146,18 @@ impl Game {
         self.isbios.as_ref()
     }
 
+    /// Get a reference to the game's isdevice.
+    #[must_use]
+    pub fn isdevice(&self) -> &str {
+        self.isdevice.as_ref()
+    }
+
+    /// Get a reference to the game's ismechanical.
+    #[must_use]
+    pub fn ismechanical(&self) -> &str {
+        self.ismechanical.as_ref()
+    }
+
     /// Get a reference to the game's romof.
     #[must_use]
     pub fn romof(&self) -> &str {
@@ -71,6 +182,12 @@ impl Game {
         self.rebuildto.as_ref()
     }
 
+    /// Get a reference to the game's description.
+    #[must_use]
+    pub fn description(&self) -> &str {
+        self.description.as_ref()
+    }
+
     /// Get a reference to the game's year.
     #[must_use]
     pub fn year(&self) -> &str {
@@ -83,12 +200,35 @@ impl Game {
         self.manufacturer.as_ref()
     }
 
+    /// Get a reference to the game's releases.
+    #[must_use]
+    pub fn releases(&self) -> &[Release] {
+        self.releases.as_ref()
+    }
+
     /// Get a reference to the game's roms.
     #[must_use]
     pub fn roms(&self) -> &[Rom] {
         self.roms.as_ref()
     }
 
+    /// Get a reference to the game's samples.
+    #[must_use]
+    pub fn samples(&self) -> &[Sample] {
+        self.samples.as_ref()
+    }
+
+    /// The sample set the game's samples are found in: its `sampleof`, or
+    /// the game itself when that is empty.
+    #[must_use]
+    pub fn sample_set(&self) -> &str {
+        if self.sampleof.is_empty() {
+            &self.name
+        } else {
+            &self.sampleof
+        }
+    }
+
     /// Get a reference to the game's cloneof.
     #[must_use]
     pub fn cloneof(&self) -> Option<&str> {
@@ -1,16 +1,99 @@
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct Header {
     name: String,
     description: Option<String>,
+    category: Option<String>,
     version: Option<String>,
     author: Option<String>,
+    email: Option<String>,
     homepage: Option<String>,
     url: Option<String>,
+    clrmamepro: Option<ClrMamePro>,
+    romcenter: Option<RomCenter>,
+}
+
+/// How clrmamepro should treat the sets of a DAT. Values are kept as
+/// written, e.g. `forcemerging="split"`.
+#[derive(Debug, Default, Deserialize)]
+struct ClrMamePro {
+    #[serde(rename = "@header", default)]
+    header: Option<String>,
+    #[serde(rename = "@forcemerging", default)]
+    forcemerging: Option<String>,
+    #[serde(rename = "@forcenodump", default)]
+    forcenodump: Option<String>,
+    #[serde(rename = "@forcepacking", default)]
+    forcepacking: Option<String>,
+}
+
+/// romcenter's settings; only the merge mode has a clrmamepro equivalent.
+#[derive(Debug, Default, Deserialize)]
+struct RomCenter {
+    #[serde(rename = "@rommode", default)]
+    rommode: Option<String>,
 }
 
 impl Header {
+    #[must_use]
+    pub const fn new(
+        name: String,
+        description: Option<String>,
+        version: Option<String>,
+        author: Option<String>,
+        homepage: Option<String>,
+        url: Option<String>,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            category: None,
+            version,
+            author,
+            email: None,
+            homepage,
+            url,
+            clrmamepro: None,
+            romcenter: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_category(mut self, category: Option<String>) -> Self {
+        self.category = category;
+        self
+    }
+
+    #[must_use]
+    pub fn with_email(mut self, email: Option<String>) -> Self {
+        self.email = email;
+        self
+    }
+
+    /// Set the clrmamepro directives; with none given, the header has no
+    /// `<clrmamepro>` element.
+    #[must_use]
+    pub fn with_directives(
+        mut self,
+        forcemerging: Option<String>,
+        forcenodump: Option<String>,
+        forcepacking: Option<String>,
+        header: Option<String>,
+    ) -> Self {
+        self.clrmamepro = (forcemerging.is_some()
+            || forcenodump.is_some()
+            || forcepacking.is_some()
+            || header.is_some())
+        .then_some(ClrMamePro {
+            header,
+            forcemerging,
+            forcenodump,
+            forcepacking,
+        });
+        self
+    }
+
     /// Get a reference to the header's name.
     #[must_use]
     pub fn name(&self) -> &str {
@@ -46,4 +129,59 @@ impl Header {
     pub const fn url(&self) -> Option<&String> {
         self.url.as_ref()
     }
+
+    /// Get a reference to the header's category.
+    #[must_use]
+    pub const fn category(&self) -> Option<&String> {
+        self.category.as_ref()
+    }
+
+    /// Get a reference to the header's email.
+    #[must_use]
+    pub const fn email(&self) -> Option<&String> {
+        self.email.as_ref()
+    }
+
+    /// clrmamepro's `forcemerging` (`none`, `split` or `full`), or the
+    /// equivalent of romcenter's `rommode`.
+    #[must_use]
+    pub fn forcemerging(&self) -> Option<&str> {
+        self.clrmamepro
+            .as_ref()
+            .and_then(|clrmamepro| clrmamepro.forcemerging.as_deref())
+            .or_else(|| {
+                self.romcenter.as_ref().and_then(|romcenter| {
+                    match romcenter.rommode.as_deref()? {
+                        "merged" => Some("full"),
+                        "split" => Some("split"),
+                        "unmerged" => Some("none"),
+                        _ => None,
+                    }
+                })
+            })
+    }
+
+    /// clrmamepro's `forcenodump`: `obsolete`, `required` or `ignore`.
+    #[must_use]
+    pub fn forcenodump(&self) -> Option<&str> {
+        self.clrmamepro
+            .as_ref()
+            .and_then(|clrmamepro| clrmamepro.forcenodump.as_deref())
+    }
+
+    /// clrmamepro's `forcepacking`: `zip` or `unzip`.
+    #[must_use]
+    pub fn forcepacking(&self) -> Option<&str> {
+        self.clrmamepro
+            .as_ref()
+            .and_then(|clrmamepro| clrmamepro.forcepacking.as_deref())
+    }
+
+    /// The clrmamepro header skipper, e.g. `nes.xml`, named by the DAT.
+    #[must_use]
+    pub fn header_skipper(&self) -> Option<&str> {
+        self.clrmamepro
+            .as_ref()
+            .and_then(|clrmamepro| clrmamepro.header.as_deref())
+    }
 }
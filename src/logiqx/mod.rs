@@ -1,8 +1,19 @@
+mod check;
 mod data_file;
+mod diff;
 mod game;
 mod header;
+mod release;
 mod rom;
+mod sample;
+mod software_list;
+mod xml;
 
+pub use check::check;
 pub use data_file::DataFile;
+pub use diff::diff;
 pub use game::Game;
+pub use header::Header;
+pub use release::Release;
 pub use rom::Rom;
+pub use sample::Sample;
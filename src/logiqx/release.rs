@@ -0,0 +1,78 @@
+use serde::Deserialize;
+
+/// A region a game was released in, as DATs with several regional releases
+/// of one game list them.
+#[derive(Debug, Default, Deserialize)]
+pub struct Release {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@region")]
+    region: String,
+    #[serde(rename = "@language", default)]
+    language: String,
+    #[serde(rename = "@date", default)]
+    date: String,
+    #[serde(rename = "@default", default)]
+    default: String,
+}
+
+impl Release {
+    #[must_use]
+    pub const fn new(name: String, region: String) -> Self {
+        Self {
+            name,
+            region,
+            language: String::new(),
+            date: String::new(),
+            default: String::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_language(mut self, language: String) -> Self {
+        self.language = language;
+        self
+    }
+
+    #[must_use]
+    pub fn with_date(mut self, date: String) -> Self {
+        self.date = date;
+        self
+    }
+
+    #[must_use]
+    pub fn with_default(mut self, default: String) -> Self {
+        self.default = default;
+        self
+    }
+
+    /// Get a reference to the release's name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    /// Get a reference to the release's region.
+    #[must_use]
+    pub fn region(&self) -> &str {
+        self.region.as_ref()
+    }
+
+    /// Get a reference to the release's language.
+    #[must_use]
+    pub fn language(&self) -> &str {
+        self.language.as_ref()
+    }
+
+    /// Get a reference to the release's date.
+    #[must_use]
+    pub fn date(&self) -> &str {
+        self.date.as_ref()
+    }
+
+    /// Get a reference to the release's default flag.
+    #[must_use]
+    pub fn default(&self) -> &str {
+        self.default.as_ref()
+    }
+}
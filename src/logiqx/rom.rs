@@ -1,16 +1,17 @@
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct Rom {
     #[serde(rename = "@name")]
     name: String,
     #[serde(rename = "@size")]
     size: i32,
-    #[serde(rename = "@md5", with = "hex")]
+    // Undumped ROMs are listed without hashes.
+    #[serde(rename = "@md5", with = "hex", default)]
     md5: Vec<u8>,
-    #[serde(rename = "@sha1", with = "hex")]
+    #[serde(rename = "@sha1", with = "hex", default)]
     sha1: Vec<u8>,
-    #[serde(rename = "@crc", with = "hex")]
+    #[serde(rename = "@crc", with = "hex", default)]
     crc: Vec<u8>,
     #[serde(rename = "@merge", default)]
     merge: String,
@@ -23,6 +24,30 @@ pub struct Rom {
 }
 
 impl Rom {
+    #[must_use]
+    pub fn new(name: String, size: i32, crc: &[u8], md5: &[u8], sha1: &[u8]) -> Self {
+        Self {
+            name,
+            size,
+            md5: md5.to_vec(),
+            sha1: sha1.to_vec(),
+            crc: crc.to_vec(),
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_merge(mut self, merge: String) -> Self {
+        self.merge = merge;
+        self
+    }
+
+    #[must_use]
+    pub fn with_status(mut self, status: String) -> Self {
+        self.status = status;
+        self
+    }
+
     /// Get a reference to the rom's name.
     #[must_use]
     pub fn name(&self) -> &str {
@@ -0,0 +1,22 @@
+use serde::Deserialize;
+
+/// A sound sample a game plays from a WAV file. DATs list samples by name
+/// only, without sizes or hashes.
+#[derive(Debug, Default, Deserialize)]
+pub struct Sample {
+    #[serde(rename = "@name")]
+    name: String,
+}
+
+impl Sample {
+    #[must_use]
+    pub const fn new(name: String) -> Self {
+        Self { name }
+    }
+
+    /// Get a reference to the sample's name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+}
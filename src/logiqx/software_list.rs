@@ -0,0 +1,197 @@
+use std::io::Read;
+
+use serde::Deserialize;
+
+use super::{DataFile, Game, Header, Rom};
+
+/// A MAME software list (`hash/<list>.xml`), read into the Logiqx model.
+#[derive(Debug, Deserialize)]
+struct SoftwareList {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@description", default)]
+    description: Option<String>,
+    #[serde(rename = "software", default)]
+    software: Vec<Software>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Software {
+    #[serde(rename = "@name")]
+    name: String,
+    #[serde(rename = "@cloneof", default)]
+    cloneof: Option<String>,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    year: String,
+    #[serde(default)]
+    publisher: String,
+    #[serde(rename = "part", default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Part {
+    #[serde(rename = "dataarea", default)]
+    data_areas: Vec<DataArea>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DataArea {
+    #[serde(rename = "rom", default)]
+    roms: Vec<SoftwareRom>,
+}
+
+/// Unnamed entries only tell MAME how to load the data of the named ROM
+/// before them (`loadflag="continue"`, `"fill"` and the like).
+#[derive(Debug, Deserialize)]
+struct SoftwareRom {
+    #[serde(rename = "@name", default)]
+    name: Option<String>,
+    #[serde(rename = "@size", default)]
+    size: i32,
+    #[serde(rename = "@crc", with = "hex", default)]
+    crc: Vec<u8>,
+    #[serde(rename = "@sha1", with = "hex", default)]
+    sha1: Vec<u8>,
+    #[serde(rename = "@status", default)]
+    status: String,
+}
+
+/// Whether the start of an XML file is a software list rather than a Logiqx
+/// data file.
+pub(super) fn is_software_list(prefix: &[u8]) -> bool {
+    let prefix = String::from_utf8_lossy(prefix);
+    match (prefix.find("<softwarelist"), prefix.find("<datafile")) {
+        (Some(software_list), Some(data_file)) => software_list < data_file,
+        (software_list, _) => software_list.is_some(),
+    }
+}
+
+/// Each software becomes a game named after it. The ROMs of every part and
+/// data area are listed together under their own names, once each.
+pub(super) fn from_reader<R: Read>(reader: R) -> crate::Result<DataFile> {
+    let software_list: SoftwareList = serde_xml_rs::from_reader(reader)?;
+    let header = Header::new(
+        software_list.name,
+        software_list.description,
+        None,
+        None,
+        None,
+        None,
+    );
+    let games = software_list
+        .software
+        .into_iter()
+        .map(|software| {
+            let mut roms: Vec<Rom> = Vec::new();
+            for rom in software
+                .parts
+                .into_iter()
+                .flat_map(|part| part.data_areas)
+                .flat_map(|data_area| data_area.roms)
+            {
+                let Some(name) = rom.name else {
+                    continue;
+                };
+                if roms.iter().any(|listed| listed.name() == name) {
+                    continue;
+                }
+                roms.push(
+                    Rom::new(name, rom.size, &rom.crc, &[], &rom.sha1).with_status(rom.status),
+                );
+            }
+            Game::new(software.name, software.description, roms)
+                .with_cloneof(software.cloneof)
+                .with_year(software.year)
+                .with_manufacturer(software.publisher)
+        })
+        .collect();
+    Ok(DataFile::new(header, games).with_software_list())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    const SOFTWARE_LIST: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE softwarelist SYSTEM "softwarelist.dtd">
+<softwarelist name="nes" description="Nintendo NES cartridges">
+  <software name="smb" supported="yes">
+    <description>Super Mario Bros.</description>
+    <year>1985</year>
+    <publisher>Nintendo</publisher>
+    <info name="serial" value="NES-SM-USA"/>
+    <part name="cart" interface="nes_cart">
+      <feature name="slot" value="nrom"/>
+      <dataarea name="prg" size="32768">
+        <rom name="smb-prg.bin" size="32768" crc="5cf548d3" sha1="fefe8ea8d6e8b4c9b9a6d2e2d9e7f9f0a1b2c3d4" offset="00000"/>
+      </dataarea>
+      <dataarea name="chr" size="8192">
+        <rom name="smb-chr.bin" size="4096" crc="867b51ad" sha1="394badaf0b0bdd0ac279e1c5b2eab1b9a52b5fd0" offset="00000"/>
+        <rom size="4096" offset="0x1000" loadflag="reload"/>
+      </dataarea>
+    </part>
+  </software>
+  <software name="smbj" cloneof="smb" supported="yes">
+    <description>Super Mario Bros. (Japan)</description>
+    <year>1985</year>
+    <publisher>Nintendo</publisher>
+    <part name="cart" interface="nes_cart">
+      <dataarea name="prg" size="32768">
+        <rom name="smbj-prg.bin" size="32768" status="baddump" crc="00000001" sha1="0000000000000000000000000000000000000001" offset="00000"/>
+      </dataarea>
+    </part>
+    <part name="flop" interface="floppy_3">
+      <dataarea name="flop" size="1">
+        <rom name="smbj-prg.bin" size="32768" status="baddump" crc="00000001" sha1="0000000000000000000000000000000000000001" offset="00000"/>
+      </dataarea>
+    </part>
+  </software>
+</softwarelist>"#;
+
+    #[test]
+    fn software_list_reads_as_a_data_file() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(is_software_list(SOFTWARE_LIST.as_bytes()));
+        assert!(!is_software_list(b"<?xml version=\"1.0\"?>\n<datafile>"));
+
+        let data_file = from_reader(SOFTWARE_LIST.as_bytes())?;
+
+        assert!(data_file.is_software_list());
+        assert_eq!(data_file.header().name(), "nes");
+        assert_eq!(
+            data_file.header().description().map(String::as_str),
+            Some("Nintendo NES cartridges")
+        );
+        let games = data_file.games();
+        assert_eq!(
+            games
+                .iter()
+                .map(|game| (game.name(), game.cloneof(), game.manufacturer()))
+                .collect::<Vec<_>>(),
+            vec![("smb", None, "Nintendo"), ("smbj", Some("smb"), "Nintendo")]
+        );
+        let smb = games
+            .first()
+            .ok_or_else(|| io::Error::other("missing smb"))?;
+        assert_eq!(
+            smb.roms()
+                .iter()
+                .map(|rom| (rom.name(), rom.size(), hex::encode(rom.crc())))
+                .collect::<Vec<_>>(),
+            vec![
+                ("smb-prg.bin", 32768, "5cf548d3".to_owned()),
+                ("smb-chr.bin", 4096, "867b51ad".to_owned()),
+            ]
+        );
+        let smbj = games
+            .get(1)
+            .ok_or_else(|| io::Error::other("missing smbj"))?;
+        assert_eq!(smbj.roms().len(), 1);
+        assert_eq!(smbj.roms()[0].status(), "baddump");
+        Ok(())
+    }
+}
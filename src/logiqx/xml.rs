@@ -0,0 +1,317 @@
+use std::io::{self, Write};
+
+use super::{DataFile, Game, Release, Rom, Sample, header::Header};
+
+const DOCTYPE: &str = r#"<!DOCTYPE datafile PUBLIC "-//Logiqx//DTD ROM Management Datafile//EN" "http://www.logiqx.com/Dats/datafile.dtd">"#;
+
+/// Write elements and attributes in Logiqx DTD order, skipping empty
+/// optional values.
+pub(super) fn write_data_file<W: Write>(data_file: &DataFile, writer: W) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(writer);
+    writeln!(writer, r#"<?xml version="1.0"?>"#)?;
+    writeln!(writer, "{DOCTYPE}")?;
+    write!(writer, "<datafile")?;
+    write_attribute(&mut writer, "build", data_file.build().unwrap_or_default())?;
+    write_attribute(&mut writer, "debug", data_file.debug().unwrap_or_default())?;
+    writeln!(writer, ">")?;
+    write_header(&mut writer, data_file.header())?;
+    for game in data_file.games() {
+        write_game(&mut writer, game)?;
+    }
+    writeln!(writer, "</datafile>")?;
+    writer.flush()
+}
+
+fn write_header<W: Write>(writer: &mut W, header: &Header) -> io::Result<()> {
+    writeln!(writer, "\t<header>")?;
+    write_element(writer, 2, "name", header.name())?;
+    for (element, value) in [
+        ("description", header.description()),
+        ("category", header.category()),
+        ("version", header.version()),
+        ("author", header.author()),
+        ("email", header.email()),
+        ("homepage", header.homepage()),
+        ("url", header.url()),
+    ] {
+        write_element(writer, 2, element, value.map_or("", String::as_str))?;
+    }
+    let directives = [
+        ("header", header.header_skipper()),
+        ("forcemerging", header.forcemerging()),
+        ("forcenodump", header.forcenodump()),
+        ("forcepacking", header.forcepacking()),
+    ];
+    if directives.iter().any(|(_, value)| value.is_some()) {
+        write!(writer, "\t\t<clrmamepro")?;
+        for (attribute, value) in directives {
+            write_attribute(writer, attribute, value.unwrap_or_default())?;
+        }
+        writeln!(writer, "/>")?;
+    }
+    writeln!(writer, "\t</header>")
+}
+
+fn write_game<W: Write>(writer: &mut W, game: &Game) -> io::Result<()> {
+    write!(writer, "\t<game")?;
+    write_required_attribute(writer, "name", game.name())?;
+    for (attribute, value) in [
+        ("sourcefile", game.sourcefile()),
+        ("isbios", game.isbios()),
+        ("isdevice", game.isdevice()),
+        ("ismechanical", game.ismechanical()),
+        ("cloneof", game.cloneof().unwrap_or_default()),
+        ("romof", game.romof()),
+        ("sampleof", game.sampleof()),
+        ("board", game.board()),
+        ("rebuildto", game.rebuildto()),
+    ] {
+        write_attribute(writer, attribute, value)?;
+    }
+    writeln!(writer, ">")?;
+    // The DTD requires a description, so fall back to the game's name.
+    let description = if game.description().is_empty() {
+        game.name()
+    } else {
+        game.description()
+    };
+    write_element(writer, 2, "description", description)?;
+    write_element(writer, 2, "year", game.year())?;
+    write_element(writer, 2, "manufacturer", game.manufacturer())?;
+    for release in game.releases() {
+        write_release(writer, release)?;
+    }
+    for rom in game.roms() {
+        write_rom(writer, rom)?;
+    }
+    for sample in game.samples() {
+        write_sample(writer, sample)?;
+    }
+    writeln!(writer, "\t</game>")
+}
+
+fn write_release<W: Write>(writer: &mut W, release: &Release) -> io::Result<()> {
+    write!(writer, "\t\t<release")?;
+    write_required_attribute(writer, "name", release.name())?;
+    write_required_attribute(writer, "region", release.region())?;
+    for (attribute, value) in [
+        ("language", release.language()),
+        ("date", release.date()),
+        ("default", release.default()),
+    ] {
+        write_attribute(writer, attribute, value)?;
+    }
+    writeln!(writer, "/>")
+}
+
+fn write_sample<W: Write>(writer: &mut W, sample: &Sample) -> io::Result<()> {
+    write!(writer, "\t\t<sample")?;
+    write_required_attribute(writer, "name", sample.name())?;
+    writeln!(writer, "/>")
+}
+
+fn write_rom<W: Write>(writer: &mut W, rom: &Rom) -> io::Result<()> {
+    write!(writer, "\t\t<rom")?;
+    write_required_attribute(writer, "name", rom.name())?;
+    write_required_attribute(writer, "size", &rom.size().to_string())?;
+    // Undumped ROMs have no hashes, so an empty one is left out.
+    for (attribute, hash) in [("crc", rom.crc()), ("md5", rom.md5()), ("sha1", rom.sha1())] {
+        write_attribute(writer, attribute, &hex::encode(hash))?;
+    }
+    for (attribute, value) in [
+        ("merge", rom.merge()),
+        ("status", rom.status()),
+        ("serial", rom.serial()),
+        ("date", rom.date()),
+    ] {
+        write_attribute(writer, attribute, value)?;
+    }
+    writeln!(writer, "/>")
+}
+
+fn write_element<W: Write>(
+    writer: &mut W,
+    depth: usize,
+    element: &str,
+    value: &str,
+) -> io::Result<()> {
+    if value.is_empty() {
+        return Ok(());
+    }
+    writeln!(
+        writer,
+        "{}<{element}>{}</{element}>",
+        "\t".repeat(depth),
+        escape(value)
+    )
+}
+
+fn write_attribute<W: Write>(writer: &mut W, attribute: &str, value: &str) -> io::Result<()> {
+    if value.is_empty() {
+        Ok(())
+    } else {
+        write_required_attribute(writer, attribute, value)
+    }
+}
+
+fn write_required_attribute<W: Write>(
+    writer: &mut W,
+    attribute: &str,
+    value: &str,
+) -> io::Result<()> {
+    write!(writer, r#" {attribute}="{}""#, escape(value))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .chars()
+        .fold(String::with_capacity(value.len()), |mut escaped, c| {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&apos;"),
+                _ => escaped.push(c),
+            }
+            escaped
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn written_data_file_reads_back_with_escaped_names() -> Result<(), Box<dyn std::error::Error>> {
+        let header = Header::new(
+            "Homebrew & Friends".to_owned(),
+            Some("Dumps <private>".to_owned()),
+            Some("20261019".to_owned()),
+            None,
+            None,
+            Some("https://example.com/?a=1&b=2".to_owned()),
+        );
+        let rom = Rom::new(
+            "it's \"quoted\".bin".to_owned(),
+            3,
+            &[0x35, 0x24, 0x41, 0xc2],
+            &[0x90; 16],
+            &[0xa9; 20],
+        );
+        let data_file = DataFile::new(
+            header,
+            vec![
+                Game::new("a&b".to_owned(), String::new(), vec![rom])
+                    .with_releases(vec![
+                        Release::new("a&b".to_owned(), "USA".to_owned())
+                            .with_language("en".to_owned())
+                            .with_default("yes".to_owned()),
+                    ])
+                    .with_samples(vec![Sample::new("fire & ice".to_owned())])
+                    .with_isdevice("yes".to_owned()),
+            ],
+        );
+
+        let mut xml = Vec::new();
+        data_file.write_to(&mut xml)?;
+        let parsed = DataFile::from_reader(xml.as_slice())?;
+
+        assert_eq!(parsed.header().name(), "Homebrew & Friends");
+        assert_eq!(
+            parsed.header().description().map(String::as_str),
+            Some("Dumps <private>")
+        );
+        assert!(parsed.header().author().is_none());
+        assert_eq!(
+            parsed.header().url().map(String::as_str),
+            Some("https://example.com/?a=1&b=2")
+        );
+        let game = parsed
+            .games()
+            .first()
+            .ok_or_else(|| io::Error::other("missing game"))?;
+        assert_eq!(game.name(), "a&b");
+        assert_eq!(game.description(), "a&b");
+        assert_eq!(game.isdevice(), "yes");
+        assert_eq!(game.ismechanical(), "");
+        let rom = game
+            .roms()
+            .first()
+            .ok_or_else(|| io::Error::other("missing rom"))?;
+        assert_eq!(rom.name(), "it's \"quoted\".bin");
+        assert_eq!(rom.size(), 3);
+        assert_eq!(hex::encode(rom.crc()), "352441c2");
+        assert_eq!(rom.md5(), [0x90; 16].as_slice());
+        assert_eq!(rom.sha1(), [0xa9; 20].as_slice());
+        assert_eq!(
+            game.releases()
+                .iter()
+                .map(|release| (release.region(), release.language(), release.default()))
+                .collect::<Vec<_>>(),
+            vec![("USA", "en", "yes")]
+        );
+        assert_eq!(
+            game.samples().iter().map(Sample::name).collect::<Vec<_>>(),
+            vec!["fire & ice"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn header_directives_are_written_as_clrmamepro() -> Result<(), Box<dyn std::error::Error>> {
+        let romcenter = DataFile::from_reader(
+            r#"<datafile><header><name>RC</name><category>Arcade</category><email>a@b.c</email><romcenter rommode="unmerged"/></header></datafile>"#
+                .as_bytes(),
+        )?;
+
+        let mut xml = Vec::new();
+        romcenter.write_to(&mut xml)?;
+        let xml = String::from_utf8(xml)?;
+        assert!(xml.contains("\t\t<category>Arcade</category>\n"));
+        assert!(xml.contains("\t\t<email>a@b.c</email>\n"));
+        assert!(xml.contains("\t\t<clrmamepro forcemerging=\"none\"/>\n"));
+
+        let header = Header::new("CMP".to_owned(), None, None, None, None, None).with_directives(
+            Some("split".to_owned()),
+            Some("obsolete".to_owned()),
+            Some("unzip".to_owned()),
+            Some("nes.xml".to_owned()),
+        );
+        let mut xml = Vec::new();
+        DataFile::new(header, Vec::new()).write_to(&mut xml)?;
+        let parsed = DataFile::from_reader(xml.as_slice())?;
+        assert_eq!(parsed.header().forcemerging(), Some("split"));
+        assert_eq!(parsed.header().forcenodump(), Some("obsolete"));
+        assert_eq!(parsed.header().forcepacking(), Some("unzip"));
+        assert_eq!(parsed.header().header_skipper(), Some("nes.xml"));
+        Ok(())
+    }
+
+    #[test]
+    fn nodump_rom_without_hashes_round_trips() -> Result<(), Box<dyn std::error::Error>> {
+        let rom = Rom::new("missing.bin".to_owned(), 1024, &[], &[], &[])
+            .with_status("nodump".to_owned());
+        let data_file = DataFile::new(
+            Header::new("Nodump".to_owned(), None, None, None, None, None),
+            vec![Game::new("game".to_owned(), String::new(), vec![rom])],
+        );
+
+        let mut xml = Vec::new();
+        data_file.write_to(&mut xml)?;
+        let text = String::from_utf8(xml.clone())?;
+        let parsed = DataFile::from_reader(xml.as_slice())?;
+
+        assert!(text.contains("\t\t<rom name=\"missing.bin\" size=\"1024\" status=\"nodump\"/>\n"));
+        let rom = parsed
+            .games()
+            .first()
+            .and_then(|game| game.roms().first())
+            .ok_or_else(|| io::Error::other("missing rom"))?;
+        assert_eq!(rom.status(), "nodump");
+        assert!(rom.crc().is_empty() && rom.md5().is_empty() && rom.sha1().is_empty());
+        Ok(())
+    }
+}
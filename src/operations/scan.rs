@@ -1,5 +1,7 @@
 use std::{
-    io::{Read, Write},
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek},
     path::{Component, Path, PathBuf},
 };
 
@@ -10,84 +12,233 @@ use indicatif::ParallelProgressIterator;
 use log::{info, warn};
 
 use rayon::prelude::*;
-use sha1::{Digest, Sha1};
 
 use walkdir::{DirEntry, WalkDir};
-use xxhash_rust::xxh3::Xxh3;
 
 use crate::{
     Error,
-    hashes::{Sha1Digest, Xxh3Digest},
+    archive::{ArchiveKind, TAR_HEADER_LEN, with_spooled_file},
+    hashes::{RomHasher, RomHashes, hash_reader_chunks, rom_hashes},
     progress,
     storage::{
         db::{self, Pool},
-        models::NewRomFile,
+        models::{ARCHIVE_CHAIN_SEPARATOR, ArchiveFile, FileStamp, NewArchiveFile, NewRomFile},
     },
 };
 
-pub fn source(path: &Utf8Path, jobs: usize, pool: &Pool) -> crate::Result<Utf8PathBuf> {
+pub fn source(
+    path: &Utf8Path,
+    jobs: usize,
+    nested_depth: usize,
+    pool: &Pool,
+) -> crate::Result<Utf8PathBuf> {
     let source_root = path.canonicalize_utf8()?;
     info!("Looking in path: {source_root}");
     let excluded_paths = db::database_file_paths(pool)?;
     let file_list = walk_for_files(&source_root, &excluded_paths);
-    let new_rom_files = get_all_rom_files(&file_list, jobs)?;
-
-    info!(
-        "rom files found (unpacked and packed both): {}",
-        new_rom_files.len()
-    );
-    let associated_roms =
-        db::replace_rom_files_for_source_root(pool, &source_root, &new_rom_files)?;
-    if associated_roms == 0 && !new_rom_files.is_empty() {
-        warn!(
-            "scanned {} ROM files, but none matched imported DAT ROMs",
-            new_rom_files.len()
+    let known_archives = db::scanned_archive_files(pool, &source_root)?
+        .into_iter()
+        .map(|archive_file| (archive_file.path.clone(), archive_file))
+        .collect::<BTreeMap<_, _>>();
+    let scan = get_all_rom_files(&file_list, jobs, nested_depth, &known_archives)?;
+    let rom_file_count = scan.rom_file_count();
+
+    info!("rom files found (unpacked and packed both): {rom_file_count}");
+    if !scan.unchanged_archive_ids.is_empty() {
+        info!(
+            "skipped {} archives unchanged since the last scan",
+            scan.unchanged_archive_ids.len()
         );
     }
+    let associated_roms = db::replace_rom_files_for_source_root(
+        pool,
+        &source_root,
+        &scan.bare_files,
+        scan.archives,
+        &scan.unchanged_archive_ids,
+    )?;
+    if associated_roms == 0 && rom_file_count > 0 {
+        warn!("scanned {rom_file_count} ROM files, but none matched imported DAT ROMs");
+    }
     Ok(source_root)
 }
 
-fn get_all_rom_files(file_list: &[Utf8PathBuf], jobs: usize) -> crate::Result<Vec<NewRomFile>> {
+/// Everything a source scan found, grouped the way the cache stores it.
+#[derive(Debug, Default)]
+struct SourceScan {
+    bare_files: Vec<NewRomFile>,
+    archives: Vec<(NewArchiveFile, Vec<NewRomFile>)>,
+    unchanged_archive_ids: Vec<i32>,
+}
+
+impl SourceScan {
+    fn add(mut self, scanned: ScannedPath) -> Self {
+        match scanned {
+            ScannedPath::Bare(rom_files) => self.bare_files.extend(rom_files),
+            ScannedPath::Archive(archive_file, rom_files) => {
+                self.archives.push((archive_file, rom_files));
+            }
+            ScannedPath::Unchanged(archive_file_id) => {
+                self.unchanged_archive_ids.push(archive_file_id);
+            }
+        }
+        self
+    }
+
+    fn merge(mut self, mut other: Self) -> Self {
+        self.bare_files.append(&mut other.bare_files);
+        self.archives.append(&mut other.archives);
+        self.unchanged_archive_ids
+            .append(&mut other.unchanged_archive_ids);
+        self
+    }
+
+    fn rom_file_count(&self) -> usize {
+        self.bare_files.len()
+            + self
+                .archives
+                .iter()
+                .map(|(_, rom_files)| rom_files.len())
+                .sum::<usize>()
+    }
+}
+
+enum ScannedPath {
+    Bare(Vec<NewRomFile>),
+    Archive(NewArchiveFile, Vec<NewRomFile>),
+    Unchanged(i32),
+}
+
+fn get_all_rom_files(
+    file_list: &[Utf8PathBuf],
+    jobs: usize,
+    nested_depth: usize,
+    known_archives: &BTreeMap<String, ArchiveFile>,
+) -> crate::Result<SourceScan> {
     let bar = progress::bar(file_list.len() as u64);
     let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
     pool.install(|| {
         file_list
             .par_iter()
             .progress_with(bar)
-            .try_fold(Vec::new, |mut rom_files, path| {
-                rom_files.extend(scan_path(path)?);
-                Ok(rom_files)
-            })
-            .try_reduce(Vec::new, |mut left, mut right| {
-                left.append(&mut right);
-                Ok(left)
+            .try_fold(SourceScan::default, |scan, path| {
+                Ok(scan.add(scan_source_path(path, nested_depth, known_archives)?))
             })
+            .try_reduce(SourceScan::default, |left, right| Ok(left.merge(right)))
     })
 }
 
-fn scan_path(path: &Utf8Path) -> crate::Result<Vec<NewRomFile>> {
-    let mmap = crate::hashes::mmap_path(path)?;
-    infer::get_from_path(path)?.map_or_else(
+/// Scan one walked file, skipping archives whose size, modification time
+/// and scan depth match the cached row.
+fn scan_source_path(
+    path: &Utf8Path,
+    nested_depth: usize,
+    known_archives: &BTreeMap<String, ArchiveFile>,
+) -> crate::Result<ScannedPath> {
+    let stamp = FileStamp::from_path(path)?;
+    if let Some(known) = known_archives.get(path.as_str())
+        && known.matches_stamp(stamp)
+        && usize::try_from(known.nested_depth).is_ok_and(|depth| depth == nested_depth)
+    {
+        return Ok(ScannedPath::Unchanged(known.id));
+    }
+
+    let Some(kind) = ArchiveKind::of_path(path)? else {
+        return scan_bare_file(path).map(ScannedPath::Bare);
+    };
+    let rom_files = scan_container(path, kind, nested_depth)?;
+    let sha1 = crate::hashes::stream_sha1(&crate::hashes::mmap_path(path)?);
+    Ok(ScannedPath::Archive(
+        NewArchiveFile::from_stamp(path, sha1, stamp, nested_depth),
+        rom_files,
+    ))
+}
+
+#[cfg(test)]
+fn scan_path(path: &Utf8Path, nested_depth: usize) -> crate::Result<Vec<NewRomFile>> {
+    ArchiveKind::of_path(path)?.map_or_else(
         || scan_bare_file(path),
-        |file_type| match file_type.mime_type() {
-            "application/zip" => scan_zip(&mmap),
-            "application/x-7z-compressed" => scan_7z(path),
-            "application/vnd.rar" => scan_rar(path),
-            _mime_type => scan_bare_file(path),
-        },
+        |kind| scan_container(path, kind, nested_depth),
     )
 }
 
+fn scan_container(
+    path: &Utf8Path,
+    kind: ArchiveKind,
+    nested_depth: usize,
+) -> crate::Result<Vec<NewRomFile>> {
+    let location = ArchiveLocation::new(path);
+    match kind {
+        ArchiveKind::Zip => scan_zip(&crate::hashes::mmap_path(path)?, nested_depth),
+        ArchiveKind::SevenZip => scan_7z(path, &location, nested_depth),
+        ArchiveKind::Rar => scan_rar(path, &location, nested_depth),
+        ArchiveKind::Gzip => scan_gzip(BufReader::new(File::open(path)?), &location, nested_depth),
+        ArchiveKind::Tar => scan_tar(BufReader::new(File::open(path)?), &location, nested_depth),
+    }
+}
+
+/// The on-disk file an archive entry was found in, plus the entry names of
+/// any archives it is nested inside, outermost first.
+struct ArchiveLocation<'a> {
+    path: &'a Utf8Path,
+    chain: Vec<String>,
+}
+
+impl<'a> ArchiveLocation<'a> {
+    const fn new(path: &'a Utf8Path) -> Self {
+        Self {
+            path,
+            chain: Vec::new(),
+        }
+    }
+
+    fn rom_file(&self, name: &Path, hashes: RomHashes) -> Option<NewRomFile> {
+        NewRomFile::from_archive(self.path, name, hashes)
+            .map(|nrf| nrf.with_archive_chain(&self.chain))
+    }
+
+    /// The file name of the innermost container: the on-disk file itself, or
+    /// the last archive entry in the chain.
+    fn container_name(&self) -> Option<&str> {
+        self.chain.last().map_or_else(
+            || self.path.file_name(),
+            |name| Utf8Path::new(name).file_name(),
+        )
+    }
+
+    fn nested(&self, name: &Path) -> Option<Self> {
+        let name = name.to_str()?;
+        if name.contains(ARCHIVE_CHAIN_SEPARATOR) {
+            return None;
+        }
+        let mut chain = self.chain.clone();
+        chain.push(name.to_owned());
+        Some(Self {
+            path: self.path,
+            chain,
+        })
+    }
+}
+
 fn scan_bare_file(path: &Utf8Path) -> crate::Result<Vec<NewRomFile>> {
     NewRomFile::from_path(path)
         .map(|nrf| vec![nrf])
         .ok_or_else(|| Error::InvalidPath(format!("couldn't scan file: {path}")))
 }
 
-fn scan_zip(mmap: &MmapFile) -> crate::Result<Vec<NewRomFile>> {
+fn scan_zip(mmap: &MmapFile, nested_depth: usize) -> crate::Result<Vec<NewRomFile>> {
     let path = Utf8Path::from_path(mmap.path())
         .ok_or_else(|| Error::InvalidPath("invalid path".to_owned()))?;
     let reader = mmap.reader(0).map_err(|e| Error::Mmap(e.to_string()))?;
+    scan_zip_reader(reader, &ArchiveLocation::new(path), nested_depth)
+}
+
+fn scan_zip_reader<R: Read + Seek>(
+    reader: R,
+    location: &ArchiveLocation<'_>,
+    nested_depth: usize,
+) -> crate::Result<Vec<NewRomFile>> {
+    let path = location.path;
     let mut zip = zip::ZipArchive::new(reader)?;
     let mut rom_files = Vec::new();
 
@@ -102,17 +253,29 @@ fn scan_zip(mmap: &MmapFile) -> crate::Result<Vec<NewRomFile>> {
             .ok_or_else(|| Error::InvalidPath(format!("invalid name inside zip: {path:?}")))?
             .clone();
 
-        let (sha1, xxh3) = hash_reader_chunks(&mut file)?;
-        let nrf = NewRomFile::from_archive(path, &name, sha1, xxh3).ok_or_else(|| {
-            Error::InvalidPath(format!("couldn't make database entry for file: {path:?}"))
-        })?;
+        if nested_depth > 0 {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            rom_files.extend(scan_entry_bytes(location, &name, &data, nested_depth)?);
+            continue;
+        }
+
+        let nrf = location
+            .rom_file(&name, hash_reader_chunks(&mut file)?)
+            .ok_or_else(|| {
+                Error::InvalidPath(format!("couldn't make database entry for file: {path:?}"))
+            })?;
         rom_files.push(nrf);
     }
 
     Ok(rom_files)
 }
 
-fn scan_rar(path: &Utf8Path) -> crate::Result<Vec<NewRomFile>> {
+fn scan_rar(
+    path: &Utf8Path,
+    location: &ArchiveLocation<'_>,
+    nested_depth: usize,
+) -> crate::Result<Vec<NewRomFile>> {
     let mut archive = unrar::Archive::new(path.as_std_path()).open_for_processing()?;
     let mut rom_files = Vec::new();
 
@@ -120,11 +283,7 @@ fn scan_rar(path: &Utf8Path) -> crate::Result<Vec<NewRomFile>> {
         let entry_path = safe_rar_entry_path(header.entry())?;
         archive = if let Some(filename) = entry_path {
             let (data, rest) = header.read()?;
-            let sha1 = crate::hashes::sha1_bytes(&data);
-            let xxh3 = crate::hashes::xxhash3_bytes(&data);
-            if let Some(nrf) = NewRomFile::from_archive(path, &filename, sha1, xxh3) {
-                rom_files.push(nrf);
-            }
+            rom_files.extend(scan_entry_bytes(location, &filename, &data, nested_depth)?);
             rest
         } else {
             header.skip()?
@@ -162,68 +321,209 @@ fn safe_rar_entry_path(header: &unrar::FileHeader) -> crate::Result<Option<PathB
     Ok(Some(path.to_owned()))
 }
 
-fn scan_7z(path: &Utf8Path) -> crate::Result<Vec<NewRomFile>> {
+/// A gzip stream is either a `.tar.gz` pack or a single compressed file,
+/// which is named after the gzip header or the container minus `.gz`.
+fn scan_gzip<R: Read>(
+    reader: R,
+    location: &ArchiveLocation<'_>,
+    nested_depth: usize,
+) -> crate::Result<Vec<NewRomFile>> {
+    let mut decoder = flate2::read::GzDecoder::new(reader);
+    let mut head = Vec::new();
+    (&mut decoder).take(TAR_HEADER_LEN).read_to_end(&mut head)?;
+    if infer::archive::is_tar(&head) {
+        return scan_tar(Cursor::new(head).chain(decoder), location, nested_depth);
+    }
+
+    let name = gzip_entry_name(decoder.header(), location).ok_or_else(|| {
+        Error::InvalidPath(format!("couldn't name gzip contents: {}", location.path))
+    })?;
+    let mut contents = Cursor::new(head).chain(decoder);
+    if nested_depth > 0 {
+        let mut data = Vec::new();
+        contents.read_to_end(&mut data)?;
+        return scan_entry_bytes(location, &name, &data, nested_depth);
+    }
+
+    let nrf = location
+        .rom_file(&name, hash_reader_chunks(contents)?)
+        .ok_or_else(|| {
+            Error::InvalidPath(format!(
+                "couldn't make database entry for file: {}",
+                location.path
+            ))
+        })?;
+    Ok(vec![nrf])
+}
+
+fn gzip_entry_name(
+    header: Option<&flate2::GzHeader>,
+    location: &ArchiveLocation<'_>,
+) -> Option<PathBuf> {
+    let header_name = header
+        .and_then(flate2::GzHeader::filename)
+        .and_then(|name| std::str::from_utf8(name).ok())
+        .filter(|name| {
+            let mut components = Path::new(name).components();
+            matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none()
+        });
+    if let Some(name) = header_name {
+        return Some(PathBuf::from(name));
+    }
+
+    let container_name = location.container_name()?;
+    let stem = container_name
+        .strip_suffix(".gz")
+        .or_else(|| container_name.strip_suffix(".GZ"))
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or(container_name);
+    Some(PathBuf::from(stem))
+}
+
+fn scan_tar<R: Read>(
+    reader: R,
+    location: &ArchiveLocation<'_>,
+    nested_depth: usize,
+) -> crate::Result<Vec<NewRomFile>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut rom_files = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = safe_tar_entry_path(&entry.path()?)?;
+
+        if nested_depth > 0 {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            rom_files.extend(scan_entry_bytes(location, &name, &data, nested_depth)?);
+            continue;
+        }
+
+        let nrf = location
+            .rom_file(&name, hash_reader_chunks(&mut entry)?)
+            .ok_or_else(|| {
+                Error::InvalidPath(format!(
+                    "couldn't make database entry for file: {}",
+                    location.path
+                ))
+            })?;
+        rom_files.push(nrf);
+    }
+
+    Ok(rom_files)
+}
+
+/// Tar writers commonly prefix members with `./`; those components are
+/// dropped, and anything that could escape the archive root is rejected.
+fn safe_tar_entry_path(path: &Path) -> crate::Result<PathBuf> {
+    let mut safe_path = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) if part.to_str().is_some() => safe_path.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(Error::InvalidPath(format!(
+                    "unsafe tar entry name: {}",
+                    path.display()
+                )));
+            }
+        }
+    }
+
+    if safe_path.as_os_str().is_empty() {
+        return Err(Error::InvalidPath(format!(
+            "unsafe tar entry name: {}",
+            path.display()
+        )));
+    }
+    Ok(safe_path)
+}
+
+fn scan_7z(
+    path: &Utf8Path,
+    location: &ArchiveLocation<'_>,
+    nested_depth: usize,
+) -> crate::Result<Vec<NewRomFile>> {
     let archive = r7z::Archive::open(path.as_std_path())?;
     let mut rom_files = Vec::new();
+    let mut nested_archives = Vec::new();
 
     archive.stream_files(|entry, reader| {
         let filename = entry
             .safe_path()
             .ok_or_else(|| r7z::R7zError::UnsafePath(entry.name.clone()))?;
-        let mut hash_writer = RomHashWriter::default();
-        std::io::copy(reader, &mut hash_writer)?;
-        let (sha1, xxh3) = hash_writer.finish();
-        if let Some(nrf) = NewRomFile::from_archive(path, filename, sha1, xxh3) {
+        let hashes = if nested_depth > 0 {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            let hashes = rom_hashes(&data);
+            if ArchiveKind::of_bytes(&data).is_some() {
+                nested_archives.push((filename.to_owned(), data));
+            }
+            hashes
+        } else {
+            let mut hasher = RomHasher::default();
+            std::io::copy(reader, &mut hasher)?;
+            hasher.finish()
+        };
+        if let Some(nrf) = location.rom_file(filename, hashes) {
             rom_files.push(nrf);
         }
         Ok(())
     })?;
 
-    Ok(rom_files)
-}
-
-fn hash_reader_chunks<R: Read>(mut reader: R) -> crate::Result<(Sha1Digest, Xxh3Digest)> {
-    let mut sha1hasher = Sha1::new();
-    let mut xxhash3 = Xxh3::new();
-    let mut scratch = vec![0_u8; 64 * 1024];
-
-    loop {
-        let read = reader.read(&mut scratch)?;
-        if read == 0 {
-            break;
+    for (filename, data) in nested_archives {
+        if let Some(nested) = location.nested(&filename) {
+            rom_files.extend(scan_nested_archive(&nested, &data, nested_depth - 1)?);
         }
-        let chunk = &scratch[..read];
-        sha1hasher.update(chunk);
-        xxhash3.update(chunk);
     }
 
-    Ok((sha1hasher.finalize().into(), xxhash3.digest().to_be_bytes()))
+    Ok(rom_files)
 }
 
-#[derive(Default)]
-struct RomHashWriter {
-    sha1: Sha1,
-    xxhash3: Xxh3,
-}
+/// Record an archive entry that has already been read into memory and, while
+/// `nested_depth` allows, descend into it when it is itself an archive.
+fn scan_entry_bytes(
+    location: &ArchiveLocation<'_>,
+    name: &Path,
+    data: &[u8],
+    nested_depth: usize,
+) -> crate::Result<Vec<NewRomFile>> {
+    let nrf = location.rom_file(name, rom_hashes(data)).ok_or_else(|| {
+        Error::InvalidPath(format!(
+            "couldn't make database entry for file: {}",
+            location.path
+        ))
+    })?;
+    let mut rom_files = vec![nrf];
 
-impl RomHashWriter {
-    fn finish(self) -> (Sha1Digest, Xxh3Digest) {
-        (
-            self.sha1.finalize().into(),
-            self.xxhash3.digest().to_be_bytes(),
-        )
+    if nested_depth > 0
+        && let Some(nested) = location.nested(name)
+    {
+        rom_files.extend(scan_nested_archive(&nested, data, nested_depth - 1)?);
     }
-}
 
-impl Write for RomHashWriter {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.sha1.update(buf);
-        self.xxhash3.update(buf);
-        Ok(buf.len())
-    }
+    Ok(rom_files)
+}
 
-    fn flush(&mut self) -> std::io::Result<()> {
-        Ok(())
+fn scan_nested_archive(
+    location: &ArchiveLocation<'_>,
+    data: &[u8],
+    nested_depth: usize,
+) -> crate::Result<Vec<NewRomFile>> {
+    match ArchiveKind::of_bytes(data) {
+        Some(ArchiveKind::Zip) => scan_zip_reader(Cursor::new(data), location, nested_depth),
+        Some(ArchiveKind::SevenZip) => {
+            with_spooled_file(data, |path| scan_7z(path, location, nested_depth))
+        }
+        Some(ArchiveKind::Rar) => {
+            with_spooled_file(data, |path| scan_rar(path, location, nested_depth))
+        }
+        Some(ArchiveKind::Gzip) => scan_gzip(data, location, nested_depth),
+        Some(ArchiveKind::Tar) => scan_tar(data, location, nested_depth),
+        None => Ok(Vec::new()),
     }
 }
 
@@ -272,29 +572,11 @@ fn optimize_file_order(mut dirs: Vec<DirEntry>) -> Vec<DirEntry> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_helpers::{gzip_bytes, tar_bytes, write_version_rar, zip_bytes};
     use std::collections::BTreeSet;
     use std::io::{self, Write};
     use zip::write::SimpleFileOptions;
 
-    fn make_test_zip(entries: &[(&str, &[u8])]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let cursor = std::io::Cursor::new(Vec::new());
-        let mut zip = zip::ZipWriter::new(cursor);
-        let options = SimpleFileOptions::default();
-        for (name, data) in entries {
-            zip.start_file(*name, options)?;
-            zip.write_all(data)?;
-        }
-        Ok(zip.finish()?.into_inner())
-    }
-
-    fn write_version_rar(path: &Utf8Path) -> Result<(), Box<dyn std::error::Error>> {
-        let archive = hex::decode(
-            "526172211a0700cf907300000d000000000000000f0c7420802700150000000b0000000345f37dc6a48a07471d330700a481000056455253494f4e0c008fec8a45cc23c848088362fe5fdd5c5388f072c43d7b00400700",
-        )?;
-        std::fs::write(path, archive)?;
-        Ok(())
-    }
-
     #[test]
     fn walk_for_files_skips_hidden_entries_below_root() -> Result<(), Box<dyn std::error::Error>> {
         let temp_dir = tempfile::tempdir()?;
@@ -331,7 +613,7 @@ mod tests {
         let path = root.join("game.rom");
         std::fs::write(&path, b"rom")?;
 
-        let rom_files = scan_path(&path)?;
+        let rom_files = scan_path(&path, 0)?;
 
         assert_eq!(rom_files.len(), 1);
         assert_eq!(rom_files[0].name, "game.rom");
@@ -346,7 +628,7 @@ mod tests {
     #[test]
     fn scan_zip_computes_correct_hashes() -> Result<(), Box<dyn std::error::Error>> {
         let content = b"hello rom";
-        let zip_data = make_test_zip(&[("test.rom", content)])?;
+        let zip_data = zip_bytes(&[("test.rom", content)])?;
 
         let expected_sha1 = crate::hashes::sha1_bytes(content);
         let expected_xxh = crate::hashes::xxhash3_bytes(content);
@@ -357,7 +639,7 @@ mod tests {
         let utf8_path = camino::Utf8Path::from_path(tmp.path())
             .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
         let mmap = crate::hashes::mmap_path(utf8_path)?;
-        let rom_files = scan_zip(&mmap)?;
+        let rom_files = scan_zip(&mmap, 0)?;
 
         assert_eq!(rom_files.len(), 1);
         assert_eq!(rom_files[0].sha1, expected_sha1);
@@ -368,7 +650,7 @@ mod tests {
     #[test]
     fn scan_zip_multiple_entries() -> Result<(), Box<dyn std::error::Error>> {
         let entries = [("a.rom", b"aaaa" as &[u8]), ("b.rom", b"bbbb" as &[u8])];
-        let zip_data = make_test_zip(&entries)?;
+        let zip_data = zip_bytes(&entries)?;
 
         let tmp = tempfile::NamedTempFile::new()?;
         std::fs::write(tmp.path(), &zip_data)?;
@@ -376,7 +658,7 @@ mod tests {
         let utf8_path = camino::Utf8Path::from_path(tmp.path())
             .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
         let mmap = crate::hashes::mmap_path(utf8_path)?;
-        let rom_files = scan_zip(&mmap)?;
+        let rom_files = scan_zip(&mmap, 0)?;
 
         assert_eq!(rom_files.len(), 2);
         // Verify hashes differ between entries
@@ -400,7 +682,7 @@ mod tests {
         let utf8_path = camino::Utf8Path::from_path(tmp.path())
             .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
         let mmap = crate::hashes::mmap_path(utf8_path)?;
-        let rom_files = scan_zip(&mmap)?;
+        let rom_files = scan_zip(&mmap, 0)?;
 
         assert_eq!(rom_files.len(), 1);
         assert_eq!(rom_files[0].name, "nested/game.rom");
@@ -409,14 +691,14 @@ mod tests {
 
     #[test]
     fn scan_zip_rejects_unsafe_enclosed_names() -> Result<(), Box<dyn std::error::Error>> {
-        let zip_data = make_test_zip(&[("../evil.rom", b"evil" as &[u8])])?;
+        let zip_data = zip_bytes(&[("../evil.rom", b"evil" as &[u8])])?;
         let tmp = tempfile::NamedTempFile::new()?;
         std::fs::write(tmp.path(), &zip_data)?;
         let utf8_path = camino::Utf8Path::from_path(tmp.path())
             .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
         let mmap = crate::hashes::mmap_path(utf8_path)?;
 
-        let Err(error) = scan_zip(&mmap) else {
+        let Err(error) = scan_zip(&mmap, 0) else {
             return Err("expected unsafe zip entry to fail".into());
         };
 
@@ -435,7 +717,16 @@ mod tests {
         std::fs::write(root.join("c.rom"), b"c")?;
         let files = walk_for_files(root, &[]);
 
-        let normalize = |mut rom_files: Vec<NewRomFile>| {
+        let normalize = |scan: SourceScan| {
+            let mut rom_files = scan
+                .bare_files
+                .into_iter()
+                .chain(
+                    scan.archives
+                        .into_iter()
+                        .flat_map(|(_, rom_files)| rom_files),
+                )
+                .collect::<Vec<_>>();
             rom_files.sort_by(|left, right| left.name.cmp(&right.name));
             rom_files
                 .into_iter()
@@ -443,9 +734,10 @@ mod tests {
                 .collect::<Vec<_>>()
         };
 
-        let jobs_zero = normalize(get_all_rom_files(&files, 0)?);
-        let jobs_one = normalize(get_all_rom_files(&files, 1)?);
-        let jobs_two = normalize(get_all_rom_files(&files, 2)?);
+        let known_archives = BTreeMap::new();
+        let jobs_zero = normalize(get_all_rom_files(&files, 0, 0, &known_archives)?);
+        let jobs_one = normalize(get_all_rom_files(&files, 1, 0, &known_archives)?);
+        let jobs_two = normalize(get_all_rom_files(&files, 2, 0, &known_archives)?);
 
         assert_eq!(jobs_zero, jobs_one);
         assert_eq!(jobs_one, jobs_two);
@@ -459,7 +751,7 @@ mod tests {
         let utf8_path = camino::Utf8Path::from_path(tmp.path())
             .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
 
-        let Err(error) = scan_path(utf8_path) else {
+        let Err(error) = scan_path(utf8_path, 0) else {
             return Err("expected corrupt zip scan to fail".into());
         };
 
@@ -474,7 +766,7 @@ mod tests {
         let utf8_path = camino::Utf8Path::from_path(tmp.path())
             .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
 
-        let Err(error) = scan_path(utf8_path) else {
+        let Err(error) = scan_path(utf8_path, 0) else {
             return Err("expected corrupt archive scan to fail".into());
         };
 
@@ -489,7 +781,7 @@ mod tests {
         let utf8_path = camino::Utf8Path::from_path(tmp.path())
             .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
 
-        let Err(error) = scan_path(utf8_path) else {
+        let Err(error) = scan_path(utf8_path, 0) else {
             return Err("expected corrupt RAR scan to fail".into());
         };
 
@@ -497,6 +789,50 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn scan_source_path_skips_archive_with_matching_stamp_and_depth()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempfile::NamedTempFile::new()?;
+        let utf8_path = camino::Utf8Path::from_path(tmp.path())
+            .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
+        let mut zip = zip::ZipWriter::new(File::create(utf8_path)?);
+        zip.start_file("game.rom", zip::write::SimpleFileOptions::default())?;
+        zip.write_all(b"rom")?;
+        zip.finish()?;
+
+        let ScannedPath::Archive(archive_file, rom_files) =
+            scan_source_path(utf8_path, 0, &BTreeMap::new())?
+        else {
+            return Err("expected a scanned archive".into());
+        };
+        assert_eq!(rom_files.len(), 1);
+        assert_eq!(
+            archive_file.sha1,
+            crate::hashes::sha1_bytes(&std::fs::read(utf8_path)?)
+        );
+
+        let known_archives = BTreeMap::from([(
+            utf8_path.to_string(),
+            ArchiveFile {
+                id: 7,
+                path: archive_file.path,
+                sha1: archive_file.sha1.to_vec(),
+                size: archive_file.size,
+                mtime: archive_file.mtime,
+                nested_depth: archive_file.nested_depth,
+            },
+        )]);
+        assert!(matches!(
+            scan_source_path(utf8_path, 0, &known_archives)?,
+            ScannedPath::Unchanged(7)
+        ));
+        assert!(matches!(
+            scan_source_path(utf8_path, 1, &known_archives)?,
+            ScannedPath::Archive(..)
+        ));
+        Ok(())
+    }
+
     #[test]
     fn scan_rar_reads_file_entries() -> Result<(), Box<dyn std::error::Error>> {
         let tmp = tempfile::NamedTempFile::new()?;
@@ -504,7 +840,7 @@ mod tests {
             .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
         write_version_rar(utf8_path)?;
 
-        let rom_files = scan_path(utf8_path)?;
+        let rom_files = scan_path(utf8_path, 0)?;
 
         assert_eq!(rom_files.len(), 1);
         assert_eq!(rom_files[0].name, "VERSION");
@@ -528,7 +864,7 @@ mod tests {
 
         let utf8_path = camino::Utf8Path::from_path(tmp.path())
             .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
-        let rom_files = scan_7z(utf8_path)?;
+        let rom_files = scan_7z(utf8_path, &ArchiveLocation::new(utf8_path), 0)?;
 
         assert_eq!(rom_files.len(), 1);
         assert_eq!(rom_files[0].name, "nested/game.rom");
@@ -536,4 +872,218 @@ mod tests {
         assert_eq!(rom_files[0].xxhash3, crate::hashes::xxhash3_bytes(b"rom"));
         Ok(())
     }
+
+    #[test]
+    fn scan_zip_leaves_nested_archives_opaque_at_depth_zero()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let inner = zip_bytes(&[("inner.rom", b"inner" as &[u8])])?;
+        let outer = zip_bytes(&[("inner.zip", inner.as_slice())])?;
+        let tmp = tempfile::NamedTempFile::new()?;
+        std::fs::write(tmp.path(), &outer)?;
+        let utf8_path = camino::Utf8Path::from_path(tmp.path())
+            .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
+
+        let rom_files = scan_path(utf8_path, 0)?;
+
+        assert_eq!(rom_files.len(), 1);
+        assert_eq!(rom_files[0].name, "inner.zip");
+        assert_eq!(rom_files[0].sha1, crate::hashes::sha1_bytes(&inner));
+        assert!(rom_files[0].archive_chain.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn scan_zip_descends_into_nested_archives() -> Result<(), Box<dyn std::error::Error>> {
+        let seven_zip = r7z::ArchiveBuilder::new()
+            .add_file("deep.rom", b"deep")
+            .build()?;
+        let inner = zip_bytes(&[
+            ("inner.rom", b"inner" as &[u8]),
+            ("pack.7z", seven_zip.as_slice()),
+        ])?;
+        let outer = zip_bytes(&[("inner.zip", inner.as_slice())])?;
+        let tmp = tempfile::NamedTempFile::new()?;
+        std::fs::write(tmp.path(), &outer)?;
+        let utf8_path = camino::Utf8Path::from_path(tmp.path())
+            .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
+
+        let rom_files = scan_path(utf8_path, 2)?
+            .into_iter()
+            .map(|rom_file| (rom_file.name, rom_file.archive_chain, rom_file.sha1))
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(
+            rom_files,
+            BTreeSet::from([
+                (
+                    "inner.zip".to_owned(),
+                    None,
+                    crate::hashes::sha1_bytes(&inner)
+                ),
+                (
+                    "inner.rom".to_owned(),
+                    Some("inner.zip".to_owned()),
+                    crate::hashes::sha1_bytes(b"inner")
+                ),
+                (
+                    "pack.7z".to_owned(),
+                    Some("inner.zip".to_owned()),
+                    crate::hashes::sha1_bytes(&seven_zip)
+                ),
+                (
+                    "deep.rom".to_owned(),
+                    Some("inner.zip\npack.7z".to_owned()),
+                    crate::hashes::sha1_bytes(b"deep")
+                ),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn nested_scan_stops_at_configured_depth() -> Result<(), Box<dyn std::error::Error>> {
+        let innermost = zip_bytes(&[("deep.rom", b"deep" as &[u8])])?;
+        let inner = zip_bytes(&[("innermost.zip", innermost.as_slice())])?;
+        let outer = zip_bytes(&[("inner.zip", inner.as_slice())])?;
+        let tmp = tempfile::NamedTempFile::new()?;
+        std::fs::write(tmp.path(), &outer)?;
+        let utf8_path = camino::Utf8Path::from_path(tmp.path())
+            .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
+
+        let names = scan_path(utf8_path, 1)?
+            .into_iter()
+            .map(|rom_file| rom_file.name)
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(
+            names,
+            BTreeSet::from(["inner.zip".to_owned(), "innermost.zip".to_owned()])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn scan_gzip_names_contents_after_header_or_container() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let temp_dir = tempfile::tempdir()?;
+        let root = Utf8Path::from_path(temp_dir.path())
+            .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
+        let unnamed = root.join("game.rom.gz");
+        std::fs::write(&unnamed, gzip_bytes(b"rom", None)?)?;
+        let named = root.join("renamed.gz");
+        std::fs::write(&named, gzip_bytes(b"rom", Some("header.rom"))?)?;
+
+        let unnamed_files = scan_path(&unnamed, 0)?;
+        let named_files = scan_path(&named, 0)?;
+
+        assert_eq!(unnamed_files.len(), 1);
+        assert_eq!(unnamed_files[0].name, "game.rom");
+        assert_eq!(unnamed_files[0].path, unnamed.as_str());
+        assert_eq!(unnamed_files[0].sha1, crate::hashes::sha1_bytes(b"rom"));
+        assert!(unnamed_files[0].in_archive);
+        assert_eq!(named_files.len(), 1);
+        assert_eq!(named_files[0].name, "header.rom");
+        Ok(())
+    }
+
+    #[test]
+    fn scan_tar_gz_reads_member_files() -> Result<(), Box<dyn std::error::Error>> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut directory = tar::Header::new_gnu();
+        directory.set_entry_type(tar::EntryType::Directory);
+        directory.set_size(0);
+        directory.set_mode(0o755);
+        directory.set_cksum();
+        builder.append_data(&mut directory, "nested/", io::empty())?;
+        let tar_data = {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(3);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, "./nested/game.rom", b"rom" as &[u8])?;
+            builder.into_inner()?
+        };
+        let tmp = tempfile::NamedTempFile::new()?;
+        std::fs::write(tmp.path(), gzip_bytes(&tar_data, None)?)?;
+        let utf8_path = camino::Utf8Path::from_path(tmp.path())
+            .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
+
+        let rom_files = scan_path(utf8_path, 0)?;
+
+        assert_eq!(rom_files.len(), 1);
+        assert_eq!(rom_files[0].name, "nested/game.rom");
+        assert_eq!(rom_files[0].sha1, crate::hashes::sha1_bytes(b"rom"));
+        assert_eq!(rom_files[0].xxhash3, crate::hashes::xxhash3_bytes(b"rom"));
+        Ok(())
+    }
+
+    #[test]
+    fn scan_tar_reads_member_files() -> Result<(), Box<dyn std::error::Error>> {
+        let tar_data = tar_bytes(&[("a.rom", b"aaaa" as &[u8]), ("b.rom", b"bbbb" as &[u8])])?;
+        let tmp = tempfile::NamedTempFile::new()?;
+        std::fs::write(tmp.path(), &tar_data)?;
+        let utf8_path = camino::Utf8Path::from_path(tmp.path())
+            .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
+
+        let names = scan_path(utf8_path, 0)?
+            .into_iter()
+            .map(|rom_file| (rom_file.name, rom_file.sha1))
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(
+            names,
+            BTreeSet::from([
+                ("a.rom".to_owned(), crate::hashes::sha1_bytes(b"aaaa")),
+                ("b.rom".to_owned(), crate::hashes::sha1_bytes(b"bbbb")),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn scan_tar_rejects_unsafe_entry_names() -> Result<(), Box<dyn std::error::Error>> {
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..11].copy_from_slice(b"../evil.rom");
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append(&header, b"evil" as &[u8])?;
+        let tar_data = builder.into_inner()?;
+
+        let Err(error) = scan_tar(
+            tar_data.as_slice(),
+            &ArchiveLocation::new(Utf8Path::new("/source/evil.tar")),
+            0,
+        ) else {
+            return Err("expected unsafe tar entry to fail".into());
+        };
+
+        assert!(error.to_string().contains("unsafe tar entry name"));
+        Ok(())
+    }
+
+    #[test]
+    fn nested_scan_descends_into_gzip_inside_zip() -> Result<(), Box<dyn std::error::Error>> {
+        let gzip = gzip_bytes(b"deep", None)?;
+        let outer = zip_bytes(&[("deep.rom.gz", gzip.as_slice())])?;
+        let tmp = tempfile::NamedTempFile::new()?;
+        std::fs::write(tmp.path(), &outer)?;
+        let utf8_path = camino::Utf8Path::from_path(tmp.path())
+            .ok_or_else(|| io::Error::other("temp path is not UTF-8"))?;
+
+        let rom_files = scan_path(utf8_path, 1)?
+            .into_iter()
+            .map(|rom_file| (rom_file.name, rom_file.archive_chain))
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(
+            rom_files,
+            BTreeSet::from([
+                ("deep.rom.gz".to_owned(), None),
+                ("deep.rom".to_owned(), Some("deep.rom.gz".to_owned())),
+            ])
+        );
+        Ok(())
+    }
 }
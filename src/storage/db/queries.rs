@@ -1,8 +1,14 @@
 use crate::{
     logiqx,
     storage::{
-        db::Pool as DbPool, models::NewDataFile, models::NewGame, models::NewRom,
+        db::Pool as DbPool,
+        models::NewDataFile,
+        models::NewGame,
+        models::NewRelease,
+        models::NewRom,
         models::NewRomFile,
+        models::NewSample,
+        models::{ArchiveFile, NewArchiveFile},
     },
 };
 
@@ -25,33 +31,160 @@ pub fn traverse_and_insert_data_file(
     let mut conn = pool.get()?;
 
     conn.transaction::<_, crate::Error, _>(|conn| {
-        delete_existing_data_file_children(conn, new_data_file.name())?;
-        let data_file_id = upsert_data_file(conn, &new_data_file)?;
-        insert_games_and_roms(conn, logiqx_data_file, data_file_id)?;
+        delete_same_data_file_version(conn, &new_data_file)?;
+        let data_file_id = insert_data_file(conn, &new_data_file)?;
+        insert_games(conn, logiqx_data_file, data_file_id)?;
         update_parent_links(conn, data_file_id)?;
         associate_rom_files(conn)?;
         Ok(data_file_id)
     })
 }
 
-fn delete_existing_data_file_children(conn: &mut SqliteConnection, name: &str) -> QueryResult<()> {
+/// Drop an earlier import of the same DAT version: the same name and SHA1,
+/// or for DATs read without a SHA1, the same name and version.
+fn delete_same_data_file_version(
+    conn: &mut SqliteConnection,
+    new_data_file: &NewDataFile<'_>,
+) -> QueryResult<()> {
     use crate::storage::schema::data_files::dsl as data_files_dsl;
 
-    if let Some(existing_data_file_id) = data_files_dsl::data_files
-        .filter(data_files_dsl::name.eq(name))
-        .select(data_files_dsl::id)
-        .first::<i32>(conn)
-        .optional()?
-    {
-        delete_data_file_children(conn, existing_data_file_id)?;
-    }
+    let same_name =
+        data_files_dsl::data_files.filter(data_files_dsl::name.eq(new_data_file.name()));
+    let existing_ids = match new_data_file.sha1() {
+        Some(sha1) => same_name
+            .filter(data_files_dsl::sha1.eq(sha1))
+            .select(data_files_dsl::id)
+            .load::<i32>(conn)?,
+        None => same_name
+            .filter(data_files_dsl::sha1.is_null())
+            .filter(data_files_dsl::version.is(new_data_file.version()))
+            .select(data_files_dsl::id)
+            .load::<i32>(conn)?,
+    };
+    existing_ids.into_iter().try_for_each(|existing_id| {
+        delete_data_file_children(conn, existing_id)?;
+        diesel::delete(data_files_dsl::data_files.find(existing_id)).execute(conn)?;
+        Ok(())
+    })
+}
+
+/// Delete DATs with their games and ROMs in one transaction, then relink
+/// scanned files that another DAT still expects. Returns the number of
+/// games and ROMs removed.
+pub fn remove_data_files(pool: &DbPool, data_file_ids: &[i32]) -> crate::Result<(usize, usize)> {
+    use crate::storage::schema::data_files::dsl as data_files_dsl;
+
+    let mut conn = pool.get()?;
+
+    Ok(conn.transaction::<_, DieselError, _>(|conn| {
+        let removed = data_file_ids
+            .iter()
+            .try_fold((0, 0), |(games, roms), &id| {
+                let (removed_games, removed_roms) = delete_data_file_children(conn, id)?;
+                diesel::delete(data_files_dsl::data_files.find(id)).execute(conn)?;
+                Ok::<_, DieselError>((games + removed_games, roms + removed_roms))
+            })?;
+        associate_rom_files(conn)?;
+        Ok(removed)
+    })?)
+}
+
+/// Forget everything scanned below `source_root`.
+pub fn remove_source_root(pool: &DbPool, source_root: &Utf8Path) -> crate::Result<RemovedRows> {
+    use crate::storage::schema::source_roots::dsl as source_roots_dsl;
+
+    let mut conn = pool.get()?;
+
+    Ok(conn.transaction::<_, DieselError, _>(|conn| {
+        stage_kept_archive_ids(conn, &[])?;
+        Ok(RemovedRows {
+            rom_files: delete_rom_files_for_source_root(conn, source_root.as_str())?,
+            archives: delete_archive_files_for_source_root(conn, source_root.as_str())?,
+            source_roots: diesel::delete(
+                source_roots_dsl::source_roots
+                    .filter(source_roots_dsl::path.eq(source_root.as_str())),
+            )
+            .execute(conn)?,
+        })
+    })?)
+}
+
+/// Scan rows removed from the cache, per table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RemovedRows {
+    pub rom_files: usize,
+    pub archives: usize,
+    pub source_roots: usize,
+}
+
+/// Drop cached rows for files and roots that are gone from disk, then
+/// `VACUUM` the database to release the space.
+pub fn collect_garbage(pool: &DbPool) -> crate::Result<RemovedRows> {
+    use crate::storage::schema::{
+        archive_files::dsl as archive_files_dsl, rom_files::dsl as rom_files_dsl,
+        source_roots::dsl as source_roots_dsl,
+    };
+
+    let mut conn = pool.get()?;
+    let missing_rom_file_paths = missing_paths(
+        rom_files_dsl::rom_files
+            .select(rom_files_dsl::path)
+            .distinct()
+            .load::<String>(&mut conn)?,
+    );
+    let missing_archive_paths = missing_paths(
+        archive_files_dsl::archive_files
+            .select(archive_files_dsl::path)
+            .load::<String>(&mut conn)?,
+    );
+    let missing_source_roots = missing_paths(
+        source_roots_dsl::source_roots
+            .select(source_roots_dsl::path)
+            .load::<String>(&mut conn)?,
+    );
+
+    let counts = conn.transaction::<_, DieselError, _>(|conn| {
+        let mut counts = RemovedRows::default();
+        for paths in missing_rom_file_paths.chunks(DELETE_CHUNK_LEN) {
+            counts.rom_files +=
+                diesel::delete(rom_files_dsl::rom_files.filter(rom_files_dsl::path.eq_any(paths)))
+                    .execute(conn)?;
+        }
+        for paths in missing_archive_paths.chunks(DELETE_CHUNK_LEN) {
+            counts.archives += diesel::delete(
+                archive_files_dsl::archive_files.filter(archive_files_dsl::path.eq_any(paths)),
+            )
+            .execute(conn)?;
+        }
+        for paths in missing_source_roots.chunks(DELETE_CHUNK_LEN) {
+            counts.source_roots += diesel::delete(
+                source_roots_dsl::source_roots.filter(source_roots_dsl::path.eq_any(paths)),
+            )
+            .execute(conn)?;
+        }
+        Ok(counts)
+    })?;
+    sql_query("VACUUM").execute(&mut conn)?;
+    Ok(counts)
+}
+
+/// Stay well below the `SQLite` bound-parameter limit.
+const DELETE_CHUNK_LEN: usize = 500;
 
-    Ok(())
+fn missing_paths(paths: Vec<String>) -> Vec<String> {
+    paths
+        .into_iter()
+        .filter(|path| !Utf8Path::new(path).exists())
+        .collect()
 }
 
-fn delete_data_file_children(conn: &mut SqliteConnection, data_file_id: i32) -> QueryResult<()> {
+fn delete_data_file_children(
+    conn: &mut SqliteConnection,
+    data_file_id: i32,
+) -> QueryResult<(usize, usize)> {
     use crate::storage::schema::{
-        games::dsl as games_dsl, rom_files::dsl as rom_files_dsl, roms::dsl as roms_dsl,
+        games::dsl as games_dsl, releases::dsl as releases_dsl, rom_files::dsl as rom_files_dsl,
+        roms::dsl as roms_dsl, samples::dsl as samples_dsl,
     };
 
     let game_ids = games_dsl::games
@@ -69,39 +202,49 @@ fn delete_data_file_children(conn: &mut SqliteConnection, data_file_id: i32) ->
             .execute(conn)?;
     }
 
-    if !game_ids.is_empty() {
-        diesel::delete(roms_dsl::roms.filter(roms_dsl::game_id.eq_any(game_ids))).execute(conn)?;
-    }
+    let roms = if game_ids.is_empty() {
+        0
+    } else {
+        diesel::delete(samples_dsl::samples.filter(samples_dsl::game_id.eq_any(&game_ids)))
+            .execute(conn)?;
+        diesel::delete(releases_dsl::releases.filter(releases_dsl::game_id.eq_any(&game_ids)))
+            .execute(conn)?;
+        diesel::delete(roms_dsl::roms.filter(roms_dsl::game_id.eq_any(game_ids))).execute(conn)?
+    };
 
-    diesel::delete(games_dsl::games.filter(games_dsl::data_file_id.eq(data_file_id)))
+    let games = diesel::delete(games_dsl::games.filter(games_dsl::data_file_id.eq(data_file_id)))
         .execute(conn)?;
 
-    Ok(())
+    Ok((games, roms))
 }
 
-fn upsert_data_file(
+fn insert_data_file(
     conn: &mut SqliteConnection,
     new_data_file: &NewDataFile<'_>,
 ) -> QueryResult<i32> {
     use crate::storage::schema::data_files::dsl as data_files_dsl;
-    use diesel::replace_into;
 
-    replace_into(data_files_dsl::data_files)
+    diesel::insert_into(data_files_dsl::data_files)
         .values(new_data_file)
         .execute(conn)?;
 
     data_files_dsl::data_files
         .filter(data_files_dsl::name.eq(new_data_file.name()))
         .select(data_files_dsl::id)
+        .order(data_files_dsl::id.desc())
         .first(conn)
 }
 
-fn insert_games_and_roms(
+/// Insert each game with its releases, ROMs and samples.
+fn insert_games(
     conn: &mut SqliteConnection,
     logiqx_data_file: &logiqx::DataFile,
     data_file_id: i32,
 ) -> QueryResult<()> {
-    use crate::storage::schema::{games::dsl as games_dsl, roms::dsl as roms_dsl};
+    use crate::storage::schema::{
+        games::dsl as games_dsl, releases::dsl as releases_dsl, roms::dsl as roms_dsl,
+        samples::dsl as samples_dsl,
+    };
     use diesel::replace_into;
 
     logiqx_data_file.games().iter().try_for_each(|game| {
@@ -119,8 +262,27 @@ fn insert_games_and_roms(
         game.roms().iter().try_for_each(|rom| {
             let new_rom = NewRom::from_logiqx(rom, game_id);
             replace_into(roms_dsl::roms).values(new_rom).execute(conn)?;
-            Ok(())
-        })
+            Ok::<_, DieselError>(())
+        })?;
+
+        let new_releases = game
+            .releases()
+            .iter()
+            .map(|release| NewRelease::from_logiqx(release, game_id))
+            .collect::<Vec<_>>();
+        diesel::insert_into(releases_dsl::releases)
+            .values(&new_releases)
+            .execute(conn)?;
+
+        let new_samples = game
+            .samples()
+            .iter()
+            .map(|sample| NewSample::from_logiqx(sample, game_id))
+            .collect::<Vec<_>>();
+        diesel::insert_into(samples_dsl::samples)
+            .values(&new_samples)
+            .execute(conn)?;
+        Ok(())
     })
 }
 
@@ -156,18 +318,50 @@ pub fn import_rom_files(pool: &DbPool, new_rom_files: &[NewRomFile]) -> crate::R
     })?)
 }
 
+/// Replace the cached scan of `source_root`. Rows for archives listed in
+/// `unchanged_archive_ids` are kept as they are; everything else below the
+/// root is dropped and replaced by `new_rom_files` and `new_archives`.
 pub fn replace_rom_files_for_source_root(
     pool: &DbPool,
     source_root: &Utf8Path,
     new_rom_files: &[NewRomFile],
+    new_archives: Vec<(NewArchiveFile, Vec<NewRomFile>)>,
+    unchanged_archive_ids: &[i32],
 ) -> crate::Result<usize> {
-    use crate::storage::schema::rom_files::dsl::rom_files;
+    use crate::storage::schema::{
+        archive_files::dsl as archive_files_dsl, rom_files::dsl::rom_files,
+        source_roots::dsl as source_roots_dsl,
+    };
     use diesel::replace_into;
 
     let mut conn = pool.get()?;
 
     Ok(conn.transaction::<_, DieselError, _>(|conn| {
+        replace_into(source_roots_dsl::source_roots)
+            .values((
+                source_roots_dsl::path.eq(source_root.as_str()),
+                source_roots_dsl::scanned_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        stage_kept_archive_ids(conn, unchanged_archive_ids)?;
         delete_rom_files_for_source_root(conn, source_root.as_str())?;
+        delete_archive_files_for_source_root(conn, source_root.as_str())?;
+        for (new_archive_file, mut archive_rom_files) in new_archives {
+            replace_into(archive_files_dsl::archive_files)
+                .values(&new_archive_file)
+                .execute(conn)?;
+            let archive_file_id = archive_files_dsl::archive_files
+                .filter(archive_files_dsl::path.eq(&new_archive_file.path))
+                .select(archive_files_dsl::id)
+                .first::<i32>(conn)?;
+            for new_rom_file in &mut archive_rom_files {
+                new_rom_file.archive_file_id = Some(archive_file_id);
+            }
+            archive_rom_files
+                .iter()
+                .map(|new_rom_file| replace_into(rom_files).values(new_rom_file).execute(conn))
+                .collect::<QueryResult<Vec<usize>>>()?;
+        }
         new_rom_files
             .iter()
             .map(|new_rom_file| replace_into(rom_files).values(new_rom_file).execute(conn))
@@ -176,6 +370,91 @@ pub fn replace_rom_files_for_source_root(
     })?)
 }
 
+/// Archives below `source_root` whose entries are in the cache, so a rescan
+/// can skip the ones that have not changed on disk. Archives with an entry
+/// scanned before its CRC32, MD5 or size was recorded are left out, so they
+/// are rehashed the next time their source is scanned.
+pub fn scanned_archive_files(
+    pool: &DbPool,
+    source_root: &Utf8Path,
+) -> crate::Result<Vec<ArchiveFile>> {
+    let mut conn = pool.get()?;
+    let prefix = format!("{}/", source_root.as_str().trim_end_matches('/'));
+    Ok(sql_query(
+        r"
+        SELECT id, path, sha1, size, mtime, nested_depth
+        FROM archive_files
+        WHERE substr(path, 1, length(?)) = ?
+            AND EXISTS (
+                SELECT 1 FROM rom_files WHERE rom_files.archive_file_id = archive_files.id
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM rom_files
+                WHERE rom_files.archive_file_id = archive_files.id
+                    AND (rom_files.crc IS NULL OR rom_files.md5 IS NULL OR rom_files.size IS NULL)
+            )
+        ",
+    )
+    .bind::<diesel::sql_types::Text, _>(&prefix)
+    .bind::<diesel::sql_types::Text, _>(&prefix)
+    .load::<ArchiveFile>(&mut conn)?)
+}
+
+/// Archive rows recorded for exactly these paths.
+pub fn archive_files_by_path(pool: &DbPool, paths: &[String]) -> crate::Result<Vec<ArchiveFile>> {
+    use crate::storage::schema::archive_files::dsl as archive_files_dsl;
+
+    let mut conn = pool.get()?;
+    Ok(archive_files_dsl::archive_files
+        .filter(archive_files_dsl::path.eq_any(paths))
+        .load::<ArchiveFile>(&mut conn)?)
+}
+
+/// Record freshly written files. Any cached entries scanned from an earlier
+/// file at the same path are dropped because they no longer describe it.
+pub fn record_archive_files(
+    pool: &DbPool,
+    new_archive_files: &[NewArchiveFile],
+) -> crate::Result<usize> {
+    use crate::storage::schema::{
+        archive_files::dsl as archive_files_dsl, rom_files::dsl as rom_files_dsl,
+    };
+
+    let mut conn = pool.get()?;
+
+    Ok(conn.transaction::<_, DieselError, _>(|conn| {
+        new_archive_files
+            .iter()
+            .map(|new_archive_file| {
+                let existing_id = archive_files_dsl::archive_files
+                    .filter(archive_files_dsl::path.eq(&new_archive_file.path))
+                    .select(archive_files_dsl::id)
+                    .first::<i32>(conn)
+                    .optional()?;
+                if let Some(existing_id) = existing_id {
+                    diesel::delete(
+                        rom_files_dsl::rom_files
+                            .filter(rom_files_dsl::archive_file_id.eq(existing_id)),
+                    )
+                    .execute(conn)?;
+                    diesel::update(archive_files_dsl::archive_files.find(existing_id))
+                        .set((
+                            archive_files_dsl::sha1.eq(new_archive_file.sha1.as_slice()),
+                            archive_files_dsl::size.eq(new_archive_file.size),
+                            archive_files_dsl::mtime.eq(new_archive_file.mtime),
+                            archive_files_dsl::nested_depth.eq(new_archive_file.nested_depth),
+                        ))
+                        .execute(conn)
+                } else {
+                    diesel::insert_into(archive_files_dsl::archive_files)
+                        .values(new_archive_file)
+                        .execute(conn)
+                }
+            })
+            .sum::<QueryResult<usize>>()
+    })?)
+}
+
 pub fn database_file_paths(pool: &DbPool) -> crate::Result<Vec<Utf8PathBuf>> {
     let mut conn = pool.get()?;
     let rows = sql_query("SELECT file FROM pragma_database_list WHERE file != ''")
@@ -200,6 +479,20 @@ fn associate_rom_files(conn: &mut SqliteConnection) -> QueryResult<usize> {
         .execute(conn)
 }
 
+/// Load the archive ids a rescan keeps into a connection-local table that
+/// the source-root deletes below exclude.
+fn stage_kept_archive_ids(conn: &mut SqliteConnection, archive_ids: &[i32]) -> QueryResult<()> {
+    sql_query("CREATE TEMP TABLE IF NOT EXISTS kept_archive_files (id INTEGER PRIMARY KEY)")
+        .execute(conn)?;
+    sql_query("DELETE FROM temp.kept_archive_files").execute(conn)?;
+    archive_ids.iter().try_for_each(|archive_id| {
+        sql_query("INSERT OR IGNORE INTO temp.kept_archive_files (id) VALUES (?)")
+            .bind::<diesel::sql_types::Integer, _>(archive_id)
+            .execute(conn)
+            .map(|_| ())
+    })
+}
+
 fn delete_rom_files_for_source_root(
     conn: &mut SqliteConnection,
     source_root: &str,
@@ -207,11 +500,17 @@ fn delete_rom_files_for_source_root(
     sql_query(
         r"
         DELETE FROM rom_files
-        WHERE parent_path = ?
-            OR (
-                length(parent_path) > length(?)
-                AND substr(parent_path, 1, length(?)) = ?
-                AND substr(parent_path, length(?) + 1, 1) = '/'
+        WHERE (
+                parent_path = ?
+                OR (
+                    length(parent_path) > length(?)
+                    AND substr(parent_path, 1, length(?)) = ?
+                    AND substr(parent_path, length(?) + 1, 1) = '/'
+                )
+            )
+            AND (
+                archive_file_id IS NULL
+                OR archive_file_id NOT IN (SELECT id FROM temp.kept_archive_files)
             )
         ",
     )
@@ -223,6 +522,23 @@ fn delete_rom_files_for_source_root(
     .execute(conn)
 }
 
+fn delete_archive_files_for_source_root(
+    conn: &mut SqliteConnection,
+    source_root: &str,
+) -> QueryResult<usize> {
+    let prefix = format!("{}/", source_root.trim_end_matches('/'));
+    sql_query(
+        r"
+        DELETE FROM archive_files
+        WHERE substr(path, 1, length(?)) = ?
+            AND id NOT IN (SELECT id FROM temp.kept_archive_files)
+        ",
+    )
+    .bind::<diesel::sql_types::Text, _>(&prefix)
+    .bind::<diesel::sql_types::Text, _>(&prefix)
+    .execute(conn)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -249,4 +565,26 @@ mod tests {
         ))));
         Ok(())
     }
+
+    #[test]
+    fn scanned_archive_files_leave_out_archives_missing_entry_hashes()
+    -> Result<(), Box<dyn std::error::Error>> {
+        use std::io::Write;
+
+        let temp_dir = tempfile::tempdir()?;
+        let root = Utf8Path::from_path(temp_dir.path())
+            .ok_or("temporary path is not UTF-8")?
+            .canonicalize_utf8()?;
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(root.join("game.zip"))?);
+        writer.start_file("game.rom", zip::write::SimpleFileOptions::default())?;
+        writer.write_all(b"rom")?;
+        writer.finish()?;
+        let pool = crate::test_helpers::in_memory_pool()?;
+        crate::operations::source(&root, 1, 0, &pool)?;
+
+        assert_eq!(scanned_archive_files(&pool, &root)?.len(), 1);
+        sql_query("UPDATE rom_files SET md5 = NULL").execute(&mut pool.get()?)?;
+        assert!(scanned_archive_files(&pool, &root)?.is_empty());
+        Ok(())
+    }
 }
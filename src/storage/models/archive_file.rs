@@ -0,0 +1,78 @@
+use std::time::UNIX_EPOCH;
+
+use camino::Utf8Path;
+use diesel::{Identifiable, Insertable, Queryable, QueryableByName};
+
+use crate::{hashes::Sha1Digest, storage::schema::archive_files};
+
+/// A container file seen by a scan or written by a build, keyed by path.
+#[derive(Identifiable, Queryable, QueryableByName, PartialEq, Eq, Debug, Hash, Clone)]
+#[diesel(table_name = archive_files)]
+pub struct ArchiveFile {
+    pub id: i32,
+    pub path: String,
+    pub sha1: Vec<u8>,
+    pub size: i64,
+    pub mtime: i64,
+    pub nested_depth: i32,
+}
+
+impl ArchiveFile {
+    /// Whether the file on disk still has the size and modification time
+    /// recorded for it, so its cached contents can be trusted.
+    #[must_use]
+    pub const fn matches_stamp(&self, stamp: FileStamp) -> bool {
+        self.size == stamp.size && self.mtime == stamp.mtime
+    }
+}
+
+/// Size and modification time (nanoseconds since the Unix epoch) of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size: i64,
+    pub mtime: i64,
+}
+
+impl FileStamp {
+    pub fn from_path(path: &Utf8Path) -> std::io::Result<Self> {
+        let metadata = path.metadata()?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| {
+                i64::try_from(elapsed.as_nanos()).unwrap_or(i64::MAX)
+            });
+        Ok(Self {
+            size: i64::try_from(metadata.len()).unwrap_or(i64::MAX),
+            mtime,
+        })
+    }
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = archive_files)]
+pub struct New {
+    pub path: String,
+    pub sha1: Sha1Digest,
+    pub size: i64,
+    pub mtime: i64,
+    pub nested_depth: i32,
+}
+
+impl New {
+    #[must_use]
+    pub fn from_stamp(
+        path: &Utf8Path,
+        sha1: Sha1Digest,
+        stamp: FileStamp,
+        nested_depth: usize,
+    ) -> Self {
+        Self {
+            path: path.to_string(),
+            sha1,
+            size: stamp.size,
+            mtime: stamp.mtime,
+            nested_depth: i32::try_from(nested_depth).unwrap_or(i32::MAX),
+        }
+    }
+}
@@ -1,6 +1,7 @@
+use chrono::{NaiveDateTime, Utc};
 use diesel::{Identifiable, Insertable, Queryable};
 
-use crate::{logiqx, storage::schema::data_files};
+use crate::{domain::DatDirectives, logiqx, storage::schema::data_files};
 
 #[derive(Identifiable, Queryable, PartialEq, Eq, Debug)]
 #[diesel(table_name = data_files)]
@@ -18,6 +19,60 @@ pub struct DataFile {
     homepage: Option<String>,
     url: Option<String>,
     sha1: Option<Vec<u8>>,
+    imported_at: Option<NaiveDateTime>,
+    force_merging: Option<String>,
+    force_nodump: Option<String>,
+    force_packing: Option<String>,
+    header_skipper: Option<String>,
+    software_list: bool,
+}
+
+impl DataFile {
+    /// Rebuild the Logiqx data file this row was imported from.
+    #[must_use]
+    pub fn to_logiqx(&self, games: Vec<logiqx::Game>) -> logiqx::DataFile {
+        let header = logiqx::Header::new(
+            self.name.clone(),
+            self.description.clone(),
+            self.version.clone(),
+            self.author.clone(),
+            self.homepage.clone(),
+            self.url.clone(),
+        )
+        .with_category(self.category.clone())
+        .with_email(self.email.clone())
+        .with_directives(
+            self.force_merging.clone(),
+            self.force_nodump.clone(),
+            self.force_packing.clone(),
+            self.header_skipper.clone(),
+        );
+        let data_file = logiqx::DataFile::new(header, games)
+            .with_build(self.build.clone())
+            .with_debug(self.debug.clone());
+        if self.software_list {
+            data_file.with_software_list()
+        } else {
+            data_file
+        }
+    }
+
+    /// The list's name, when the DAT was imported from a MAME software list.
+    #[must_use]
+    pub fn software_list_name(&self) -> Option<&str> {
+        self.software_list.then_some(self.name.as_str())
+    }
+
+    /// The header's clrmamepro `forcemerging`, `forcenodump` and
+    /// `forcepacking` values.
+    #[must_use]
+    pub fn directives(&self) -> DatDirectives {
+        DatDirectives {
+            merging: self.force_merging.clone(),
+            nodump: self.force_nodump.clone(),
+            packing: self.force_packing.clone(),
+        }
+    }
 }
 
 #[derive(Insertable)]
@@ -28,11 +83,19 @@ pub struct New<'a> {
     file_name: Option<String>,
     name: String,
     description: Option<String>,
+    category: Option<String>,
     version: Option<String>,
     author: Option<String>,
+    email: Option<String>,
     homepage: Option<String>,
     url: Option<String>,
     sha1: Option<&'a [u8]>,
+    imported_at: Option<NaiveDateTime>,
+    force_merging: Option<String>,
+    force_nodump: Option<String>,
+    force_packing: Option<String>,
+    header_skipper: Option<String>,
+    software_list: bool,
 }
 
 impl New<'_> {
@@ -44,11 +107,19 @@ impl New<'_> {
             file_name: l_data_file.file_name().map(str::to_owned),
             name: l_data_file.header().name().to_owned(),
             description: l_data_file.header().description().cloned(),
+            category: l_data_file.header().category().cloned(),
             version: l_data_file.header().version().cloned(),
             author: l_data_file.header().author().cloned(),
+            email: l_data_file.header().email().cloned(),
             homepage: l_data_file.header().homepage().cloned(),
             url: l_data_file.header().url().cloned(),
             sha1: l_data_file.sha1(),
+            imported_at: Some(Utc::now().naive_utc()),
+            force_merging: l_data_file.header().forcemerging().map(str::to_owned),
+            force_nodump: l_data_file.header().forcenodump().map(str::to_owned),
+            force_packing: l_data_file.header().forcepacking().map(str::to_owned),
+            header_skipper: l_data_file.header().header_skipper().map(str::to_owned),
+            software_list: l_data_file.is_software_list(),
         }
     }
 
@@ -61,4 +132,9 @@ impl New<'_> {
     pub fn name(&self) -> &str {
         self.name.as_ref()
     }
+
+    #[must_use]
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
 }
@@ -20,6 +20,10 @@ pub struct Game {
     pub manufacturer: Option<String>,
     pub data_file_id: Option<i32>,
     pub parent_id: Option<i32>,
+    pub description: Option<String>,
+    pub sourcefile: Option<String>,
+    pub is_device: Option<String>,
+    pub is_mechanical: Option<String>,
 }
 
 impl Game {
@@ -27,6 +31,27 @@ impl Game {
     pub fn name(&self) -> &str {
         self.name.as_ref()
     }
+
+    /// Rebuild the Logiqx game this row was imported from.
+    #[must_use]
+    pub fn to_logiqx(&self, roms: Vec<logiqx::Rom>) -> logiqx::Game {
+        logiqx::Game::new(
+            self.name.clone(),
+            self.description.clone().unwrap_or_default(),
+            roms,
+        )
+        .with_sourcefile(self.sourcefile.clone().unwrap_or_default())
+        .with_isbios(self.is_bios.clone().unwrap_or_default())
+        .with_isdevice(self.is_device.clone().unwrap_or_default())
+        .with_ismechanical(self.is_mechanical.clone().unwrap_or_default())
+        .with_cloneof(self.clone_of.clone())
+        .with_romof(self.rom_of.clone().unwrap_or_default())
+        .with_sampleof(self.sample_of.clone().unwrap_or_default())
+        .with_board(self.board.clone().unwrap_or_default())
+        .with_rebuildto(self.rebuildto.clone().unwrap_or_default())
+        .with_year(self.year.clone().unwrap_or_default())
+        .with_manufacturer(self.manufacturer.clone().unwrap_or_default())
+    }
 }
 
 #[derive(Insertable, Debug)]
@@ -42,6 +67,10 @@ pub struct New {
     pub year: Option<String>,
     pub manufacturer: Option<String>,
     pub data_file_id: Option<i32>,
+    pub description: Option<String>,
+    pub sourcefile: Option<String>,
+    pub is_device: Option<String>,
+    pub is_mechanical: Option<String>,
 }
 
 impl New {
@@ -58,6 +87,10 @@ impl New {
             year: Some(logiqx.year().to_owned()),
             manufacturer: Some(logiqx.manufacturer().to_owned()),
             data_file_id: Some(data_file_id),
+            description: Some(logiqx.description().to_owned()),
+            sourcefile: Some(logiqx.sourcefile().to_owned()),
+            is_device: Some(logiqx.isdevice().to_owned()),
+            is_mechanical: Some(logiqx.ismechanical().to_owned()),
         }
     }
 }
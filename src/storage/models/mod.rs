@@ -1,11 +1,20 @@
+mod archive_file;
+pub use archive_file::{ArchiveFile, FileStamp, New as NewArchiveFile};
+
 mod data_file;
 pub use data_file::{DataFile, New as NewDataFile};
 
 mod game;
 pub use game::{Game, New as NewGame};
 
+mod release;
+pub use release::{New as NewRelease, Release};
+
 mod rom;
 pub use rom::{New as NewRom, Rom};
 
 mod rom_file;
-pub use rom_file::{New as NewRomFile, RomFile};
+pub use rom_file::{ARCHIVE_CHAIN_SEPARATOR, New as NewRomFile, RomFile};
+
+mod sample;
+pub use sample::{New as NewSample, Sample};
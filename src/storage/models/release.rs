@@ -0,0 +1,58 @@
+use diesel::{Associations, Insertable, Queryable};
+
+use super::Game;
+use crate::{logiqx, storage::schema::releases};
+
+#[derive(Queryable, Associations, PartialEq, Eq, Debug)]
+#[diesel(table_name = releases)]
+#[diesel(belongs_to(Game))]
+pub struct Release {
+    pub id: i32,
+    pub name: String,
+    pub region: String,
+    pub language: Option<String>,
+    pub date: Option<String>,
+    pub is_default: Option<String>,
+    pub game_id: i32,
+}
+
+impl Release {
+    /// Rebuild the Logiqx release this row was imported from.
+    #[must_use]
+    pub fn to_logiqx(&self) -> logiqx::Release {
+        logiqx::Release::new(self.name.clone(), self.region.clone())
+            .with_language(self.language.clone().unwrap_or_default())
+            .with_date(self.date.clone().unwrap_or_default())
+            .with_default(self.is_default.clone().unwrap_or_default())
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = releases)]
+pub struct New {
+    pub name: String,
+    pub region: String,
+    pub language: Option<String>,
+    pub date: Option<String>,
+    pub is_default: Option<String>,
+    pub game_id: i32,
+}
+
+impl New {
+    #[must_use]
+    pub fn from_logiqx(release: &logiqx::Release, game_id: i32) -> Self {
+        Self {
+            name: release.name().to_owned(),
+            region: release.region().to_owned(),
+            language: non_empty(release.language()),
+            date: non_empty(release.date()),
+            is_default: non_empty(release.default()),
+            game_id,
+        }
+    }
+}
+
+/// Attributes a release leaves out are stored as `NULL`, not `''`.
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_owned())
+}
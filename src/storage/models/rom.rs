@@ -19,6 +19,8 @@ pub struct Rom {
     pub inserted_at: Option<NaiveDateTime>,
     pub game_id: Option<i32>,
     pub archive_file_id: Option<i32>,
+    pub merge: Option<String>,
+    pub status: Option<String>,
 }
 
 impl Rom {
@@ -26,6 +28,26 @@ impl Rom {
     pub fn name(&self) -> &str {
         self.name.as_ref()
     }
+
+    /// Whether the DAT lists the ROM as not yet dumped.
+    #[must_use]
+    pub fn is_nodump(&self) -> bool {
+        self.status.as_deref() == Some("nodump")
+    }
+
+    /// Rebuild the Logiqx ROM this row was imported from.
+    #[must_use]
+    pub fn to_logiqx(&self) -> logiqx::Rom {
+        logiqx::Rom::new(
+            self.name.clone(),
+            self.size,
+            &self.crc,
+            &self.md5,
+            &self.sha1,
+        )
+        .with_merge(self.merge.clone().unwrap_or_default())
+        .with_status(self.status.clone().unwrap_or_default())
+    }
 }
 
 #[derive(Insertable, Debug)]
@@ -40,6 +62,8 @@ pub struct New {
     pub updated_at: Option<String>,
     pub inserted_at: Option<String>,
     pub game_id: i32,
+    pub merge: Option<String>,
+    pub status: Option<String>,
 }
 
 impl New {
@@ -55,6 +79,8 @@ impl New {
             updated_at: None,
             inserted_at: None,
             game_id,
+            merge: Some(rom.merge().to_owned()),
+            status: Some(rom.status().to_owned()),
         }
     }
 }
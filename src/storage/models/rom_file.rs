@@ -2,15 +2,17 @@ use std::path::Path;
 
 use camino::Utf8Path;
 use diesel::{Associations, Insertable, Queryable};
+use fmmap::MmapFileExt;
 
 use crate::{
-    hashes::{self, Sha1Digest, Xxh3Digest},
+    hashes::{self, Crc32Digest, Md5Digest, RomHashes, Sha1Digest, Xxh3Digest},
     storage::schema::rom_files,
 };
 
 #[derive(Queryable, Associations, PartialEq, Eq, Debug, Hash)]
 #[diesel(table_name = rom_files)]
 #[diesel(belongs_to(crate::storage::models::Rom))]
+#[diesel(belongs_to(crate::storage::models::ArchiveFile))]
 pub struct RomFile {
     pub id: i32,
     pub parent_path: String,
@@ -23,6 +25,29 @@ pub struct RomFile {
     pub xxhash3: Vec<u8>,
     pub in_archive: bool,
     pub rom_id: Option<i32>,
+    pub archive_chain: Option<String>,
+    pub archive_file_id: Option<i32>,
+    pub size: Option<i64>,
+}
+
+/// Separates container entry names in `rom_files.archive_chain`. Entry names
+/// containing it are never descended into, so the encoding stays unambiguous.
+pub const ARCHIVE_CHAIN_SEPARATOR: &str = "\n";
+
+impl RomFile {
+    /// Entry names of the containers between `path` and `name`, outermost first.
+    #[must_use]
+    pub fn archive_chain(&self) -> Vec<String> {
+        self.archive_chain
+            .as_deref()
+            .map(|chain| {
+                chain
+                    .split(ARCHIVE_CHAIN_SEPARATOR)
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Insertable, Debug)]
@@ -33,16 +58,20 @@ pub struct New {
     pub name: String,
     pub sha1: Sha1Digest,
     pub xxhash3: Xxh3Digest,
+    pub crc: Crc32Digest,
+    pub md5: Md5Digest,
+    pub size: i64,
     pub in_archive: bool,
     pub rom_id: Option<i32>,
+    pub archive_chain: Option<String>,
+    pub archive_file_id: Option<i32>,
 }
 
 impl New {
     #[must_use]
     pub fn from_path(path: &Utf8Path) -> Option<Self> {
         let mmap = hashes::mmap_path(path).ok()?;
-        let sha1 = hashes::stream_sha1(&mmap);
-        let xxhash3 = hashes::stream_xxhash3(&mmap);
+        let hashes = hashes::rom_hashes(mmap.as_slice());
 
         let name = path.file_name()?.to_owned();
         let parent_path = path.parent()?.to_string();
@@ -51,20 +80,20 @@ impl New {
             parent_path,
             path,
             name,
-            sha1,
-            xxhash3,
+            sha1: hashes.sha1,
+            xxhash3: hashes.xxhash3,
+            crc: hashes.crc32,
+            md5: hashes.md5,
+            size: i64::try_from(hashes.size).ok()?,
             in_archive: false,
             rom_id: None,
+            archive_chain: None,
+            archive_file_id: None,
         })
     }
 
     #[must_use]
-    pub fn from_archive(
-        path: &Utf8Path,
-        name: &Path,
-        sha1: Sha1Digest,
-        xxhash3: Xxh3Digest,
-    ) -> Option<Self> {
+    pub fn from_archive(path: &Utf8Path, name: &Path, hashes: RomHashes) -> Option<Self> {
         let parent_path = path.parent()?.to_string();
         let path = path.to_string();
         let name = name.to_str()?.to_owned();
@@ -72,13 +101,25 @@ impl New {
             parent_path,
             path,
             name,
-            sha1,
-            xxhash3,
+            sha1: hashes.sha1,
+            xxhash3: hashes.xxhash3,
+            crc: hashes.crc32,
+            md5: hashes.md5,
+            size: i64::try_from(hashes.size).ok()?,
             in_archive: true,
             rom_id: None,
+            archive_chain: None,
+            archive_file_id: None,
         })
     }
 
+    /// Record the containers this archive entry is nested inside, outermost first.
+    #[must_use]
+    pub fn with_archive_chain(mut self, chain: &[String]) -> Self {
+        self.archive_chain = (!chain.is_empty()).then(|| chain.join(ARCHIVE_CHAIN_SEPARATOR));
+        self
+    }
+
     #[must_use]
     pub fn name(&self) -> &str {
         self.name.as_ref()
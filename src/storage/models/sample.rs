@@ -0,0 +1,38 @@
+use diesel::{Associations, Insertable, Queryable};
+
+use super::Game;
+use crate::{logiqx, storage::schema::samples};
+
+#[derive(Queryable, Associations, PartialEq, Eq, Debug)]
+#[diesel(table_name = samples)]
+#[diesel(belongs_to(Game))]
+pub struct Sample {
+    pub id: i32,
+    pub name: String,
+    pub game_id: i32,
+}
+
+impl Sample {
+    /// Rebuild the Logiqx sample this row was imported from.
+    #[must_use]
+    pub fn to_logiqx(&self) -> logiqx::Sample {
+        logiqx::Sample::new(self.name.clone())
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = samples)]
+pub struct New {
+    pub name: String,
+    pub game_id: i32,
+}
+
+impl New {
+    #[must_use]
+    pub fn from_logiqx(sample: &logiqx::Sample, game_id: i32) -> Self {
+        Self {
+            name: sample.name().to_owned(),
+            game_id,
+        }
+    }
+}
@@ -1,36 +1,124 @@
-use diesel::prelude::*;
+use std::collections::BTreeMap;
+
+use camino::Utf8Path;
+use chrono::NaiveDateTime;
+use diesel::{
+    dsl::{AsExprOf, sql},
+    expression::{SqlLiteral, UncheckedBind},
+    prelude::*,
+    sql_query, sql_types,
+};
 
 use crate::{
-    domain::{DatRom, SourceFile, SourceKind},
+    domain::{
+        CacheStats, DatCompleteness, DatDirectives, DatGame, DatRom, DatSample, DatSummary,
+        ExpectedRom, ProvidedRom, RomLookup, RomQuery, ScannedRom, SourceFile, SourceKind,
+        SourceSummary,
+    },
     hashes::Sha1Digest,
+    logiqx,
     storage::{
-        db::Pool,
-        models::{DataFile, RomFile},
+        db::{self, Pool},
+        models::{ArchiveFile, DataFile, Game, NewArchiveFile, Release, Rom, RomFile, Sample},
         schema,
     },
 };
 
 #[cfg(test)]
-use crate::{
-    logiqx,
-    storage::{db, models::NewRomFile},
-};
+use crate::storage::models::NewRomFile;
 
-#[cfg(test)]
 pub struct DatRepository<'pool> {
     pool: &'pool Pool,
 }
 
-#[cfg(test)]
 impl<'pool> DatRepository<'pool> {
     #[must_use]
     pub const fn new(pool: &'pool Pool) -> Self {
         Self { pool }
     }
 
+    #[cfg(test)]
     pub fn import(&self, data_file: &logiqx::DataFile) -> crate::Result<i32> {
         db::traverse_and_insert_data_file(self.pool, data_file)
     }
+
+    /// Rebuild an imported DAT, with games and everything they list in
+    /// import order.
+    pub fn export(
+        &self,
+        selector: DataFileSelector<'_>,
+        version: Option<&str>,
+    ) -> crate::Result<logiqx::DataFile> {
+        let mut conn = self.pool.get()?;
+        let data_file = find_data_files(&mut conn, selector, version)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| not_cached(selector, version))?;
+        let games = Game::belonging_to(&data_file)
+            .order(schema::games::dsl::id)
+            .load::<Game>(&mut conn)?;
+        let mut roms =
+            schema::roms::dsl::roms
+                .filter(schema::roms::dsl::game_id.eq_any(
+                    Game::belonging_to(&data_file).select(schema::games::dsl::id.nullable()),
+                ))
+                .order(schema::roms::dsl::id)
+                .load::<Rom>(&mut conn)?
+                .into_iter()
+                .fold(BTreeMap::<i32, Vec<logiqx::Rom>>::new(), |mut roms, rom| {
+                    if let Some(game_id) = rom.game_id {
+                        roms.entry(game_id).or_default().push(rom.to_logiqx());
+                    }
+                    roms
+                });
+        let mut releases = schema::releases::dsl::releases
+            .filter(
+                schema::releases::dsl::game_id
+                    .eq_any(Game::belonging_to(&data_file).select(schema::games::dsl::id)),
+            )
+            .order(schema::releases::dsl::id)
+            .load::<Release>(&mut conn)?
+            .into_iter()
+            .fold(
+                BTreeMap::<i32, Vec<logiqx::Release>>::new(),
+                |mut releases, release| {
+                    releases
+                        .entry(release.game_id)
+                        .or_default()
+                        .push(release.to_logiqx());
+                    releases
+                },
+            );
+        let mut samples = schema::samples::dsl::samples
+            .filter(
+                schema::samples::dsl::game_id
+                    .eq_any(Game::belonging_to(&data_file).select(schema::games::dsl::id)),
+            )
+            .order(schema::samples::dsl::id)
+            .load::<Sample>(&mut conn)?
+            .into_iter()
+            .fold(
+                BTreeMap::<i32, Vec<logiqx::Sample>>::new(),
+                |mut samples, sample| {
+                    samples
+                        .entry(sample.game_id)
+                        .or_default()
+                        .push(sample.to_logiqx());
+                    samples
+                },
+            );
+
+        Ok(data_file.to_logiqx(
+            games
+                .iter()
+                .map(|game| {
+                    game.to_logiqx(roms.remove(&game.id).unwrap_or_default())
+                        .with_releases(releases.remove(&game.id).unwrap_or_default())
+                        .with_samples(samples.remove(&game.id).unwrap_or_default())
+                })
+                .collect(),
+        ))
+    }
 }
 
 pub struct SourceRepository<'pool> {
@@ -56,6 +144,50 @@ impl<'pool> SourceRepository<'pool> {
             .map(source_file_from_model)
             .collect()
     }
+
+    /// Every file and archive entry recorded by the last scan of
+    /// `source_root` or a directory below it.
+    pub fn load_scanned_roms(&self, source_root: &Utf8Path) -> crate::Result<Vec<ScannedRom>> {
+        use schema::rom_files::dsl;
+
+        // Paths below the root sort between `<root>/` and `<root>0`, the
+        // character after `/`.
+        let root = source_root.as_str().trim_end_matches('/');
+        let mut conn = self.pool.get()?;
+        dsl::rom_files
+            .filter(
+                dsl::parent_path
+                    .eq(source_root.as_str())
+                    .or(dsl::parent_path
+                        .ge(format!("{root}/"))
+                        .and(dsl::parent_path.lt(format!("{root}0")))),
+            )
+            .order((dsl::path, dsl::archive_chain, dsl::name))
+            .load::<RomFile>(&mut conn)?
+            .into_iter()
+            .map(scanned_rom_from_model)
+            .collect()
+    }
+}
+
+/// Output archives recorded by earlier builds.
+pub struct OutputRepository<'pool> {
+    pool: &'pool Pool,
+}
+
+impl<'pool> OutputRepository<'pool> {
+    #[must_use]
+    pub const fn new(pool: &'pool Pool) -> Self {
+        Self { pool }
+    }
+
+    pub fn load_outputs(&self, paths: &[String]) -> crate::Result<Vec<ArchiveFile>> {
+        db::archive_files_by_path(self.pool, paths)
+    }
+
+    pub fn record_outputs(&self, outputs: &[NewArchiveFile]) -> crate::Result<usize> {
+        db::record_archive_files(self.pool, outputs)
+    }
 }
 
 pub struct BuildRepository<'pool> {
@@ -83,16 +215,13 @@ impl<'pool> BuildRepository<'pool> {
         Self { pool }
     }
 
-    pub fn load_dat_roms(&self, selector: DataFileSelector<'_>) -> crate::Result<Vec<DatRom>> {
+    pub fn load_dat_roms(
+        &self,
+        selector: DataFileSelector<'_>,
+        version: Option<&str>,
+    ) -> crate::Result<Vec<DatRom>> {
         let mut conn = self.pool.get()?;
-        let data_file = match selector {
-            DataFileSelector::FileName(value) => schema::data_files::dsl::data_files
-                .filter(schema::data_files::dsl::file_name.eq(value))
-                .first::<DataFile>(&mut conn)?,
-            DataFileSelector::Name(value) => schema::data_files::dsl::data_files
-                .filter(schema::data_files::dsl::name.eq(value))
-                .first::<DataFile>(&mut conn)?,
-        };
+        let data_file = find_data_file(&mut conn, selector, version)?;
         let dat_name = selector.value().to_owned();
 
         let rows = schema::games::dsl::games
@@ -100,8 +229,12 @@ impl<'pool> BuildRepository<'pool> {
             .inner_join(schema::roms::dsl::roms)
             .load::<(crate::storage::models::Game, crate::storage::models::Rom)>(&mut conn)?;
 
+        // An undumped ROM without a SHA1 can't be matched to any source.
         rows.into_iter()
+            .filter(|(_, rom)| !(rom.is_nodump() && rom.sha1.is_empty()))
             .map(|(game, rom)| {
+                let nodump = rom.is_nodump();
+                let merged = rom.merge.as_deref().is_some_and(|merge| !merge.is_empty());
                 let sha1 = sha1_digest_from_db(rom.sha1, "roms.sha1", &rom.name)?;
                 Ok(DatRom {
                     dat_name: dat_name.clone(),
@@ -109,45 +242,638 @@ impl<'pool> BuildRepository<'pool> {
                     parent_name: game.clone_of,
                     rom_name: rom.name,
                     sha1,
+                    nodump,
+                    merged,
                 })
             })
             .collect()
     }
+
+    /// The selected DAT's games with the regions and languages of their
+    /// releases and the metadata game filters look at.
+    pub fn load_dat_games(
+        &self,
+        selector: DataFileSelector<'_>,
+        version: Option<&str>,
+    ) -> crate::Result<Vec<DatGame>> {
+        let mut conn = self.pool.get()?;
+        let data_file = find_data_file(&mut conn, selector, version)?;
+        let games = Game::belonging_to(&data_file)
+            .order(schema::games::dsl::id)
+            .load::<Game>(&mut conn)?;
+        let mut releases = schema::releases::dsl::releases
+            .filter(
+                schema::releases::dsl::game_id
+                    .eq_any(Game::belonging_to(&data_file).select(schema::games::dsl::id)),
+            )
+            .order(schema::releases::dsl::id)
+            .load::<Release>(&mut conn)?
+            .into_iter()
+            .fold(
+                BTreeMap::<i32, Vec<Release>>::new(),
+                |mut releases, release| {
+                    releases.entry(release.game_id).or_default().push(release);
+                    releases
+                },
+            );
+
+        Ok(games
+            .into_iter()
+            .map(|game| {
+                let releases = releases.remove(&game.id).unwrap_or_default();
+                DatGame {
+                    is_bios: is_yes(game.is_bios.as_deref()),
+                    is_device: is_yes(game.is_device.as_deref()),
+                    is_mechanical: is_yes(game.is_mechanical.as_deref()),
+                    year: game.year.filter(|year| !year.is_empty()),
+                    manufacturer: game
+                        .manufacturer
+                        .filter(|manufacturer| !manufacturer.is_empty()),
+                    game_name: game.name,
+                    parent_name: game.clone_of,
+                    regions: releases
+                        .iter()
+                        .map(|release| release.region.clone())
+                        .collect(),
+                    languages: releases
+                        .into_iter()
+                        .filter_map(|release| release.language)
+                        .filter(|language| !language.is_empty())
+                        .collect(),
+                }
+            })
+            .collect())
+    }
+
+    /// Every sample the selected DAT's games play, with the sample set each
+    /// is found in.
+    pub fn load_dat_samples(
+        &self,
+        selector: DataFileSelector<'_>,
+        version: Option<&str>,
+    ) -> crate::Result<Vec<DatSample>> {
+        let mut conn = self.pool.get()?;
+        let data_file = find_data_file(&mut conn, selector, version)?;
+
+        Ok(schema::games::dsl::games
+            .filter(schema::games::dsl::data_file_id.eq(data_file.id))
+            .inner_join(schema::samples::dsl::samples)
+            .load::<(Game, Sample)>(&mut conn)?
+            .into_iter()
+            .map(|(game, sample)| DatSample {
+                sample_set: game
+                    .sample_of
+                    .filter(|sample_of| !sample_of.is_empty())
+                    .unwrap_or_else(|| game.name.clone()),
+                game_name: game.name,
+                sample_name: sample.name,
+            })
+            .collect())
+    }
+
+    /// Whether the selected DAT was imported before games' device and
+    /// mechanical flags were recorded, so filters can't tell them apart.
+    pub fn lacks_device_flags(
+        &self,
+        selector: DataFileSelector<'_>,
+        version: Option<&str>,
+    ) -> crate::Result<bool> {
+        let mut conn = self.pool.get()?;
+        let data_file = find_data_file(&mut conn, selector, version)?;
+        Ok(diesel::select(diesel::dsl::exists(
+            Game::belonging_to(&data_file).filter(schema::games::dsl::is_device.is_null()),
+        ))
+        .get_result(&mut conn)?)
+    }
+
+    /// Whether the selected DAT's games name sample sets but it has no
+    /// samples, as when it was imported before samples were recorded.
+    pub fn lacks_samples(
+        &self,
+        selector: DataFileSelector<'_>,
+        version: Option<&str>,
+    ) -> crate::Result<bool> {
+        let mut conn = self.pool.get()?;
+        let data_file = find_data_file(&mut conn, selector, version)?;
+        let names_sample_sets = diesel::select(diesel::dsl::exists(
+            Game::belonging_to(&data_file).filter(schema::games::dsl::sample_of.ne("")),
+        ))
+        .get_result::<bool>(&mut conn)?;
+        let has_samples = diesel::select(diesel::dsl::exists(
+            schema::samples::dsl::samples.filter(
+                schema::samples::dsl::game_id
+                    .eq_any(Game::belonging_to(&data_file).select(schema::games::dsl::id)),
+            ),
+        ))
+        .get_result::<bool>(&mut conn)?;
+        Ok(names_sample_sets && !has_samples)
+    }
+
+    /// The clrmamepro directives of the selected DAT's header.
+    pub fn load_directives(
+        &self,
+        selector: DataFileSelector<'_>,
+        version: Option<&str>,
+    ) -> crate::Result<DatDirectives> {
+        let mut conn = self.pool.get()?;
+        Ok(find_data_file(&mut conn, selector, version)?.directives())
+    }
+
+    /// The list's name, when the selected DAT was imported from a MAME
+    /// software list.
+    pub fn load_software_list_name(
+        &self,
+        selector: DataFileSelector<'_>,
+        version: Option<&str>,
+    ) -> crate::Result<Option<String>> {
+        let mut conn = self.pool.get()?;
+        Ok(find_data_file(&mut conn, selector, version)?
+            .software_list_name()
+            .map(str::to_owned))
+    }
+}
+
+/// The latest import of the selected DAT, or with `version`, the latest
+/// import whose version or hex SHA1 matches.
+fn find_data_file(
+    conn: &mut SqliteConnection,
+    selector: DataFileSelector<'_>,
+    version: Option<&str>,
+) -> QueryResult<DataFile> {
+    find_data_files(conn, selector, version)?
+        .into_iter()
+        .next()
+        .ok_or(diesel::result::Error::NotFound)
+}
+
+/// Every import of the selected DAT, newest first.
+fn find_data_files(
+    conn: &mut SqliteConnection,
+    selector: DataFileSelector<'_>,
+    version: Option<&str>,
+) -> QueryResult<Vec<DataFile>> {
+    use schema::data_files::dsl;
+
+    let query = match selector {
+        DataFileSelector::FileName(value) => dsl::data_files
+            .filter(dsl::file_name.eq(value))
+            .into_boxed(),
+        DataFileSelector::Name(value) => dsl::data_files.filter(dsl::name.eq(value)).into_boxed(),
+    };
+    let query = match version {
+        Some(version) => query.filter(
+            dsl::version
+                .eq(version)
+                .or(dsl::sha1.eq(hex::decode(version).unwrap_or_default())),
+        ),
+        None => query,
+    };
+    query.order(dsl::id.desc()).load::<DataFile>(conn)
+}
+
+fn not_cached(selector: DataFileSelector<'_>, version: Option<&str>) -> crate::Error {
+    crate::Error::NotInCache(version.map_or_else(
+        || format!("DAT {}", selector.value()),
+        |version| format!("DAT {} version {version}", selector.value()),
+    ))
+}
+
+/// Removes DATs, source roots, and stale rows from the cache.
+pub struct MaintenanceRepository<'pool> {
+    pool: &'pool Pool,
+}
+
+impl<'pool> MaintenanceRepository<'pool> {
+    #[must_use]
+    pub const fn new(pool: &'pool Pool) -> Self {
+        Self { pool }
+    }
+
+    /// Removes every version of the DAT unless `version` picks one. Returns
+    /// the number of games and ROMs removed.
+    pub fn remove_dat(
+        &self,
+        selector: DataFileSelector<'_>,
+        version: Option<&str>,
+    ) -> crate::Result<(usize, usize)> {
+        // The connection goes back to the pool before the removal takes one.
+        let data_file_ids = {
+            let mut conn = self.pool.get()?;
+            find_data_files(&mut conn, selector, version)?
+                .iter()
+                .map(|data_file| data_file.id)
+                .collect::<Vec<_>>()
+        };
+        if data_file_ids.is_empty() {
+            return Err(not_cached(selector, version));
+        }
+        db::remove_data_files(self.pool, &data_file_ids)
+    }
+
+    pub fn remove_source(&self, source_root: &Utf8Path) -> crate::Result<db::RemovedRows> {
+        let removed = db::remove_source_root(self.pool, source_root)?;
+        if removed == db::RemovedRows::default() {
+            return Err(crate::Error::NotInCache(format!("source {source_root}")));
+        }
+        Ok(removed)
+    }
+
+    pub fn collect_garbage(&self) -> crate::Result<db::RemovedRows> {
+        db::collect_garbage(self.pool)
+    }
+}
+
+/// Read-only summaries of what the cache holds.
+pub struct InventoryRepository<'pool> {
+    pool: &'pool Pool,
+}
+
+impl<'pool> InventoryRepository<'pool> {
+    #[must_use]
+    pub const fn new(pool: &'pool Pool) -> Self {
+        Self { pool }
+    }
+
+    pub fn list_dats(&self) -> crate::Result<Vec<DatSummary>> {
+        let mut conn = self.pool.get()?;
+        Ok(sql_query(
+            r"
+            SELECT data_files.name AS name,
+                data_files.version AS version,
+                data_files.sha1 AS sha1,
+                data_files.id = (
+                    SELECT MAX(newer.id) FROM data_files AS newer
+                    WHERE newer.name = data_files.name
+                ) AS latest,
+                data_files.file_name AS file_name,
+                data_files.imported_at AS imported_at,
+                data_files.software_list AS software_list,
+                COUNT(DISTINCT games.id) AS games,
+                COUNT(roms.id) AS roms
+            FROM data_files
+            LEFT JOIN games ON games.data_file_id = data_files.id
+            LEFT JOIN roms ON roms.game_id = games.id
+            GROUP BY data_files.id
+            ORDER BY data_files.name, data_files.id DESC
+            ",
+        )
+        .load::<DatSummaryRow>(&mut conn)?
+        .into_iter()
+        .map(|row| DatSummary {
+            name: row.name,
+            version: row.version,
+            sha1: row.sha1.map(hex::encode),
+            latest: row.latest,
+            software_list: row.software_list,
+            file_name: row.file_name,
+            games: count(row.games),
+            roms: count(row.roms),
+            imported_at: row.imported_at,
+        })
+        .collect())
+    }
+
+    pub fn list_sources(&self) -> crate::Result<Vec<SourceSummary>> {
+        let mut conn = self.pool.get()?;
+        Ok(sql_query(
+            r"
+            SELECT source_roots.path AS path,
+                source_roots.scanned_at AS scanned_at,
+                (
+                    SELECT COUNT(*) FROM rom_files
+                    WHERE rom_files.parent_path = source_roots.path
+                        OR substr(rom_files.parent_path, 1, length(rtrim(source_roots.path, '/')) + 1)
+                            = rtrim(source_roots.path, '/') || '/'
+                ) AS rom_files,
+                (
+                    SELECT COUNT(*) FROM archive_files
+                    WHERE substr(archive_files.path, 1, length(rtrim(source_roots.path, '/')) + 1)
+                            = rtrim(source_roots.path, '/') || '/'
+                        AND EXISTS (
+                            SELECT 1 FROM rom_files
+                            WHERE rom_files.archive_file_id = archive_files.id
+                        )
+                ) AS archives
+            FROM source_roots
+            ORDER BY source_roots.path
+            ",
+        )
+        .load::<SourceSummaryRow>(&mut conn)?
+        .into_iter()
+        .map(|row| SourceSummary {
+            path: row.path,
+            rom_files: count(row.rom_files),
+            archives: count(row.archives),
+            scanned_at: row.scanned_at,
+        })
+        .collect())
+    }
+
+    /// ROM files count as matched once linked to a DAT ROM; a DAT ROM counts
+    /// as available when any scanned file has its SHA1.
+    pub fn stats(&self) -> crate::Result<CacheStats> {
+        use schema::rom_files::dsl as rom_files_dsl;
+
+        let mut conn = self.pool.get()?;
+        let rom_files = rom_files_dsl::rom_files
+            .count()
+            .get_result::<i64>(&mut conn)?;
+        let matched_rom_files = rom_files_dsl::rom_files
+            .filter(rom_files_dsl::rom_id.is_not_null())
+            .count()
+            .get_result::<i64>(&mut conn)?;
+        let dats = sql_query(
+            r"
+            SELECT data_files.name AS name,
+                COUNT(roms.id) AS roms,
+                COUNT(
+                    CASE WHEN EXISTS (
+                        SELECT 1 FROM rom_files WHERE rom_files.sha1 = roms.sha1
+                    ) THEN 1 END
+                ) AS available_roms
+            FROM data_files
+            LEFT JOIN games ON games.data_file_id = data_files.id
+            LEFT JOIN roms ON roms.game_id = games.id
+            WHERE data_files.id IN (SELECT MAX(id) FROM data_files GROUP BY name)
+            GROUP BY data_files.id
+            ORDER BY data_files.name
+            ",
+        )
+        .load::<DatCompletenessRow>(&mut conn)?
+        .into_iter()
+        .map(|row| DatCompleteness {
+            name: row.name,
+            roms: count(row.roms),
+            available_roms: count(row.available_roms),
+        })
+        .collect();
+
+        Ok(CacheStats {
+            rom_files: count(rom_files),
+            matched_rom_files: count(matched_rom_files),
+            unmatched_rom_files: count(rom_files - matched_rom_files),
+            dats,
+        })
+    }
+
+    /// Only the latest version of each DAT is searched. CRC32 and MD5
+    /// queries also find source files by the SHA1 of every DAT ROM they
+    /// match.
+    pub fn find_roms(&self, query: &RomQuery) -> crate::Result<RomLookup> {
+        use schema::{
+            data_files::dsl as data_files_dsl, games::dsl as games_dsl,
+            rom_files::dsl as rom_files_dsl, roms::dsl as roms_dsl,
+        };
+
+        let mut conn = self.pool.get()?;
+        let expected_roms = roms_dsl::roms
+            .inner_join(games_dsl::games.inner_join(data_files_dsl::data_files))
+            .select((
+                data_files_dsl::name,
+                games_dsl::name,
+                roms_dsl::name,
+                roms_dsl::sha1,
+            ))
+            .filter(sql::<sql_types::Bool>(
+                "data_files.id IN (SELECT MAX(id) FROM data_files GROUP BY name)",
+            ))
+            .into_boxed();
+        let expected_roms = match query {
+            RomQuery::Sha1(sha1) => expected_roms.filter(roms_dsl::sha1.eq(sha1.as_slice())),
+            RomQuery::Crc32(crc) => expected_roms.filter(roms_dsl::crc.eq(crc.as_slice())),
+            RomQuery::Md5(md5) => expected_roms.filter(roms_dsl::md5.eq(md5.as_slice())),
+            // Undumped ROMs without a SHA1 have nothing to look up.
+            RomQuery::Name(pattern) => expected_roms
+                .filter(name_glob("roms.name", pattern))
+                .filter(roms_dsl::sha1.ne(Vec::<u8>::new())),
+        }
+        .order((data_files_dsl::name, games_dsl::name, roms_dsl::name))
+        .load::<(String, String, String, Vec<u8>)>(&mut conn)?
+        .into_iter()
+        .map(|(dat_name, game_name, rom_name, sha1)| {
+            let sha1 = sha1_digest_from_db(sha1, "roms.sha1", &rom_name)?;
+            Ok(ExpectedRom {
+                dat_name,
+                game_name,
+                rom_name,
+                sha1,
+            })
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+
+        let expected_sha1s = expected_roms
+            .iter()
+            .map(|rom| rom.sha1.to_vec())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let source_files = rom_files_dsl::rom_files.into_boxed();
+        let source_files = match query {
+            RomQuery::Sha1(sha1) => source_files.filter(rom_files_dsl::sha1.eq(sha1.as_slice())),
+            RomQuery::Crc32(crc) => source_files.filter(
+                rom_files_dsl::crc
+                    .eq(crc.as_slice())
+                    .or(rom_files_dsl::sha1.eq_any(&expected_sha1s).nullable()),
+            ),
+            RomQuery::Md5(md5) => source_files.filter(
+                rom_files_dsl::md5
+                    .eq(md5.as_slice())
+                    .or(rom_files_dsl::sha1.eq_any(&expected_sha1s).nullable()),
+            ),
+            RomQuery::Name(pattern) => source_files.filter(name_glob("rom_files.name", pattern)),
+        };
+        let sources = source_files
+            .order((rom_files_dsl::path, rom_files_dsl::name))
+            .load::<RomFile>(&mut conn)?
+            .into_iter()
+            .map(|rom_file| {
+                source_file_from_model(rom_file).map(|source| ProvidedRom {
+                    location: source.display_name(),
+                    sha1: source.sha1,
+                })
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        Ok(RomLookup {
+            expected: expected_roms,
+            sources,
+        })
+    }
+}
+
+/// `SQLite`'s `GLOB` shares the `*`, `?` and `[...]` syntax of
+/// [`glob::Pattern`], which has already validated the pattern, except that
+/// it negates a class with `[^...]` where `glob` uses `[!...]`.
+fn name_glob(
+    column: &str,
+    pattern: &glob::Pattern,
+) -> UncheckedBind<SqlLiteral<sql_types::Bool>, AsExprOf<String, sql_types::Text>> {
+    sql::<sql_types::Bool>(&format!("{column} GLOB "))
+        .bind::<sql_types::Text, _>(sqlite_glob(pattern.as_str()))
+}
+
+fn sqlite_glob(pattern: &str) -> String {
+    let mut translated = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        translated.push(c);
+        if c != '[' {
+            continue;
+        }
+        if chars.next_if_eq(&'!').is_some() {
+            translated.push('^');
+        }
+        // A `]` right after the opening bracket is a member, not the end.
+        if let Some(first) = chars.next_if_eq(&']') {
+            translated.push(first);
+        }
+        for c in chars.by_ref() {
+            translated.push(c);
+            if c == ']' {
+                break;
+            }
+        }
+    }
+    translated
+}
+
+#[derive(QueryableByName)]
+struct DatSummaryRow {
+    #[diesel(sql_type = sql_types::Text)]
+    name: String,
+    #[diesel(sql_type = sql_types::Nullable<sql_types::Text>)]
+    version: Option<String>,
+    #[diesel(sql_type = sql_types::Nullable<sql_types::Binary>)]
+    sha1: Option<Vec<u8>>,
+    #[diesel(sql_type = sql_types::Bool)]
+    latest: bool,
+    #[diesel(sql_type = sql_types::Nullable<sql_types::Text>)]
+    file_name: Option<String>,
+    #[diesel(sql_type = sql_types::Nullable<sql_types::Timestamp>)]
+    imported_at: Option<NaiveDateTime>,
+    #[diesel(sql_type = sql_types::Bool)]
+    software_list: bool,
+    #[diesel(sql_type = sql_types::BigInt)]
+    games: i64,
+    #[diesel(sql_type = sql_types::BigInt)]
+    roms: i64,
+}
+
+#[derive(QueryableByName)]
+struct SourceSummaryRow {
+    #[diesel(sql_type = sql_types::Text)]
+    path: String,
+    #[diesel(sql_type = sql_types::Timestamp)]
+    scanned_at: NaiveDateTime,
+    #[diesel(sql_type = sql_types::BigInt)]
+    rom_files: i64,
+    #[diesel(sql_type = sql_types::BigInt)]
+    archives: i64,
+}
+
+#[derive(QueryableByName)]
+struct DatCompletenessRow {
+    #[diesel(sql_type = sql_types::Text)]
+    name: String,
+    #[diesel(sql_type = sql_types::BigInt)]
+    roms: i64,
+    #[diesel(sql_type = sql_types::BigInt)]
+    available_roms: i64,
+}
+
+/// DAT flag attributes such as `isbios` are `yes` when set.
+fn is_yes(flag: Option<&str>) -> bool {
+    flag == Some("yes")
+}
+
+fn count(value: i64) -> usize {
+    usize::try_from(value).unwrap_or_default()
 }
 
 fn source_file_from_model(rom_file: RomFile) -> crate::Result<SourceFile> {
     let kind = source_kind_from_rom_file(&rom_file);
+    let archive_chain = rom_file.archive_chain();
     let sha1 = sha1_digest_from_db(rom_file.sha1, "rom_files.sha1", &rom_file.name)?;
 
     Ok(SourceFile {
         source_root: rom_file.parent_path,
         canonical_path: rom_file.path,
         entry_name: rom_file.in_archive.then_some(rom_file.name),
+        archive_chain,
         sha1,
         kind,
     })
 }
 
+fn scanned_rom_from_model(mut rom_file: RomFile) -> crate::Result<ScannedRom> {
+    let label = format!("{}:{}", rom_file.path, rom_file.name);
+    let (Some(crc), Some(md5), Some(size)) =
+        (rom_file.crc.take(), rom_file.md5.take(), rom_file.size)
+    else {
+        return Err(crate::Error::NotInCache(format!(
+            "{label} was scanned without its CRC32, MD5 and size; scan the source again"
+        )));
+    };
+    let crc32 = digest_from_db(crc, "rom_files.crc", &label)?;
+    let md5 = digest_from_db(md5, "rom_files.md5", &label)?;
+    let size = u64::try_from(size).map_err(|_| {
+        crate::Error::InvalidHash(format!("rom_files.size for {label} is negative: {size}"))
+    })?;
+
+    Ok(ScannedRom {
+        source: source_file_from_model(rom_file)?,
+        size,
+        crc32,
+        md5,
+    })
+}
+
 fn source_kind_from_rom_file(rom_file: &RomFile) -> SourceKind {
     if !rom_file.in_archive {
         return SourceKind::BareFile;
     }
 
-    if std::path::Path::new(&rom_file.path)
-        .extension()
-        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
-    {
+    if rom_file.archive_chain.is_some() {
+        return SourceKind::NestedEntry;
+    }
+
+    let path = std::path::Path::new(&rom_file.path);
+    let is_tar_gz = has_extension(path, "tgz")
+        || (has_extension(path, "gz")
+            && path
+                .file_stem()
+                .is_some_and(|stem| has_extension(std::path::Path::new(stem), "tar")));
+    if has_extension(path, "zip") {
         SourceKind::ZipEntry
+    } else if is_tar_gz {
+        SourceKind::TarGzEntry
+    } else if has_extension(path, "tar") {
+        SourceKind::TarEntry
+    } else if has_extension(path, "gz") {
+        SourceKind::GzipEntry
     } else {
         SourceKind::ArchiveEntry
     }
 }
 
+fn has_extension(path: &std::path::Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
 fn sha1_digest_from_db(bytes: Vec<u8>, column: &str, label: &str) -> crate::Result<Sha1Digest> {
+    digest_from_db(bytes, column, label)
+}
+
+fn digest_from_db<const N: usize>(
+    bytes: Vec<u8>,
+    column: &str,
+    label: &str,
+) -> crate::Result<[u8; N]> {
     let len = bytes.len();
     bytes.try_into().map_err(|_| {
         crate::Error::InvalidHash(format!(
-            "{column} for {label} has length {len}; expected 20 bytes"
+            "{column} for {label} has length {len}; expected {N} bytes"
         ))
     })
 }
@@ -182,14 +908,19 @@ mod tests {
             name: "repo.rom".to_owned(),
             sha1: crate::hashes::sha1_bytes(b"abc"),
             xxhash3: crate::hashes::xxhash3_bytes(b"abc"),
+            crc: crate::hashes::rom_hashes(b"abc").crc32,
+            md5: crate::hashes::rom_hashes(b"abc").md5,
+            size: 3,
             in_archive: false,
             rom_id: None,
+            archive_chain: None,
+            archive_file_id: None,
         };
         let associated = SourceRepository::new(&pool).import_rom_files(&[rom_file])?;
         assert_eq!(associated, 1);
 
-        let dat_roms =
-            BuildRepository::new(&pool).load_dat_roms(DataFileSelector::Name("Repository Test"))?;
+        let dat_roms = BuildRepository::new(&pool)
+            .load_dat_roms(DataFileSelector::Name("Repository Test"), None)?;
         let source_files = SourceRepository::new(&pool).load_source_files()?;
 
         assert_eq!(dat_roms.len(), 1);
@@ -199,6 +930,238 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn export_rebuilds_the_imported_dat() -> Result<(), Box<dyn std::error::Error>> {
+        let (_temp_dir, pool) = file_backed_pool()?;
+        let dat = r#"<?xml version="1.0"?>
+<datafile build="0.250">
+  <header>
+    <name>Export Test</name>
+    <description>Export &amp; reimport</description>
+    <version>0.250</version>
+    <homepage>example.com</homepage>
+  </header>
+  <game name="parent" sourcefile="parent.cpp">
+    <description>Parent Game</description>
+    <year>1980</year>
+    <manufacturer>Acme</manufacturer>
+    <rom name="shared.rom" size="3" sha1="a9993e364706816aba3e25717850c26c9cd0d89d" md5="900150983cd24fb0d6963f7d28e17f72" crc="352441c2"/>
+  </game>
+  <game name="clone" cloneof="parent" romof="parent">
+    <description>Clone Game</description>
+    <rom name="shared.rom" merge="shared.rom" size="3" sha1="a9993e364706816aba3e25717850c26c9cd0d89d" md5="900150983cd24fb0d6963f7d28e17f72" crc="352441c2"/>
+    <rom name="bad.rom" status="baddump" size="1" sha1="84983e441c3bd26ebaae4aa1f575527d004816f2" md5="f96b697d7cb7938d525a2f31aaf161d0" crc="bbccddee"/>
+  </game>
+</datafile>"#;
+        DatRepository::new(&pool).import(&logiqx::DataFile::from_reader(dat.as_bytes())?)?;
+
+        let mut xml = Vec::new();
+        DatRepository::new(&pool)
+            .export(DataFileSelector::Name("Export Test"), None)?
+            .write_to(&mut xml)?;
+        let exported = logiqx::DataFile::from_reader(xml.as_slice())?;
+
+        assert_eq!(exported.build(), Some("0.250"));
+        assert_eq!(exported.header().name(), "Export Test");
+        assert_eq!(
+            exported.header().description().map(String::as_str),
+            Some("Export & reimport")
+        );
+        assert_eq!(
+            exported.header().homepage().map(String::as_str),
+            Some("example.com")
+        );
+        let [parent, clone] = exported.games() else {
+            return Err("expected two games".into());
+        };
+        assert_eq!(parent.name(), "parent");
+        assert_eq!(parent.description(), "Parent Game");
+        assert_eq!(parent.sourcefile(), "parent.cpp");
+        assert_eq!(parent.year(), "1980");
+        assert_eq!(parent.manufacturer(), "Acme");
+        assert_eq!(clone.cloneof(), Some("parent"));
+        assert_eq!(clone.romof(), "parent");
+        let [shared, bad] = clone.roms() else {
+            return Err("expected two clone ROMs".into());
+        };
+        assert_eq!(shared.merge(), "shared.rom");
+        assert_eq!(hex::encode(shared.crc()), "352441c2");
+        assert_eq!(bad.name(), "bad.rom");
+        assert_eq!(bad.status(), "baddump");
+        Ok(())
+    }
+
+    #[test]
+    fn undumped_roms_without_hashes_are_not_loaded() -> Result<(), Box<dyn std::error::Error>> {
+        let (_temp_dir, pool) = file_backed_pool()?;
+        let dat = SIMPLE_DAT
+            .replace(
+                "</header>",
+                r#"<clrmamepro forcenodump="obsolete"/></header>"#,
+            )
+            .replace(
+                "</game>",
+                r#"<rom name="undumped.rom" size="16" status="nodump"/></game>"#,
+            );
+        DatRepository::new(&pool).import(&logiqx::DataFile::from_reader(dat.as_bytes())?)?;
+
+        let repository = BuildRepository::new(&pool);
+        let dat_roms = repository.load_dat_roms(DataFileSelector::Name("Repository Test"), None)?;
+        assert_eq!(
+            dat_roms
+                .iter()
+                .map(|rom| (rom.rom_name.as_str(), rom.nodump))
+                .collect::<Vec<_>>(),
+            vec![("repo.rom", false)]
+        );
+        assert_eq!(
+            repository
+                .load_directives(DataFileSelector::Name("Repository Test"), None)?
+                .nodump_policy(),
+            Some(crate::domain::NodumpPolicy::Obsolete)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dats_imported_before_flags_and_samples_were_recorded_are_detected()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let (_temp_dir, pool) = file_backed_pool()?;
+        let dat = SIMPLE_DAT.replace(
+            r#"<game name="repo-game">"#,
+            r#"<game name="repo-game" sampleof="repo-game"><sample name="bang"/>"#,
+        );
+        DatRepository::new(&pool).import(&logiqx::DataFile::from_reader(dat.as_bytes())?)?;
+        let repository = BuildRepository::new(&pool);
+        let selector = || DataFileSelector::Name("Repository Test");
+        assert!(!repository.lacks_device_flags(selector(), None)?);
+        assert!(!repository.lacks_samples(selector(), None)?);
+
+        let mut conn = pool.get()?;
+        sql_query("UPDATE games SET is_device = NULL, is_mechanical = NULL").execute(&mut conn)?;
+        sql_query("DELETE FROM samples").execute(&mut conn)?;
+
+        assert!(repository.lacks_device_flags(selector(), None)?);
+        assert!(repository.lacks_samples(selector(), None)?);
+        Ok(())
+    }
+
+    #[test]
+    fn reimporting_a_new_version_keeps_the_old_one() -> Result<(), Box<dyn std::error::Error>> {
+        let (_temp_dir, pool) = file_backed_pool()?;
+        let versioned = |version: &str, rom_name: &str| {
+            SIMPLE_DAT
+                .replace(
+                    "<name>Repository Test</name>",
+                    &format!("<name>Repository Test</name><version>{version}</version>"),
+                )
+                .replace("repo.rom", rom_name)
+        };
+        let repository = DatRepository::new(&pool);
+        repository.import(&logiqx::DataFile::from_reader(
+            versioned("1", "old.rom").as_bytes(),
+        )?)?;
+        repository.import(&logiqx::DataFile::from_reader(
+            versioned("2", "new.rom").as_bytes(),
+        )?)?;
+        repository.import(&logiqx::DataFile::from_reader(
+            versioned("2", "new.rom").as_bytes(),
+        )?)?;
+
+        let dats = InventoryRepository::new(&pool).list_dats()?;
+        assert_eq!(
+            dats.iter()
+                .map(|dat| (dat.version.as_deref(), dat.latest, dat.roms))
+                .collect::<Vec<_>>(),
+            vec![(Some("2"), true, 1), (Some("1"), false, 1)]
+        );
+        let rom_names = |version| -> crate::Result<Vec<String>> {
+            Ok(BuildRepository::new(&pool)
+                .load_dat_roms(DataFileSelector::Name("Repository Test"), version)?
+                .into_iter()
+                .map(|rom| rom.rom_name)
+                .collect())
+        };
+        assert_eq!(rom_names(None)?, vec!["new.rom"]);
+        assert_eq!(rom_names(Some("1"))?, vec!["old.rom"]);
+
+        MaintenanceRepository::new(&pool)
+            .remove_dat(DataFileSelector::Name("Repository Test"), Some("2"))?;
+        assert_eq!(rom_names(None)?, vec!["old.rom"]);
+        assert!(matches!(
+            MaintenanceRepository::new(&pool)
+                .remove_dat(DataFileSelector::Name("Repository Test"), Some("2")),
+            Err(crate::Error::NotInCache(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn export_rejects_a_dat_that_is_not_cached() -> Result<(), Box<dyn std::error::Error>> {
+        let (_temp_dir, pool) = file_backed_pool()?;
+
+        let result = DatRepository::new(&pool).export(DataFileSelector::Name("Missing"), None);
+
+        assert!(matches!(result, Err(crate::Error::NotInCache(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn find_roms_matches_dat_roms_and_sources_by_hash_or_name()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let (_temp_dir, pool) = file_backed_pool()?;
+        let data_file = logiqx::DataFile::from_reader(SIMPLE_DAT.as_bytes())?;
+        DatRepository::new(&pool).import(&data_file)?;
+        let rom_file = NewRomFile {
+            parent_path: "/source".to_owned(),
+            path: "/source/renamed.bin".to_owned(),
+            name: "renamed.bin".to_owned(),
+            sha1: crate::hashes::sha1_bytes(b"abc"),
+            xxhash3: crate::hashes::xxhash3_bytes(b"abc"),
+            crc: crate::hashes::rom_hashes(b"abc").crc32,
+            md5: crate::hashes::rom_hashes(b"abc").md5,
+            size: 3,
+            in_archive: false,
+            rom_id: None,
+            archive_chain: None,
+            archive_file_id: None,
+        };
+        SourceRepository::new(&pool).import_rom_files(&[rom_file])?;
+        let inventory = InventoryRepository::new(&pool);
+
+        for query in [
+            RomQuery::sha1("a9993e364706816aba3e25717850c26c9cd0d89d")?,
+            RomQuery::crc32("12345678")?,
+            RomQuery::md5("900150983cd24fb0d6963f7d28e17f72")?,
+        ] {
+            let lookup = inventory.find_roms(&query)?;
+            assert_eq!(lookup.expected.len(), 1);
+            assert_eq!(lookup.expected[0].game_name, "repo-game");
+            assert_eq!(lookup.sources.len(), 1);
+            assert_eq!(lookup.sources[0].location, "/source/renamed.bin");
+        }
+
+        let by_rom_name = inventory.find_roms(&RomQuery::name("repo.*")?)?;
+        assert_eq!(by_rom_name.expected.len(), 1);
+        assert!(by_rom_name.sources.is_empty());
+        let by_source_name = inventory.find_roms(&RomQuery::name("*.bin")?)?;
+        assert!(by_source_name.expected.is_empty());
+        assert_eq!(by_source_name.sources.len(), 1);
+        let negated = inventory.find_roms(&RomQuery::name("[!r]*")?)?;
+        assert!(negated.expected.is_empty());
+        assert!(negated.sources.is_empty());
+        assert_eq!(
+            inventory
+                .find_roms(&RomQuery::name("[!x]*.bin")?)?
+                .sources
+                .len(),
+            1
+        );
+        assert_eq!(sqlite_glob("[!]a]*[!b]"), "[^]a]*[^b]");
+        assert!(RomQuery::crc32("123").is_err());
+        Ok(())
+    }
+
     #[test]
     fn source_kind_derives_archive_variants_from_rom_file() {
         let mut rom_file = RomFile {
@@ -213,6 +1176,9 @@ mod tests {
             xxhash3: crate::hashes::xxhash3_bytes(b"abc").to_vec(),
             in_archive: true,
             rom_id: None,
+            archive_chain: None,
+            archive_file_id: None,
+            size: None,
         };
 
         assert_eq!(source_kind_from_rom_file(&rom_file), SourceKind::ZipEntry);
@@ -221,6 +1187,20 @@ mod tests {
             source_kind_from_rom_file(&rom_file),
             SourceKind::ArchiveEntry
         );
+        rom_file.path = "/source/archive.ROM.GZ".to_owned();
+        assert_eq!(source_kind_from_rom_file(&rom_file), SourceKind::GzipEntry);
+        rom_file.path = "/source/archive.tar".to_owned();
+        assert_eq!(source_kind_from_rom_file(&rom_file), SourceKind::TarEntry);
+        rom_file.path = "/source/archive.tar.gz".to_owned();
+        assert_eq!(source_kind_from_rom_file(&rom_file), SourceKind::TarGzEntry);
+        rom_file.path = "/source/archive.tgz".to_owned();
+        assert_eq!(source_kind_from_rom_file(&rom_file), SourceKind::TarGzEntry);
+        rom_file.archive_chain = Some("inner.zip".to_owned());
+        assert_eq!(
+            source_kind_from_rom_file(&rom_file),
+            SourceKind::NestedEntry
+        );
+        rom_file.archive_chain = None;
         rom_file.in_archive = false;
         assert_eq!(source_kind_from_rom_file(&rom_file), SourceKind::BareFile);
     }
@@ -306,7 +1286,7 @@ mod tests {
         .execute(&mut conn)?;
 
         let repository = BuildRepository::new(&pool);
-        let Err(error) = repository.load_dat_roms(DataFileSelector::Name("Bad DAT")) else {
+        let Err(error) = repository.load_dat_roms(DataFileSelector::Name("Bad DAT"), None) else {
             return Err("expected invalid SHA1 length to fail".into());
         };
 
@@ -3,6 +3,9 @@ diesel::table! {
         id -> Integer,
         path -> Text,
         sha1 -> Binary,
+        size -> BigInt,
+        mtime -> BigInt,
+        nested_depth -> Integer,
     }
 }
 
@@ -21,6 +24,12 @@ diesel::table! {
         homepage -> Nullable<Text>,
         url -> Nullable<Text>,
         sha1 -> Nullable<Binary>,
+        imported_at -> Nullable<Timestamp>,
+        force_merging -> Nullable<Text>,
+        force_nodump -> Nullable<Text>,
+        force_packing -> Nullable<Text>,
+        header_skipper -> Nullable<Text>,
+        software_list -> Bool,
     }
 }
 
@@ -38,6 +47,22 @@ diesel::table! {
         manufacturer -> Nullable<Text>,
         data_file_id -> Nullable<Integer>,
         parent_id -> Nullable<Integer>,
+        description -> Nullable<Text>,
+        sourcefile -> Nullable<Text>,
+        is_device -> Nullable<Text>,
+        is_mechanical -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    releases (id) {
+        id -> Integer,
+        name -> Text,
+        region -> Text,
+        language -> Nullable<Text>,
+        date -> Nullable<Text>,
+        is_default -> Nullable<Text>,
+        game_id -> Integer,
     }
 }
 
@@ -54,6 +79,9 @@ diesel::table! {
         xxhash3 -> Binary,
         in_archive -> Bool,
         rom_id -> Nullable<Integer>,
+        archive_chain -> Nullable<Text>,
+        archive_file_id -> Nullable<Integer>,
+        size -> Nullable<BigInt>,
     }
 }
 
@@ -70,12 +98,42 @@ diesel::table! {
         inserted_at -> Nullable<Timestamp>,
         game_id -> Nullable<Integer>,
         archive_file_id -> Nullable<Integer>,
+        merge -> Nullable<Text>,
+        status -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    samples (id) {
+        id -> Integer,
+        name -> Text,
+        game_id -> Integer,
+    }
+}
+
+diesel::table! {
+    source_roots (id) {
+        id -> Integer,
+        path -> Text,
+        scanned_at -> Timestamp,
     }
 }
 
 diesel::joinable!(games -> data_files (data_file_id));
+diesel::joinable!(releases -> games (game_id));
+diesel::joinable!(rom_files -> archive_files (archive_file_id));
 diesel::joinable!(rom_files -> roms (rom_id));
 diesel::joinable!(roms -> archive_files (archive_file_id));
 diesel::joinable!(roms -> games (game_id));
+diesel::joinable!(samples -> games (game_id));
 
-diesel::allow_tables_to_appear_in_same_query!(archive_files, data_files, games, rom_files, roms,);
+diesel::allow_tables_to_appear_in_same_query!(
+    archive_files,
+    data_files,
+    games,
+    releases,
+    rom_files,
+    roms,
+    samples,
+    source_roots,
+);
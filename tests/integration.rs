@@ -1,15 +1,18 @@
 use assert_cmd::Command;
 use mame_coalesce::{
-    app::{self, BuildWorkflowRequest, DatImportRequest, RunWorkflowRequest, SourceScanRequest},
+    app::{
+        self, BuildAllRequest, BuildWorkflowRequest, DatImportRequest, DatRemovalRequest,
+        RunWorkflowRequest, SourceRemovalRequest, SourceScanRequest,
+    },
     database::Database,
-    domain::{BuildMode, ZipCompression},
+    domain::{BuildMode, MissingSample, OutputFormat},
     logiqx::DataFile,
 };
 use predicates::str::contains;
 use std::{
     collections::BTreeMap,
     fs,
-    io::{self, Read},
+    io::{self, Read, Write},
     process::Command as ProcessCommand,
 };
 
@@ -234,11 +237,11 @@ fn run_workflow_writes_from_7z_archive() -> Result<(), Box<dyn std::error::Error
             dat_path,
             source_path,
             destination_path: output_path.clone(),
-            mode: BuildMode::ParentBundles,
+            mode: Some(BuildMode::ParentBundles),
             jobs: 1,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
+            output: Some(OutputFormat::Zip),
             strict: true,
+            ..Default::default()
         },
     )?;
 
@@ -278,11 +281,11 @@ fn run_workflow_writes_from_rar_archive() -> Result<(), Box<dyn std::error::Erro
             dat_path,
             source_path,
             destination_path: output_path.clone(),
-            mode: BuildMode::ParentBundles,
+            mode: Some(BuildMode::ParentBundles),
             jobs: 1,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
+            output: Some(OutputFormat::Zip),
             strict: true,
+            ..Default::default()
         },
     )?;
 
@@ -299,6 +302,156 @@ fn run_workflow_writes_from_rar_archive() -> Result<(), Box<dyn std::error::Erro
     Ok(())
 }
 
+#[test]
+fn run_workflow_writes_from_tar_gz_source() -> Result<(), Box<dyn std::error::Error>> {
+    let database_dir = tempfile::tempdir()?;
+    let database = test_database(database_dir.path())?;
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let dat_path = write_shared_dat(
+        work_dir.path(),
+        "set-tar-gz.dat",
+        "Set Tar Gz",
+        "a9993e364706816aba3e25717850c26c9cd0d89d",
+    )?;
+    let mut tar = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(3);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, "shared.rom", b"abc" as &[u8])?;
+    let mut gzip = flate2::write::GzEncoder::new(
+        fs::File::create(source_dir.path().join("pack.tar.gz"))?,
+        flate2::Compression::default(),
+    );
+    gzip.write_all(&tar.into_inner()?)?;
+    gzip.finish()?;
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+
+    let report = app::run(
+        &database,
+        &RunWorkflowRequest {
+            dat_path,
+            source_path: utf8_path(source_dir.path())?.to_path_buf(),
+            destination_path: output_path.clone(),
+            mode: Some(BuildMode::ParentBundles),
+            jobs: 1,
+            output: Some(OutputFormat::Zip),
+            ..Default::default()
+        },
+    )?;
+
+    assert_eq!(report.exit_code, 0);
+    assert_eq!(report.build_report.matched_roms, 1);
+    assert_eq!(
+        zip_entries(&output_path.join("shared.zip"))?
+            .get("shared.rom")
+            .map(Vec::as_slice),
+        Some(b"abc" as &[u8])
+    );
+    Ok(())
+}
+
+#[test]
+fn run_workflow_writes_from_zip_nested_in_zip() -> Result<(), Box<dyn std::error::Error>> {
+    let database_dir = tempfile::tempdir()?;
+    let database = test_database(database_dir.path())?;
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let dat_path = write_shared_dat(
+        work_dir.path(),
+        "set-nested.dat",
+        "Set Nested",
+        "a9993e364706816aba3e25717850c26c9cd0d89d",
+    )?;
+    let mut inner = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+    inner.start_file("shared.rom", zip::write::SimpleFileOptions::default())?;
+    inner.write_all(b"abc")?;
+    let inner = inner.finish()?.into_inner();
+    let mut outer = zip::ZipWriter::new(fs::File::create(source_dir.path().join("pack.zip"))?);
+    outer.start_file("inner.zip", zip::write::SimpleFileOptions::default())?;
+    outer.write_all(&inner)?;
+    outer.finish()?;
+    let source_path = utf8_path(source_dir.path())?.to_path_buf();
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+    let request = RunWorkflowRequest {
+        dat_path,
+        source_path,
+        destination_path: output_path.clone(),
+        mode: Some(BuildMode::ParentBundles),
+        jobs: 1,
+        output: Some(OutputFormat::Zip),
+        ..Default::default()
+    };
+
+    let opaque_report = app::run(&database, &request)?;
+    assert_eq!(opaque_report.build_report.matched_roms, 0);
+
+    let report = app::run(
+        &database,
+        &RunWorkflowRequest {
+            nested_depth: 1,
+            ..request
+        },
+    )?;
+
+    assert_eq!(report.exit_code, 0);
+    assert_eq!(report.build_report.matched_roms, 1);
+    assert_eq!(
+        zip_entries(&output_path.join("shared.zip"))?
+            .get("shared.rom")
+            .map(Vec::as_slice),
+        Some(b"abc" as &[u8])
+    );
+    Ok(())
+}
+
+#[test]
+fn repeated_run_skips_unchanged_archive_and_keeps_identical_output()
+-> Result<(), Box<dyn std::error::Error>> {
+    let database_dir = tempfile::tempdir()?;
+    let database = test_database(database_dir.path())?;
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let dat_path = write_shared_dat(
+        work_dir.path(),
+        "set-repeat.dat",
+        "Set Repeat",
+        "a9993e364706816aba3e25717850c26c9cd0d89d",
+    )?;
+    let mut source = zip::ZipWriter::new(fs::File::create(source_dir.path().join("pack.zip"))?);
+    source.start_file("shared.rom", zip::write::SimpleFileOptions::default())?;
+    source.write_all(b"abc")?;
+    source.finish()?;
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+    let request = RunWorkflowRequest {
+        dat_path,
+        source_path: utf8_path(source_dir.path())?.to_path_buf(),
+        destination_path: output_path.clone(),
+        mode: Some(BuildMode::ParentBundles),
+        jobs: 1,
+        output: Some(OutputFormat::Zip),
+        strict: true,
+        ..Default::default()
+    };
+
+    let first_report = app::run(&database, &request)?;
+    let first_bytes = fs::read(output_path.join("shared.zip"))?;
+    let second_report = app::run(&database, &request)?;
+
+    assert!(first_report.unchanged_paths.is_empty());
+    assert_eq!(second_report.build_report.matched_roms, 1);
+    assert_eq!(
+        second_report.unchanged_paths,
+        vec![output_path.join("shared.zip")]
+    );
+    assert_eq!(fs::read(output_path.join("shared.zip"))?, first_bytes);
+    Ok(())
+}
+
 #[test]
 #[ignore = "requires p7zip in the test environment"]
 fn p7zip_extracts_r7z_builder_archive() -> Result<(), Box<dyn std::error::Error>> {
@@ -346,6 +499,7 @@ fn importing_dat_links_previously_scanned_rom_files() -> Result<(), Box<dyn std:
         &SourceScanRequest {
             source_path: source_path.clone(),
             jobs: 1,
+            nested_depth: 0,
         },
     )?;
     app::import_dat(
@@ -361,10 +515,9 @@ fn importing_dat_links_previously_scanned_rom_files() -> Result<(), Box<dyn std:
             dat_path,
             source_path,
             destination_path: output_path,
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
-            strict: false,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
+            ..Default::default()
         },
     )?;
 
@@ -389,11 +542,10 @@ fn run_workflow_writes_parent_bundle_zip() -> Result<(), Box<dyn std::error::Err
             dat_path,
             source_path,
             destination_path: output_path.clone(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             jobs: 1,
-            dry_run: false,
-            strict: false,
+            ..Default::default()
         },
     )?;
 
@@ -416,6 +568,132 @@ fn run_workflow_writes_parent_bundle_zip() -> Result<(), Box<dyn std::error::Err
     Ok(())
 }
 
+#[test]
+fn run_workflow_writes_sample_sets_found_by_name() -> Result<(), Box<dyn std::error::Error>> {
+    let database_dir = tempfile::tempdir()?;
+    let database = test_database(database_dir.path())?;
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let dat_path = utf8_path(&work_dir.path().join("samples.dat"))?.to_path_buf();
+    fs::write(
+        &dat_path,
+        CLONE_DAT
+            .replacen(
+                "crc=\"aabbccdd\"/>",
+                "crc=\"aabbccdd\"/>\n    <sample name=\"bang\"/>\n    <sample name=\"shot\"/>",
+                1,
+            )
+            .replacen(
+                r#"<game name="clone1" cloneof="parent""#,
+                r#"<game name="clone1" cloneof="parent" sampleof="parent""#,
+                1,
+            ),
+    )?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let mut writer = zip::ZipWriter::new(fs::File::create(source_path.join("parent.zip"))?);
+    writer.start_file("bang.wav", zip::write::SimpleFileOptions::default())?;
+    writer.write_all(b"RIFF")?;
+    writer.finish()?;
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+    let samples_path = output_path.join("samples");
+
+    let report = app::run(
+        &database,
+        &RunWorkflowRequest {
+            dat_path,
+            source_path,
+            destination_path: output_path.join("roms"),
+            samples_path: Some(samples_path.clone()),
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
+            jobs: 1,
+            ..Default::default()
+        },
+    )?;
+
+    assert_eq!(report.exit_code, 0);
+    assert_eq!(report.build_report.matched_samples, 1);
+    assert_eq!(
+        report.build_report.missing_samples,
+        vec![MissingSample {
+            sample_set: "parent".to_owned(),
+            sample_name: "shot".to_owned(),
+        }]
+    );
+    assert_eq!(
+        report.written_paths,
+        vec![
+            output_path.join("roms").join("parent.zip"),
+            samples_path.join("parent.zip")
+        ]
+    );
+    assert_eq!(
+        zip_entries(&samples_path.join("parent.zip"))?,
+        BTreeMap::from([("bang.wav".to_owned(), b"RIFF".to_vec())])
+    );
+    Ok(())
+}
+
+#[test]
+fn one_game_one_rom_workflow_writes_only_the_chosen_games_samples()
+-> Result<(), Box<dyn std::error::Error>> {
+    let database_dir = tempfile::tempdir()?;
+    let database = test_database(database_dir.path())?;
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let dat_path = utf8_path(&work_dir.path().join("samples.dat"))?.to_path_buf();
+    fs::write(
+        &dat_path,
+        CLONE_DAT
+            .replacen(
+                "crc=\"aabbccdd\"/>",
+                "crc=\"aabbccdd\"/>\n    <sample name=\"bang\"/>",
+                1,
+            )
+            .replacen(
+                "crc=\"bbccddee\"/>",
+                "crc=\"bbccddee\"/>\n    <sample name=\"laser\"/>",
+                1,
+            )
+            .replacen(
+                r#"<game name="clone1" cloneof="parent""#,
+                r#"<game name="clone1" cloneof="parent" sampleof="parent""#,
+                1,
+            ),
+    )?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let mut writer = zip::ZipWriter::new(fs::File::create(source_path.join("parent.zip"))?);
+    writer.start_file("bang.WAV", zip::write::SimpleFileOptions::default())?;
+    writer.write_all(b"RIFF")?;
+    writer.finish()?;
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+    let samples_path = output_path.join("samples");
+
+    let report = app::run(
+        &database,
+        &RunWorkflowRequest {
+            dat_path,
+            source_path,
+            destination_path: output_path.join("roms"),
+            samples_path: Some(samples_path.clone()),
+            mode: Some(BuildMode::OneGameOneRom),
+            output: Some(OutputFormat::Zip),
+            jobs: 1,
+            ..Default::default()
+        },
+    )?;
+
+    assert_eq!(report.build_report.matched_samples, 1);
+    assert!(report.build_report.missing_samples.is_empty());
+    assert_eq!(
+        zip_entries(&samples_path.join("parent.zip"))?,
+        BTreeMap::from([("bang.wav".to_owned(), b"RIFF".to_vec())])
+    );
+    Ok(())
+}
+
 #[test]
 fn dry_run_workflow_reports_plan_without_writing_files() -> Result<(), Box<dyn std::error::Error>> {
     let database_dir = tempfile::tempdir()?;
@@ -433,11 +711,11 @@ fn dry_run_workflow_reports_plan_without_writing_files() -> Result<(), Box<dyn s
             dat_path,
             source_path,
             destination_path: output_path.clone(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             jobs: 1,
             dry_run: true,
-            strict: false,
+            ..Default::default()
         },
     )?;
 
@@ -467,6 +745,7 @@ fn build_workflow_accepts_imported_dat_name() -> Result<(), Box<dyn std::error::
         &SourceScanRequest {
             source_path: source_path.clone(),
             jobs: 1,
+            nested_depth: 0,
         },
     )?;
 
@@ -476,10 +755,9 @@ fn build_workflow_accepts_imported_dat_name() -> Result<(), Box<dyn std::error::
             dat_path: camino::Utf8PathBuf::from("Clone Test"),
             source_path,
             destination_path: output_path.clone(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
-            strict: false,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
+            ..Default::default()
         },
     )?;
 
@@ -526,6 +804,7 @@ fn build_matches_sources_scanned_from_noncanonical_path() -> Result<(), Box<dyn
         &SourceScanRequest {
             source_path: scanned_source_path,
             jobs: 1,
+            nested_depth: 0,
         },
     )?;
 
@@ -537,10 +816,9 @@ fn build_matches_sources_scanned_from_noncanonical_path() -> Result<(), Box<dyn
             dat_path,
             source_path,
             destination_path: output_path.clone(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
-            strict: false,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
+            ..Default::default()
         },
     )?;
 
@@ -591,6 +869,7 @@ fn source_scan_replaces_rows_for_source_root() -> Result<(), Box<dyn std::error:
         &SourceScanRequest {
             source_path: source_path.clone(),
             jobs: 1,
+            nested_depth: 0,
         },
     )?;
 
@@ -601,6 +880,7 @@ fn source_scan_replaces_rows_for_source_root() -> Result<(), Box<dyn std::error:
         &SourceScanRequest {
             source_path: source_path.clone(),
             jobs: 1,
+            nested_depth: 0,
         },
     )?;
 
@@ -610,10 +890,10 @@ fn source_scan_replaces_rows_for_source_root() -> Result<(), Box<dyn std::error:
             dat_path: dat_a,
             source_path: source_path.clone(),
             destination_path: utf8_path(output_dirs[0].path())?.to_path_buf(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             strict: true,
+            ..Default::default()
         },
     )?;
     let fresh_report = app::build(
@@ -622,10 +902,10 @@ fn source_scan_replaces_rows_for_source_root() -> Result<(), Box<dyn std::error:
             dat_path: dat_b,
             source_path,
             destination_path: utf8_path(output_dirs[1].path())?.to_path_buf(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             strict: true,
+            ..Default::default()
         },
     )?;
 
@@ -678,6 +958,7 @@ fn source_scan_does_not_delete_similarly_prefixed_root() -> Result<(), Box<dyn s
         &SourceScanRequest {
             source_path: source_path.clone(),
             jobs: 1,
+            nested_depth: 0,
         },
     )?;
     app::scan_source(
@@ -685,6 +966,7 @@ fn source_scan_does_not_delete_similarly_prefixed_root() -> Result<(), Box<dyn s
         &SourceScanRequest {
             source_path: prefixed_source_path,
             jobs: 1,
+            nested_depth: 0,
         },
     )?;
 
@@ -694,10 +976,10 @@ fn source_scan_does_not_delete_similarly_prefixed_root() -> Result<(), Box<dyn s
             dat_path: dat_a,
             source_path,
             destination_path: utf8_path(output_dir.path())?.to_path_buf(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             strict: true,
+            ..Default::default()
         },
     )?;
 
@@ -730,11 +1012,11 @@ fn one_shot_build_does_not_use_stale_removed_source_file() -> Result<(), Box<dyn
             dat_path: dat_path.clone(),
             source_path: source_path.clone(),
             destination_path: utf8_path(output_dirs[0].path())?.to_path_buf(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             jobs: 1,
-            dry_run: false,
             strict: true,
+            ..Default::default()
         },
     )?;
     fs::remove_file(source_dir.path().join("a.rom"))?;
@@ -744,11 +1026,11 @@ fn one_shot_build_does_not_use_stale_removed_source_file() -> Result<(), Box<dyn
             dat_path,
             source_path,
             destination_path: utf8_path(output_dirs[1].path())?.to_path_buf(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             jobs: 1,
-            dry_run: false,
             strict: true,
+            ..Default::default()
         },
     )?;
 
@@ -776,11 +1058,11 @@ fn strict_run_workflow_writes_nothing_when_roms_are_missing()
             dat_path,
             source_path,
             destination_path: output_path.clone(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             jobs: 1,
-            dry_run: false,
             strict: true,
+            ..Default::default()
         },
     )?;
 
@@ -837,6 +1119,7 @@ fn overlapping_dats_with_same_game_and_rom_names_build_independently()
         &SourceScanRequest {
             source_path: source_a.clone(),
             jobs: 1,
+            nested_depth: 0,
         },
     )?;
     app::scan_source(
@@ -844,6 +1127,7 @@ fn overlapping_dats_with_same_game_and_rom_names_build_independently()
         &SourceScanRequest {
             source_path: source_b.clone(),
             jobs: 1,
+            nested_depth: 0,
         },
     )?;
 
@@ -853,10 +1137,10 @@ fn overlapping_dats_with_same_game_and_rom_names_build_independently()
             dat_path: dat_a,
             source_path: source_a,
             destination_path: output_a.clone(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             strict: true,
+            ..Default::default()
         },
     )?;
     let report_b = app::build(
@@ -865,10 +1149,10 @@ fn overlapping_dats_with_same_game_and_rom_names_build_independently()
             dat_path: dat_b,
             source_path: source_b,
             destination_path: output_b.clone(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             strict: true,
+            ..Default::default()
         },
     )?;
 
@@ -935,6 +1219,7 @@ fn one_scanned_source_file_can_build_matching_roms_from_multiple_dats()
         &SourceScanRequest {
             source_path: source_path.clone(),
             jobs: 1,
+            nested_depth: 0,
         },
     )?;
 
@@ -944,10 +1229,10 @@ fn one_scanned_source_file_can_build_matching_roms_from_multiple_dats()
             dat_path: dat_a,
             source_path: source_path.clone(),
             destination_path: output_a.clone(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             strict: true,
+            ..Default::default()
         },
     )?;
     let report_b = app::build(
@@ -956,10 +1241,10 @@ fn one_scanned_source_file_can_build_matching_roms_from_multiple_dats()
             dat_path: dat_b,
             source_path,
             destination_path: output_b.clone(),
-            mode: BuildMode::ParentBundles,
-            compression: ZipCompression::Deflate,
-            dry_run: false,
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
             strict: true,
+            ..Default::default()
         },
     )?;
 
@@ -987,10 +1272,25 @@ fn cli_help_commands_render_successfully() {
     for args in [
         vec!["--help"],
         vec!["build", "--help"],
+        vec!["build-all", "--help"],
+        vec!["fix", "--help"],
+        vec!["verify", "--help"],
+        vec!["dat", "--help"],
+        vec!["dat", "create", "--help"],
+        vec!["dat", "diff", "--help"],
+        vec!["dat", "check", "--help"],
         vec!["cache", "--help"],
         vec!["cache", "import", "--help"],
         vec!["cache", "scan", "--help"],
         vec!["cache", "build", "--help"],
+        vec!["cache", "export-dat", "--help"],
+        vec!["cache", "list-dats", "--help"],
+        vec!["cache", "list-sources", "--help"],
+        vec!["cache", "stats", "--help"],
+        vec!["cache", "remove-dat", "--help"],
+        vec!["cache", "remove-source", "--help"],
+        vec!["cache", "gc", "--help"],
+        vec!["cache", "find", "--help"],
     ] {
         cargo_command()
             .args(args)
@@ -1060,6 +1360,28 @@ fn cli_build_missing_fail_exits_two_and_writes_no_files() -> Result<(), Box<dyn
     Ok(())
 }
 
+#[test]
+fn cli_prefer_path_glob_requires_a_glob() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let database_path = root.join("cli.db");
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args([
+            "build",
+            "missing.dat",
+            root.as_str(),
+            root.join("out").as_str(),
+            "--prefer",
+            "path-glob",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("--prefer-glob"));
+    Ok(())
+}
+
 #[test]
 fn cli_cache_build_accepts_dat_header_name_after_import() -> Result<(), Box<dyn std::error::Error>>
 {
@@ -1098,6 +1420,107 @@ fn cli_cache_build_accepts_dat_header_name_after_import() -> Result<(), Box<dyn
     Ok(())
 }
 
+#[test]
+fn cli_one_game_one_rom_layout_keeps_the_preferred_release()
+-> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = root.join("no-intro.dat");
+    fs::write(
+        &dat_path,
+        CLONE_DAT
+            .replace(r#""parent""#, r#""Game (Japan)""#)
+            .replace(r#""clone1""#, r#""Game (USA) (Beta)""#)
+            .replace(r#""clone2""#, r#""Game (Europe)""#),
+    )?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let database_path = root.join("cli.db");
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["build", dat_path.as_str(), source_path.as_str()])
+        .args([output_path.as_str(), "--jobs", "1", "--layout", "1g1r"])
+        .args(["--region", "USA,Europe"])
+        .assert()
+        .success()
+        .stdout(contains(
+            "1G1R Game (Japan): kept Game (Europe): region Europe is priority 2",
+        ))
+        .stdout(contains(
+            "1G1R Game (Japan): skipped Game (USA) (Beta): (Beta) releases are excluded",
+        ));
+
+    assert_eq!(
+        fs::read_dir(&output_path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?,
+        ["Game (Europe).zip"]
+    );
+    assert_eq!(
+        zip_entries(&output_path.join("Game (Europe).zip"))?
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>(),
+        ["clone2.rom"]
+    );
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["verify", dat_path.as_str(), output_path.as_str()])
+        .args(["--layout", "1g1r", "--region", "USA,Europe"])
+        .assert()
+        .success();
+    Ok(())
+}
+
+#[test]
+fn cli_game_filters_restrict_what_is_built() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let database_path = root.join("cli.db");
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+    let games_path = root.join("games.txt");
+    fs::write(&games_path, "# wanted\nclone1\n\nclone2\n")?;
+    let filters = ["--games-from", games_path.as_str(), "--year", "1982-"];
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["build", dat_path.as_str(), source_path.as_str()])
+        .args([output_path.as_str(), "--jobs", "1", "--layout", "per-game"])
+        .args(filters)
+        .assert()
+        .success()
+        .stdout(contains("game filters kept 1 of 3 games"));
+
+    assert_eq!(
+        fs::read_dir(&output_path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?,
+        ["clone2.zip"]
+    );
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["verify", dat_path.as_str(), output_path.as_str()])
+        .args(["--layout", "per-game"])
+        .args(filters)
+        .assert()
+        .success();
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["build", dat_path.as_str(), source_path.as_str()])
+        .args([output_path.as_str(), "--name-regex", "clone("])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid regular expression"));
+    Ok(())
+}
+
 #[test]
 fn cli_per_game_layout_writes_separate_zip_files() -> Result<(), Box<dyn std::error::Error>> {
     let work_dir = tempfile::tempdir()?;
@@ -1130,62 +1553,1254 @@ fn cli_per_game_layout_writes_separate_zip_files() -> Result<(), Box<dyn std::er
 }
 
 #[test]
-fn cli_store_compression_writes_stored_zip_entries() -> Result<(), Box<dyn std::error::Error>> {
+fn cli_build_profile_supplies_paths_and_policies_under_cli_flags()
+-> Result<(), Box<dyn std::error::Error>> {
     let work_dir = tempfile::tempdir()?;
     let source_dir = tempfile::tempdir()?;
     let output_dir = tempfile::tempdir()?;
     let root = utf8_path(work_dir.path())?;
     let dat_path = write_clone_dat(work_dir.path())?;
     let source_path = write_present_clone_roms(source_dir.path())?;
-    let database_path = root.join("cli.db");
+    let database_path = root.join("profile.db");
     let output_path = utf8_path(output_dir.path())?.to_path_buf();
+    let config_path = root.join("config.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "[profiles.arcade]\n\
+             dat = '{dat_path}'\n\
+             source = '{source_path}'\n\
+             out = '{output_path}'\n\
+             cache = '{database_path}'\n\
+             jobs = 1\n\
+             layout = 'per-game'\n\
+             compression = 'store'\n"
+        ),
+    )?;
 
     cargo_command()
-        .args(db_arg(&database_path))
-        .args([
-            "build",
-            dat_path.as_str(),
-            source_path.as_str(),
-            output_path.as_str(),
-            "--jobs",
-            "1",
-            "--compression",
-            "store",
-        ])
+        .args(["--config", config_path.as_str()])
+        .args(["build", "--profile", "arcade", "--compression", "deflate"])
         .assert()
         .success();
 
+    assert!(database_path.exists());
+    assert!(output_path.join("clone2.zip").exists());
     let file = fs::File::open(output_path.join("parent.zip"))?;
     let mut archive = zip::ZipArchive::new(file)?;
-    let entry = archive.by_name("parent.rom")?;
+    assert_eq!(
+        archive.by_name("parent.rom")?.compression(),
+        zip::CompressionMethod::Deflated
+    );
 
-    assert_eq!(entry.compression(), zip::CompressionMethod::Stored);
+    cargo_command()
+        .args(["--config", config_path.as_str()])
+        .args(["build", "--profile", "console"])
+        .assert()
+        .failure()
+        .stderr(contains("no profile named console"));
     Ok(())
 }
 
 #[test]
-fn cli_invalid_dat_path_exits_one() -> Result<(), Box<dyn std::error::Error>> {
+fn cli_directory_output_writes_loose_rom_folders() -> Result<(), Box<dyn std::error::Error>> {
     let work_dir = tempfile::tempdir()?;
     let source_dir = tempfile::tempdir()?;
     let output_dir = tempfile::tempdir()?;
     let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
     let database_path = root.join("cli.db");
-    let missing_dat = root.join("missing.dat");
-    let source_path = utf8_path(source_dir.path())?;
-    let output_path = utf8_path(output_dir.path())?;
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+
+    for _ in 0..2 {
+        cargo_command()
+            .args(db_arg(&database_path))
+            .args([
+                "build",
+                dat_path.as_str(),
+                source_path.as_str(),
+                output_path.as_str(),
+                "--jobs",
+                "1",
+                "--output",
+                "directory",
+                "--link",
+                "hardlink",
+            ])
+            .assert()
+            .success();
+    }
+
+    assert_eq!(fs::read(output_path.join("parent/parent.rom"))?, b"abc");
+    assert!(output_path.join("parent/clone2.rom").exists());
+    assert!(!output_path.join("parent.zip").exists());
+    Ok(())
+}
+
+#[test]
+fn cli_seven_zip_output_round_trips_through_a_rescan() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let archive_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let database_path = root.join("cli.db");
+    let archive_path = utf8_path(archive_dir.path())?.to_path_buf();
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
 
     cargo_command()
         .args(db_arg(&database_path))
         .args([
             "build",
-            missing_dat.as_str(),
+            dat_path.as_str(),
             source_path.as_str(),
-            output_path.as_str(),
-            "--jobs",
-            "1",
+            archive_path.as_str(),
+            "--output",
+            "7z",
+            "--solid",
+            "false",
         ])
         .assert()
-        .failure()
-        .code(1);
+        .success();
+    assert!(archive_path.join("parent.7z").exists());
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args([
+            "build",
+            dat_path.as_str(),
+            archive_path.as_str(),
+            output_path.as_str(),
+        ])
+        .assert()
+        .success();
+
+    let entries = zip_entries(&output_path.join("parent.zip"))?;
+    assert_eq!(
+        entries.get("parent.rom").map(Vec::as_slice),
+        Some(&b"abc"[..])
+    );
+    Ok(())
+}
+
+#[test]
+fn cli_store_compression_writes_stored_zip_entries() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let database_path = root.join("cli.db");
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args([
+            "build",
+            dat_path.as_str(),
+            source_path.as_str(),
+            output_path.as_str(),
+            "--jobs",
+            "1",
+            "--compression",
+            "store",
+        ])
+        .assert()
+        .success();
+
+    let file = fs::File::open(output_path.join("parent.zip"))?;
+    let mut archive = zip::ZipArchive::new(file)?;
+    let entry = archive.by_name("parent.rom")?;
+
+    assert_eq!(entry.compression(), zip::CompressionMethod::Stored);
+    Ok(())
+}
+
+#[test]
+fn cli_compression_level_is_checked_against_the_method() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let database_path = root.join("cli.db");
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+    let build = |compression: &str, level: &str| {
+        let mut command = cargo_command();
+        command.args(db_arg(&database_path)).args([
+            "build",
+            dat_path.as_str(),
+            source_path.as_str(),
+            output_path.as_str(),
+            "--compression",
+            compression,
+            "--compression-level",
+            level,
+        ]);
+        command
+    };
+
+    build("store", "3")
+        .assert()
+        .failure()
+        .stderr(contains("does not take a level"));
+    build("zstd", "30")
+        .assert()
+        .failure()
+        .stderr(contains("zstd level must be between -7 and 22"));
+    build("zstd", "-3").assert().success();
+
+    let file = fs::File::open(output_path.join("parent.zip"))?;
+    let mut archive = zip::ZipArchive::new(file)?;
+    assert_eq!(
+        archive.by_name("parent.rom")?.compression(),
+        zip::CompressionMethod::Zstd
+    );
+    Ok(())
+}
+
+#[test]
+fn cli_fix_rewrites_wrong_zips_and_moves_junk_to_backup() -> Result<(), Box<dyn std::error::Error>>
+{
+    let work_dir = tempfile::tempdir()?;
+    let collection_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let database_path = root.join("cli.db");
+    let backup_path = root.join("backup");
+    let collection_path = utf8_path(collection_dir.path())?.to_path_buf();
+    let source_path = utf8_path(source_dir.path())?.to_path_buf();
+    let mut writer = zip::ZipWriter::new(fs::File::create(collection_path.join("parent.zip"))?);
+    writer.start_file("wrong.rom", zip::write::SimpleFileOptions::default())?;
+    writer.write_all(b"abc")?;
+    writer.finish()?;
+    fs::write(collection_path.join("junk.txt"), b"junk")?;
+    fs::write(source_path.join("clone2.rom"), b"")?;
+    let fix = |dry_run: bool| {
+        let mut command = cargo_command();
+        command.args(db_arg(&database_path)).args([
+            "fix",
+            dat_path.as_str(),
+            collection_path.as_str(),
+            "--source",
+            source_path.as_str(),
+            "--backup",
+            backup_path.as_str(),
+            "--jobs",
+            "1",
+        ]);
+        if dry_run {
+            command.arg("--dry-run");
+        }
+        command
+    };
+
+    fix(true).assert().success();
+    assert!(collection_path.join("junk.txt").exists());
+    assert!(zip_entries(&collection_path.join("parent.zip"))?.contains_key("wrong.rom"));
+    assert!(!backup_path.exists());
+
+    fix(false).assert().success();
+    let entries = zip_entries(&collection_path.join("parent.zip"))?;
+    assert_eq!(
+        entries.keys().map(String::as_str).collect::<Vec<_>>(),
+        ["clone2.rom", "parent.rom"]
+    );
+    assert_eq!(
+        entries.get("parent.rom").map(Vec::as_slice),
+        Some(&b"abc"[..])
+    );
+    assert!(!collection_path.join("junk.txt").exists());
+    let backups = fs::read_dir(&backup_path)?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(backups.len(), 1);
+    let backup = backups
+        .first()
+        .ok_or_else(|| io::Error::other("no backup directory"))?
+        .path();
+    assert_eq!(fs::read(backup.join("junk.txt"))?, b"junk");
+    assert!(zip_entries(utf8_path(&backup.join("parent.zip"))?)?.contains_key("wrong.rom"));
+
+    let fixed = fs::metadata(collection_path.join("parent.zip"))?.modified()?;
+    fix(false).assert().success();
+    assert_eq!(
+        fs::metadata(collection_path.join("parent.zip"))?.modified()?,
+        fixed
+    );
+    assert_eq!(fs::read_dir(&backup_path)?.count(), 1);
+    Ok(())
+}
+
+#[test]
+fn cli_fix_keeps_zips_of_games_the_filters_leave_out() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let collection_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let database_path = root.join("cli.db");
+    let backup_path = root.join("backup");
+    let collection_path = utf8_path(collection_dir.path())?.to_path_buf();
+    let source_path = utf8_path(source_dir.path())?.to_path_buf();
+    let mut writer = zip::ZipWriter::new(fs::File::create(collection_path.join("clone2.zip"))?);
+    writer.start_file("clone2.rom", zip::write::SimpleFileOptions::default())?;
+    writer.finish()?;
+    fs::write(collection_path.join("junk.txt"), b"junk")?;
+    fs::write(source_path.join("parent.rom"), b"abc")?;
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args([
+            "fix",
+            dat_path.as_str(),
+            collection_path.as_str(),
+            "--source",
+            source_path.as_str(),
+            "--backup",
+            backup_path.as_str(),
+            "--layout",
+            "per-game",
+            "--exclude",
+            "clones",
+            "--jobs",
+            "1",
+        ])
+        .assert()
+        .success();
+
+    assert_eq!(
+        zip_entries(&collection_path.join("parent.zip"))?,
+        BTreeMap::from([("parent.rom".to_owned(), b"abc".to_vec())])
+    );
+    assert!(zip_entries(&collection_path.join("clone2.zip"))?.contains_key("clone2.rom"));
+    assert!(!collection_path.join("junk.txt").exists());
+    let backups = fs::read_dir(&backup_path)?.collect::<Result<Vec<_>, _>>()?;
+    let [backup] = backups.as_slice() else {
+        return Err("expected one backup directory".into());
+    };
+    assert_eq!(
+        fs::read_dir(backup.path())?
+            .map(|entry| Ok(entry?.file_name()))
+            .collect::<io::Result<Vec<_>>>()?,
+        ["junk.txt"]
+    );
+    Ok(())
+}
+
+#[test]
+fn cli_after_build_move_relocates_only_fully_consumed_sources()
+-> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let source_path = utf8_path(source_dir.path())?.to_path_buf();
+    fs::write(source_path.join("clone2.rom"), b"")?;
+    let database_path = root.join("cli.db");
+    let moved_path = root.join("consumed");
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+    let mut writer = zip::ZipWriter::new(fs::File::create(source_path.join("partial.zip"))?);
+    writer.start_file("parent.rom", zip::write::SimpleFileOptions::default())?;
+    writer.write_all(b"abc")?;
+    writer.start_file("readme.txt", zip::write::SimpleFileOptions::default())?;
+    writer.write_all(b"not a rom")?;
+    writer.finish()?;
+    let build = |extra: &[&str]| {
+        let mut command = cargo_command();
+        command
+            .args(db_arg(&database_path))
+            .args([
+                "build",
+                dat_path.as_str(),
+                source_path.as_str(),
+                output_path.as_str(),
+                "--jobs",
+                "1",
+                "--after-build",
+                "move",
+            ])
+            .args(extra);
+        command
+    };
+
+    build(&[])
+        .assert()
+        .failure()
+        .stderr(contains("--move-to <dir>"));
+    build(&["--move-to", moved_path.as_str(), "--dry-run"])
+        .assert()
+        .success();
+    assert!(source_path.join("clone2.rom").exists());
+    assert!(!moved_path.exists());
+
+    build(&["--move-to", moved_path.as_str()])
+        .assert()
+        .success();
+    assert_eq!(
+        zip_entries(&output_path.join("parent.zip"))?
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>(),
+        ["clone2.rom", "parent.rom"]
+    );
+    assert!(moved_path.join("clone2.rom").exists());
+    assert!(!source_path.join("clone2.rom").exists());
+    assert!(source_path.join("partial.zip").exists());
+    assert!(!moved_path.join("partial.zip").exists());
+    Ok(())
+}
+
+#[test]
+fn cli_verify_names_the_corrupted_entry() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let database_path = root.join("cli.db");
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+    let verify = || {
+        let mut command = cargo_command();
+        command.args(db_arg(&database_path)).args([
+            "verify",
+            dat_path.as_str(),
+            output_path.as_str(),
+        ]);
+        command
+    };
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args([
+            "build",
+            dat_path.as_str(),
+            source_path.as_str(),
+            output_path.as_str(),
+            "--verify",
+        ])
+        .assert()
+        .success();
+    verify().assert().failure().code(2).stdout(contains(format!(
+        "{}:clone1.rom is missing",
+        output_path.join("parent.zip")
+    )));
+
+    let mut writer = zip::ZipWriter::new(fs::File::create(output_path.join("parent.zip"))?);
+    writer.start_file("parent.rom", zip::write::SimpleFileOptions::default())?;
+    writer.write_all(b"abd")?;
+    writer.start_file("clone2.rom", zip::write::SimpleFileOptions::default())?;
+    writer.finish()?;
+    verify()
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(contains(format!(
+            "{}:clone1.rom is missing",
+            output_path.join("parent.zip")
+        )))
+        .stderr(contains(format!(
+            "{}:parent.rom has SHA1",
+            output_path.join("parent.zip")
+        )));
+
+    fs::remove_file(output_path.join("parent.zip"))?;
+    verify().assert().failure().code(2).stdout(contains(format!(
+        "{} is missing",
+        output_path.join("parent.zip")
+    )));
+    Ok(())
+}
+
+#[test]
+fn cli_dat_create_reports_same_named_games_from_different_directories()
+-> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = root.join("created.dat");
+    let source_path = utf8_path(source_dir.path())?;
+    for (dir, rom) in [("a", "first.rom"), ("b", "second.rom")] {
+        fs::create_dir(source_path.join(dir))?;
+        let mut writer =
+            zip::ZipWriter::new(fs::File::create(source_path.join(dir).join("game.zip"))?);
+        writer.start_file(rom, zip::write::SimpleFileOptions::default())?;
+        writer.write_all(rom.as_bytes())?;
+        writer.finish()?;
+    }
+
+    cargo_command()
+        .args(db_arg(&root.join("cli.db")))
+        .args(["dat", "create", source_path.as_str(), dat_path.as_str()])
+        .assert()
+        .success()
+        .stdout(contains("game game already comes from"));
+
+    let data_file = DataFile::from_path(&dat_path)?;
+    assert_eq!(
+        data_file
+            .games()
+            .iter()
+            .map(|game| (game.name(), game.roms().len()))
+            .collect::<Vec<_>>(),
+        [("game", 1)]
+    );
+    Ok(())
+}
+
+#[test]
+fn cli_dat_create_writes_a_dat_that_builds_the_source() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let database_path = root.join("cli.db");
+    let dat_path = root.join("homebrew.dat");
+    let source_path = utf8_path(source_dir.path())?.join("homebrew");
+    let output_path = utf8_path(output_dir.path())?;
+    fs::create_dir_all(source_path.join("demos"))?;
+    fs::write(source_path.join("loose.bin"), b"abc")?;
+    fs::write(source_path.join("demos").join("intro.bin"), b"intro")?;
+    let mut writer = zip::ZipWriter::new(fs::File::create(source_path.join("pong.zip"))?);
+    writer.start_file("pong.rom", zip::write::SimpleFileOptions::default())?;
+    writer.write_all(b"pong")?;
+    writer.finish()?;
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args([
+            "dat",
+            "create",
+            source_path.as_str(),
+            dat_path.as_str(),
+            "--author",
+            "Tester",
+            "--version",
+            "1.0",
+        ])
+        .assert()
+        .success();
+
+    let data_file = DataFile::from_path(&dat_path)?;
+    assert_eq!(data_file.header().name(), "homebrew");
+    assert_eq!(
+        data_file.header().version().map(String::as_str),
+        Some("1.0")
+    );
+    assert_eq!(
+        data_file.header().author().map(String::as_str),
+        Some("Tester")
+    );
+    let games = data_file
+        .games()
+        .iter()
+        .map(|game| {
+            (
+                game.name(),
+                game.roms()
+                    .iter()
+                    .map(|rom| (rom.name(), rom.size(), hex::encode(rom.crc())))
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        games,
+        vec![
+            (
+                "demos",
+                vec![(
+                    "intro.bin",
+                    5,
+                    hex::encode(crc32fast::hash(b"intro").to_be_bytes())
+                )]
+            ),
+            ("homebrew", vec![("loose.bin", 3, "352441c2".to_owned())]),
+            (
+                "pong",
+                vec![(
+                    "pong.rom",
+                    4,
+                    hex::encode(crc32fast::hash(b"pong").to_be_bytes())
+                )]
+            ),
+        ]
+    );
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args([
+            "build",
+            dat_path.as_str(),
+            source_path.as_str(),
+            output_path.as_str(),
+            "--missing",
+            "fail",
+        ])
+        .assert()
+        .success();
+    for bundle in ["demos.zip", "homebrew.zip", "pong.zip"] {
+        assert!(output_path.join(bundle).is_file(), "{bundle} was not built");
+    }
+    Ok(())
+}
+
+#[test]
+fn cli_invalid_dat_path_exits_one() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let database_path = root.join("cli.db");
+    let missing_dat = root.join("missing.dat");
+    let source_path = utf8_path(source_dir.path())?;
+    let output_path = utf8_path(output_dir.path())?;
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args([
+            "build",
+            missing_dat.as_str(),
+            source_path.as_str(),
+            output_path.as_str(),
+            "--jobs",
+            "1",
+        ])
+        .assert()
+        .failure()
+        .code(1);
+    Ok(())
+}
+
+#[test]
+fn cli_build_defaults_to_the_dat_clrmamepro_directives() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = root.join("directives.dat");
+    fs::write(
+        &dat_path,
+        CLONE_DAT.replace(
+            "</header>",
+            r#"<clrmamepro forcemerging="split" forcepacking="unzip"/></header>"#,
+        ),
+    )?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let database_path = root.join("cli.db");
+    let build = |out: &camino::Utf8Path, overrides: &[&str]| {
+        cargo_command()
+            .args(db_arg(&database_path))
+            .args([
+                "build",
+                dat_path.as_str(),
+                source_path.as_str(),
+                out.as_str(),
+            ])
+            .args(["--jobs", "1"])
+            .args(overrides)
+            .assert()
+            .success();
+    };
+
+    let directives_out = root.join("directives");
+    build(&directives_out, &[]);
+    assert!(directives_out.join("parent").join("parent.rom").is_file());
+    assert!(directives_out.join("clone2").join("clone2.rom").is_file());
+
+    let overridden_out = root.join("overridden");
+    build(
+        &overridden_out,
+        &["--layout", "parent-bundles", "--output", "zip"],
+    );
+    assert!(overridden_out.join("parent.zip").is_file());
+    assert!(!overridden_out.join("clone2.zip").exists());
+
+    let exported = cargo_command()
+        .args(db_arg(&database_path))
+        .args(["cache", "export-dat", "Clone Test"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert!(
+        String::from_utf8(exported)?
+            .contains(r#"<clrmamepro forcemerging="split" forcepacking="unzip"/>"#)
+    );
+    Ok(())
+}
+
+#[test]
+fn cli_fix_rejects_a_dat_that_asks_for_unzipped_sets() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let collection_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = root.join("unzip.dat");
+    fs::write(
+        &dat_path,
+        CLONE_DAT.replace(
+            "</header>",
+            r#"<clrmamepro forcepacking="unzip"/></header>"#,
+        ),
+    )?;
+    let collection_path = utf8_path(collection_dir.path())?.to_path_buf();
+    fs::write(collection_path.join("parent.rom"), b"abc")?;
+    let fix = |extra: &[&str]| {
+        let mut command = cargo_command();
+        command
+            .args(db_arg(&root.join("cli.db")))
+            .args(["fix", dat_path.as_str(), collection_path.as_str()])
+            .args(["--backup", root.join("backup").as_str(), "--jobs", "1"])
+            .args(extra);
+        command
+    };
+
+    fix(&[])
+        .assert()
+        .failure()
+        .stderr(contains("fix only rewrites ZIP collections"));
+    assert!(!collection_path.join("parent.zip").exists());
+    fix(&["--output", "zip"]).assert().success();
+    assert!(zip_entries(&collection_path.join("parent.zip"))?.contains_key("parent.rom"));
+    Ok(())
+}
+
+#[test]
+fn cli_split_dat_leaves_merged_roms_out_of_clone_zips() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = root.join("split.dat");
+    let merged_parent_rom = r#"<rom name="parent.rom" merge="parent.rom" size="3" sha1="a9993e364706816aba3e25717850c26c9cd0d89d" md5="900150983cd24fb0d6963f7d28e17f72" crc="352441c2"/>"#;
+    fs::write(
+        &dat_path,
+        CLONE_DAT
+            .replace(
+                "</header>",
+                r#"<clrmamepro forcemerging="split"/></header>"#,
+            )
+            .replace(
+                "<rom name=\"clone1.rom\"",
+                &format!("{merged_parent_rom}\n    <rom name=\"clone1.rom\""),
+            )
+            .replace(
+                "<rom name=\"clone2.rom\"",
+                &format!("{merged_parent_rom}\n    <rom name=\"clone2.rom\""),
+            )
+            .replace(
+                "84983e441c3bd26ebaae4aa1f575527d004816f2",
+                "6c2ebc662c65aace41c11ab3e99b261a473f0816",
+            ),
+    )?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    fs::write(source_path.join("clone1.rom"), b"clone1")?;
+    let output_path = root.join("out");
+
+    cargo_command()
+        .args(db_arg(&root.join("cli.db")))
+        .args(["build", dat_path.as_str(), source_path.as_str()])
+        .args([output_path.as_str(), "--jobs", "1"])
+        .assert()
+        .success();
+
+    let entry_names = |zip_name: &str| {
+        zip_entries(&output_path.join(zip_name))
+            .map(|entries| entries.into_keys().collect::<Vec<_>>())
+    };
+    assert_eq!(entry_names("parent.zip")?, ["parent.rom"]);
+    assert_eq!(entry_names("clone1.zip")?, ["clone1.rom"]);
+    assert_eq!(entry_names("clone2.zip")?, ["clone2.rom"]);
+    Ok(())
+}
+
+const SOFTWARE_LIST: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE softwarelist SYSTEM "softwarelist.dtd">
+<softwarelist name="testlist" description="Test software list">
+  <software name="parent">
+    <description>Parent Software</description>
+    <part name="cart" interface="test_cart">
+      <dataarea name="rom" size="3">
+        <rom name="parent.rom" size="3" crc="352441c2" sha1="a9993e364706816aba3e25717850c26c9cd0d89d" offset="0"/>
+      </dataarea>
+    </part>
+  </software>
+  <software name="clone2" cloneof="parent">
+    <description>Clone Software</description>
+    <part name="cart" interface="test_cart">
+      <dataarea name="rom" size="0">
+        <rom name="clone2.rom" size="0" crc="00000000" sha1="da39a3ee5e6b4b0d3255bfef95601890afd80709" offset="0"/>
+      </dataarea>
+    </part>
+  </software>
+</softwarelist>"#;
+
+#[test]
+fn cli_builds_software_lists_into_a_directory_named_after_the_list()
+-> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dats_dir = root.join("hash");
+    fs::create_dir(&dats_dir)?;
+    let list_path = dats_dir.join("testlist.xml");
+    fs::write(&list_path, SOFTWARE_LIST)?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let database_path = root.join("cli.db");
+
+    let build_out = root.join("roms");
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["build", list_path.as_str(), source_path.as_str()])
+        .args([build_out.as_str(), "--layout", "per-game", "--jobs", "1"])
+        .assert()
+        .success();
+    assert!(build_out.join("testlist").join("parent.zip").is_file());
+    assert!(build_out.join("testlist").join("clone2.zip").is_file());
+
+    let build_all_out = root.join("all");
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["build-all", source_path.as_str(), build_all_out.as_str()])
+        .args(["--dats", dats_dir.as_str(), "--jobs", "1"])
+        .assert()
+        .success();
+    assert!(build_all_out.join("testlist").join("parent.zip").is_file());
+    assert!(!build_all_out.join("testlist").join("testlist").exists());
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["verify", "testlist", build_all_out.as_str()])
+        .assert()
+        .success();
+
+    let backup_path = root.join("backup");
+    fs::write(build_out.join("testlist").join("parent.zip"), b"not a zip")?;
+    let fix = |extra: &[&str]| {
+        let mut command = cargo_command();
+        command
+            .args(db_arg(&database_path))
+            .args(["fix", list_path.as_str(), build_out.as_str()])
+            .args(["--source", source_path.as_str(), "--layout", "per-game"])
+            .args(["--backup", backup_path.as_str(), "--jobs", "1"])
+            .args(extra);
+        command
+    };
+    fix(&["--output", "directory"])
+        .assert()
+        .failure()
+        .stderr(contains("fix only rewrites ZIP collections"));
+    fix(&[]).assert().success();
+    assert!(
+        zip_entries(&build_out.join("testlist").join("parent.zip"))?.contains_key("parent.rom")
+    );
+    let backups = fs::read_dir(&backup_path)?.collect::<Result<Vec<_>, _>>()?;
+    let backup = backups
+        .first()
+        .ok_or_else(|| io::Error::other("no backup directory"))?
+        .path();
+    assert_eq!(fs::read(backup.join("parent.zip"))?, b"not a zip");
+    assert_eq!(fs::read_dir(&backup)?.count(), 1);
+    Ok(())
+}
+
+#[test]
+fn cli_cache_export_dat_writes_the_imported_dat_back_out() -> Result<(), Box<dyn std::error::Error>>
+{
+    let work_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let database_path = root.join("cli.db");
+    let export_path = root.join("exported.dat");
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["cache", "import", dat_path.as_str()])
+        .assert()
+        .success();
+    let stdout = cargo_command()
+        .args(db_arg(&database_path))
+        .args(["cache", "export-dat", "Clone Test"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args([
+            "cache",
+            "export-dat",
+            "Clone Test",
+            "--out",
+            export_path.as_str(),
+        ])
+        .assert()
+        .success();
+
+    let exported = DataFile::from_reader(stdout.as_slice())?;
+    assert_eq!(fs::read(&export_path)?, stdout);
+    assert_eq!(exported.header().name(), "Clone Test");
+    assert_eq!(
+        exported.header().description().map(String::as_str),
+        Some("Test with parent/clone relationship")
+    );
+    assert_eq!(
+        exported
+            .games()
+            .iter()
+            .map(|game| (game.name(), game.description(), game.cloneof()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("parent", "Parent Game", None),
+            ("clone1", "Clone Game 1", Some("parent")),
+            ("clone2", "Clone Game 2", Some("parent")),
+        ]
+    );
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["cache", "export-dat", "Missing"])
+        .assert()
+        .failure()
+        .code(1);
+    Ok(())
+}
+
+#[test]
+fn cli_dat_diff_compares_a_cached_dat_with_a_file() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let database_path = root.join("cli.db");
+    let new_dat_path = root.join("new.dat");
+    fs::write(
+        &new_dat_path,
+        CLONE_DAT
+            .replace(r#"game name="clone1""#, r#"game name="clone1a""#)
+            .replace("aabbccdd", "aabbccde"),
+    )?;
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["cache", "import", dat_path.as_str()])
+        .assert()
+        .success();
+    let stdout = cargo_command()
+        .args(db_arg(&database_path))
+        .args([
+            "dat",
+            "diff",
+            "Clone Test",
+            new_dat_path.as_str(),
+            "--format",
+            "json",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let diff: serde_json::Value = serde_json::from_slice(&stdout)?;
+    assert_eq!(diff["added_games"], serde_json::json!([]));
+    assert_eq!(diff["removed_games"], serde_json::json!([]));
+    assert_eq!(
+        diff["renamed_games"],
+        serde_json::json!([{ "from": "clone1", "to": "clone1a" }])
+    );
+    assert_eq!(diff["rom_changes"][0]["game_name"], "parent");
+    assert_eq!(diff["rom_changes"][0]["old"]["crc"], "aabbccdd");
+    assert_eq!(diff["rom_changes"][0]["new"]["crc"], "aabbccde");
+    assert_eq!(diff["parent_changes"], serde_json::json!([]));
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["dat", "diff", dat_path.as_str(), new_dat_path.as_str()])
+        .assert()
+        .success()
+        .stdout(contains("  clone1 -> clone1a"));
+    Ok(())
+}
+
+#[test]
+fn cli_dat_check_fails_on_errors_and_passes_clean_dats() -> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let broken_dat_path = root.join("broken.dat");
+    fs::write(
+        &broken_dat_path,
+        CLONE_DAT.replace(
+            r#"cloneof="parent" sourcefile="clone.c">
+    <description>Clone Game 1"#,
+            r#"cloneof="nothere" sourcefile="clone.c">
+    <description>Clone Game 1"#,
+        ),
+    )?;
+
+    let database_path = root.join("unused.db");
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["dat", "check", dat_path.as_str()])
+        .assert()
+        .success()
+        .stdout(contains("0 errors, 0 warnings"));
+    assert!(!database_path.exists());
+    cargo_command()
+        .args(["dat", "check", broken_dat_path.as_str()])
+        .assert()
+        .code(1)
+        .stdout(contains(
+            "line 15: error: clone1: cloneof nothere is not a game in the DAT",
+        ))
+        .stdout(contains("1 errors, 0 warnings"));
+    Ok(())
+}
+
+#[test]
+fn cli_cache_inspection_commands_report_dats_sources_and_stats()
+-> Result<(), Box<dyn std::error::Error>> {
+    let work_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let root = utf8_path(work_dir.path())?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let database_path = root.join("cli.db");
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["cache", "import", dat_path.as_str()])
+        .assert()
+        .success();
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["cache", "scan", source_path.as_str(), "--jobs", "1"])
+        .assert()
+        .success();
+
+    let dats: serde_json::Value = serde_json::from_slice(
+        &cargo_command()
+            .args(db_arg(&database_path))
+            .args(["cache", "list-dats", "--format", "json"])
+            .output()?
+            .stdout,
+    )?;
+    assert_eq!(dats[0]["name"], "Clone Test");
+    assert_eq!(dats[0]["version"], "1.0");
+    assert_eq!(dats[0]["games"], 3);
+    assert_eq!(dats[0]["roms"], 3);
+    assert!(dats[0]["imported_at"].is_string());
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["cache", "list-sources"])
+        .assert()
+        .success()
+        .stdout(contains(format!(
+            "{}\t2\t0\t",
+            source_path.canonicalize_utf8()?
+        )));
+
+    let stats: serde_json::Value = serde_json::from_slice(
+        &cargo_command()
+            .args(db_arg(&database_path))
+            .args(["cache", "stats", "--format", "json"])
+            .output()?
+            .stdout,
+    )?;
+    assert_eq!(stats["rom_files"], 2);
+    assert_eq!(stats["matched_rom_files"], 2);
+    assert_eq!(stats["unmatched_rom_files"], 0);
+    assert_eq!(stats["dats"][0]["available_roms"], 2);
+
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["cache", "stats"])
+        .assert()
+        .success()
+        .stdout(contains("Clone Test: 2/3 ROMs available (66%)"));
+
+    let lookup: serde_json::Value = serde_json::from_slice(
+        &cargo_command()
+            .args(db_arg(&database_path))
+            .args(["cache", "find", "--crc", "aabbccdd", "--format", "json"])
+            .output()?
+            .stdout,
+    )?;
+    assert_eq!(lookup["expected"][0]["game_name"], "parent");
+    assert_eq!(
+        lookup["sources"][0]["location"],
+        source_path.canonicalize_utf8()?.join("parent.rom").as_str()
+    );
+    cargo_command()
+        .args(db_arg(&database_path))
+        .args(["cache", "find", "--sha1", "a9993e", "--name", "*.rom"])
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn cache_removal_commands_drop_dats_sources_and_missing_files()
+-> Result<(), Box<dyn std::error::Error>> {
+    let database_dir = tempfile::tempdir()?;
+    let database = test_database(database_dir.path())?;
+    let work_dir = tempfile::tempdir()?;
+    let kept_dir = tempfile::tempdir()?;
+    let removed_dir = tempfile::tempdir()?;
+    let dat_path = write_clone_dat(work_dir.path())?;
+    let kept_path = write_present_clone_roms(kept_dir.path())?;
+    let removed_path = write_present_clone_roms(removed_dir.path())?;
+    app::import_dat(&database, &DatImportRequest { dat_path })?;
+    for source_path in [&kept_path, &removed_path] {
+        app::scan_source(
+            &database,
+            &SourceScanRequest {
+                source_path: source_path.clone(),
+                jobs: 1,
+                nested_depth: 0,
+            },
+        )?;
+    }
+
+    let source_report = app::remove_source(
+        &database,
+        &SourceRemovalRequest {
+            source_path: removed_path,
+        },
+    )?;
+    assert_eq!(source_report.rom_files, 2);
+    assert_eq!(source_report.source_roots, 1);
+    assert_eq!(app::list_sources(&database)?.len(), 1);
+
+    fs::remove_file(kept_dir.path().join("clone2.rom"))?;
+    let gc_report = app::collect_garbage(&database)?;
+    assert_eq!(gc_report.rom_files, 1);
+    assert_eq!(gc_report.source_roots, 0);
+    assert_eq!(app::cache_stats(&database)?.rom_files, 1);
+
+    let dat_request = DatRemovalRequest {
+        dat_path: "Clone Test".into(),
+        dat_version: None,
+    };
+    let dat_report = app::remove_dat(&database, &dat_request)?;
+    assert_eq!((dat_report.games, dat_report.roms), (3, 3));
+    assert!(app::list_dats(&database)?.is_empty());
+    let stats = app::cache_stats(&database)?;
+    assert_eq!((stats.rom_files, stats.matched_rom_files), (1, 0));
+
+    let Err(error) = app::remove_dat(&database, &dat_request) else {
+        return Err("removing a missing DAT should fail".into());
+    };
+    assert!(error.to_string().contains("Clone Test"));
+    Ok(())
+}
+
+#[test]
+fn build_all_builds_each_dat_in_directory_into_its_own_folder()
+-> Result<(), Box<dyn std::error::Error>> {
+    let database_dir = tempfile::tempdir()?;
+    let database = test_database(database_dir.path())?;
+    let dat_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    write_clone_dat(dat_dir.path())?;
+    write_shared_dat(
+        dat_dir.path(),
+        "shared.dat",
+        "Set Shared",
+        "a9993e364706816aba3e25717850c26c9cd0d89d",
+    )?;
+    fs::write(dat_dir.path().join("notes.txt"), "not a DAT")?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+
+    let report = app::build_all(
+        &database,
+        &BuildAllRequest {
+            dat_dir: Some(utf8_path(dat_dir.path())?.to_path_buf()),
+            source_path,
+            destination_path: output_path.clone(),
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
+            jobs: 1,
+            strict: true,
+            ..Default::default()
+        },
+    )?;
+
+    assert_eq!(
+        report
+            .builds
+            .iter()
+            .map(|build| (build.dat_name.as_str(), build.exit_code))
+            .collect::<Vec<_>>(),
+        vec![("Clone Test", 2), ("Set Shared", 0)]
+    );
+    assert_eq!(report.exit_code, 2);
+    assert!(!output_path.join("Clone Test").exists());
+    assert_eq!(
+        zip_entries(&output_path.join("Set Shared").join("shared.zip"))?
+            .get("shared.rom")
+            .map(Vec::as_slice),
+        Some(b"abc" as &[u8])
+    );
+    Ok(())
+}
+
+#[test]
+fn build_all_reports_a_broken_dat_and_builds_the_rest() -> Result<(), Box<dyn std::error::Error>> {
+    let database_dir = tempfile::tempdir()?;
+    let database = test_database(database_dir.path())?;
+    let dat_dir = tempfile::tempdir()?;
+    let source_dir = tempfile::tempdir()?;
+    let output_dir = tempfile::tempdir()?;
+    fs::write(dat_dir.path().join("broken.dat"), "<datafile><game")?;
+    write_shared_dat(
+        dat_dir.path(),
+        "shared.dat",
+        "Set Shared",
+        "a9993e364706816aba3e25717850c26c9cd0d89d",
+    )?;
+    let source_path = write_present_clone_roms(source_dir.path())?;
+    let output_path = utf8_path(output_dir.path())?.to_path_buf();
+
+    let report = app::build_all(
+        &database,
+        &BuildAllRequest {
+            dat_dir: Some(utf8_path(dat_dir.path())?.to_path_buf()),
+            source_path,
+            destination_path: output_path.clone(),
+            mode: Some(BuildMode::ParentBundles),
+            output: Some(OutputFormat::Zip),
+            jobs: 1,
+            strict: true,
+            ..Default::default()
+        },
+    )?;
+
+    assert_eq!(
+        report
+            .builds
+            .iter()
+            .map(|build| (
+                build.dat_name.as_str(),
+                build.exit_code,
+                build.report.is_some(),
+                build.error.is_some()
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            ("broken.dat", 1, false, true),
+            ("Set Shared", 0, true, false)
+        ]
+    );
+    assert_eq!(report.exit_code, 1);
+    assert!(output_path.join("Set Shared").join("shared.zip").is_file());
     Ok(())
 }